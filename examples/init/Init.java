@@ -0,0 +1,36 @@
+package init;
+
+class Base {
+    static int order;
+    static int base;
+
+    static {
+        order = order + 1;
+        base = order;
+    }
+}
+
+class Derived extends Base {
+    static final int CONSTANT = 7;
+    static final long BIG = 1L << 40;
+    static int derived;
+    static int[] table;
+
+    static {
+        Base.order = Base.order + 1;
+        derived = Base.order;
+    }
+}
+
+// Missing.class isn't kept, so initializing Broken fails
+class Broken {
+    static int value;
+
+    static {
+        Missing.value = 1;
+    }
+}
+
+class Missing {
+    static int value;
+}
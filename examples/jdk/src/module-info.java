@@ -0,0 +1,3 @@
+module java.base {
+    exports java.lang;
+}
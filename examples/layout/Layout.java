@@ -0,0 +1,19 @@
+package layout;
+
+class Point {
+    static int created;
+
+    int x;
+    long y;
+    Object label;
+}
+
+class Point3 extends Point {
+    int[] z;
+    // Hides Point.x, but both are stored
+    String x;
+
+    int sum() {
+        return x.length() + super.x;
+    }
+}
@@ -0,0 +1,17 @@
+package overload;
+
+abstract class Overloads {
+    static native void out(int i);
+
+    static native void out(String s);
+
+    static int twice(int i) {
+        return i * 2;
+    }
+
+    static long twice(long l) {
+        return l * 2;
+    }
+
+    abstract void run();
+}
@@ -0,0 +1,28 @@
+package resolve;
+
+interface Named {
+    String NAME = String.valueOf(1);
+}
+
+class Parent {
+    static int count;
+
+    static int next() {
+        return ++count;
+    }
+}
+
+class Child extends Parent implements Named {
+}
+
+// Missing.class isn't kept, so resolving its field fails
+class User {
+    static int use() {
+        Child.count = Child.next();
+        return Child.NAME.length() + Missing.value;
+    }
+}
+
+class Missing {
+    static int value;
+}
@@ -1,4 +1,9 @@
-use crate::bytecode::ConstantPool;
+use std::borrow::Cow;
+
+use crate::{
+    bytecode::{ClassView, ConstantPool},
+    error::{Result, VmError},
+};
 
 
 pub struct ByteStream<'a> {
@@ -43,12 +48,14 @@ impl<'a> ByteStream<'a> {
         &self.data[self.index..]
     }
 
-    pub fn read<B: StreamRead>(&mut self, ctx: &ReaderContext) -> B {
+    pub fn read<B: StreamRead>(&mut self, ctx: &ReaderContext) -> Result<B> {
         B::read(self, ctx)
     }
 
-    pub fn read_many<B: StreamRead>(&mut self, count: usize, ctx: &ReaderContext) -> Vec<B> {
-        (0..count).map(|_| self.read(ctx)).collect()
+    pub fn read_many<B: StreamRead>(&mut self, count: usize, ctx: &ReaderContext) -> Result<Vec<B>> {
+        (0..count)
+            .map(|i| self.read(ctx).map_err(|e| e.within(format!("[{i}]"))))
+            .collect()
 
         // let data = &self.data[self.index..self.index + count * B::SIZE];
         // let data_ptr = data.as_ptr();
@@ -60,6 +67,25 @@ impl<'a> ByteStream<'a> {
         // data_slice
     }
 
+    /// Takes the next `len` bytes, failing if the stream ends before then.
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.data.len().saturating_sub(self.index) {
+            return Err(VmError::class_format(
+                self.index,
+                format!(
+                    "unexpected end of data: needed {} bytes, {} remaining",
+                    len,
+                    self.data.len().saturating_sub(self.index)
+                ),
+            ));
+        }
+
+        let data = &self.data[self.index..self.index + len];
+        self.index += len;
+
+        Ok(data)
+    }
+
     pub fn read_vary<V: VaryingRead<'a>>(&mut self) -> V {
         let len = V::len(&self.data[self.index..]);
 
@@ -161,29 +187,59 @@ pub trait VaryingRead<'a> {
     fn read(data: &'a [u8]) -> Self;
 }
 
-pub struct ReaderContext {
+pub struct ReaderContext<'v> {
     pub constant_pool: Vec<ConstantPool>,
+    /// The class file's major version, used to reject features that are newer than it.
+    pub major_version: u16,
+    /// When decoding through a `ClassView`, constants are looked up in the view on demand
+    /// instead of in `constant_pool`.
+    pub view: Option<&'v ClassView<'v>>,
+}
+
+impl ReaderContext<'_> {
+    /// Looks up the Utf8 constant at `index`.
+    pub fn utf8(&self, index: u16) -> Option<Cow<'_, str>> {
+        match self.view {
+            Some(view) => view.utf8(index).ok(),
+            None => match self.constant_pool.get(index as usize) {
+                Some(ConstantPool::Utf8(utf8)) => Some(Cow::Borrowed(utf8.as_str())),
+                _ => None,
+            },
+        }
+    }
 }
 
-pub trait StreamRead {
-    fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Self;
+pub trait StreamRead: Sized {
+    fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Self>;
 }
 
 macro_rules! impl_stream_read {
     ($ty:ty) => {
         impl StreamRead for $ty {
-            fn read<'a>(stream: &mut ByteStream<'a>, _ctx: &ReaderContext) -> Self {
-                let value = Self::from_be_bytes(stream.current()[..std::mem::size_of::<Self>()].try_into().unwrap());
+            fn read<'a>(stream: &mut ByteStream<'a>, _ctx: &ReaderContext) -> Result<Self> {
+                let bytes = stream.take(std::mem::size_of::<Self>())?;
 
-                stream.index += std::mem::size_of::<Self>();
-
-                value
+                Ok(Self::from_be_bytes(bytes.try_into().unwrap()))
             }
         }
     };
 }
 
 
+pub trait StreamWrite {
+    fn write(&self, buffer: &mut Vec<u8>);
+}
+
+macro_rules! impl_stream_write {
+    ($ty:ty) => {
+        impl StreamWrite for $ty {
+            fn write(&self, buffer: &mut Vec<u8>) {
+                buffer.extend_from_slice(&self.to_be_bytes());
+            }
+        }
+    };
+}
+
 impl_stream_read!(u8);
 impl_stream_read!(i8);
 impl_stream_read!(u16);
@@ -193,6 +249,15 @@ impl_stream_read!(i32);
 impl_stream_read!(u64);
 impl_stream_read!(i64);
 
+impl_stream_write!(u8);
+impl_stream_write!(i8);
+impl_stream_write!(u16);
+impl_stream_write!(i16);
+impl_stream_write!(u32);
+impl_stream_write!(i32);
+impl_stream_write!(u64);
+impl_stream_write!(i64);
+
 // impl <'a> VaryingByteRead<'a> for &'a str {
 // fn valid(data: &'a [u8]) -> bool {
 //     data[0] != 0
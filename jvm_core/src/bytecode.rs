@@ -1,8 +1,19 @@
 use bitflags::bitflags;
 
-use jvm_macros::StreamReader;
+use jvm_macros::{StreamReader, StreamWriter};
 
-use crate::{byte_stream::{ByteStream, ReaderContext, StreamRead}, bytecode::attribute_info::StackMapTable};
+use crate::{
+    byte_stream::{ByteStream, ReaderContext, StreamRead, StreamWrite},
+    error::{Result, VmError},
+    signature::{ClassSignature, GenericType, MethodSignature},
+};
+
+mod format_check;
+mod reference;
+mod view;
+
+pub use reference::{MemberKind, MemberRef, MethodHandleRef, ReferenceKind};
+pub use view::{AttributeView, ClassView, MemberView};
 
 macro_rules! impl_read {
     ($ty:ty) => {
@@ -31,6 +42,32 @@ macro_rules! impl_tag {
     };
 }
 
+/// The `magic` item identifying the class file format.
+pub const MAGIC: u32 = 0xCAFEBABE;
+/// The oldest class file major version that can be loaded (JDK 1.0.2).
+pub const MIN_MAJOR_VERSION: u16 = 45;
+/// The newest class file major version that can be loaded (Java 21).
+pub const MAX_MAJOR_VERSION: u16 = 65;
+/// The minor version marking a class file that depends on preview features.
+pub const PREVIEW_MINOR_VERSION: u16 = 0xFFFF;
+
+/// Returns the Java SE release that introduced a class file major version, such as "1.4" or "17".
+pub fn java_version(major_version: u16) -> String {
+    match major_version {
+        ..=45 => "1.1".to_string(),
+        46..=48 => format!("1.{}", major_version - 44),
+        _ => (major_version - 44).to_string(),
+    }
+}
+
+/// Options that change which class files are accepted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadOptions {
+    /// Accept class files that depend on the preview features of `MAX_MAJOR_VERSION`.
+    pub enable_preview: bool,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug)]
 pub struct ClassFile {
     magic: u32,
@@ -38,7 +75,7 @@ pub struct ClassFile {
     major_version: u16,
 
     constant_pools: Vec<ConstantPool>,
-    access_flags: AccessFlags,
+    access_flags: ClassAccess,
     this_class: u16,
     super_class: u16,
 
@@ -49,46 +86,64 @@ pub struct ClassFile {
 }
 
 impl ClassFile {
-    pub fn read<'a>(stream: &'a mut ByteStream<'a>) -> Self {
+    pub fn read<'a>(stream: &'a mut ByteStream<'a>) -> Result<Self> {
+        Self::read_with_options(stream, ReadOptions::default())
+    }
+
+    pub fn read_with_options<'a>(
+        stream: &'a mut ByteStream<'a>,
+        options: ReadOptions,
+    ) -> Result<Self> {
         let mut ctx = ReaderContext {
             constant_pool: Vec::new(),
+            major_version: 0,
+            view: None,
         };
 
-        let magic = stream.read(&ctx);
-        let minor_version = stream.read(&ctx);
-        let major_version = stream.read(&ctx);
-
-        println!(
-            "{:X} {}.{} {}",
-            magic, major_version, minor_version, stream.index
-        );
-
-        let constant_pool_count = stream.read::<u16>(&ctx) - 1;
-        println!("{}", constant_pool_count);
-
-        let mut constant_pools = vec![ConstantPool::Empty]; // Constant Pool indicies are 1 based indexed (i think), so add empty as an offset
-        constant_pools.extend(stream.read_many(constant_pool_count as _, &ctx));
-
-        ctx.constant_pool = constant_pools;
-
-        let access_flags =
-            AccessFlags::from_bits(stream.read(&ctx)).expect("Unable to read access flags!");
-        let this_class = stream.read(&ctx);
-        let super_class = stream.read(&ctx);
-
-        let interface_count = stream.read::<u16>(&ctx);
-        let interfaces = stream.read_many(interface_count as usize, &ctx);
-
-        let field_count = stream.read::<u16>(&ctx);
-        let fields = stream.read_many(field_count as usize, &ctx);
-
-        let method_count = stream.read::<u16>(&ctx);
-        let methods = stream.read_many(method_count as usize, &ctx);
+        let magic = stream.read(&ctx).map_err(|e| e.within("magic"))?;
+        if magic != MAGIC {
+            return Err(VmError::class_format(
+                0,
+                format!("bad magic 0x{:X}, expected 0x{:X}", magic, MAGIC),
+            )
+            .within("magic"));
+        }
 
-        let attribute_count = stream.read::<u16>(&ctx);
-        let attributes = stream.read_many(attribute_count as usize, &ctx);
+        let minor_version = stream.read(&ctx).map_err(|e| e.within("minor_version"))?;
+        let major_version = stream.read(&ctx).map_err(|e| e.within("major_version"))?;
+        Self::check_version(major_version, minor_version, options)?;
+
+        tracing::debug!("Reading class file version {}.{}", major_version, minor_version);
+        ctx.major_version = major_version;
+
+        let constant_pool_count = stream
+            .read::<u16>(&ctx)
+            .map_err(|e| e.within("constant_pool_count"))?;
+
+        ctx.constant_pool = Self::read_constant_pool(stream, &ctx, constant_pool_count)
+            .map_err(|e| e.within("constant_pool"))?;
+
+        // Unknown access flag bits are reserved and must be ignored (JVMS 4.1)
+        let access_flags = stream.read(&ctx).map_err(|e| e.within("access_flags"))?;
+        let this_class = stream.read(&ctx).map_err(|e| e.within("this_class"))?;
+        let super_class = stream.read(&ctx).map_err(|e| e.within("super_class"))?;
+
+        let interfaces = Self::read_table(stream, &ctx, "interfaces")?;
+        let fields = Self::read_table(stream, &ctx, "fields")?;
+        let methods = Self::read_table(stream, &ctx, "methods")?;
+        let attributes = Self::read_table(stream, &ctx, "attributes")?;
+
+        if stream.has_next() {
+            return Err(VmError::class_format(
+                stream.index,
+                format!(
+                    "{} unexpected bytes after the end of the class file",
+                    stream.data.len() - stream.index
+                ),
+            ));
+        }
 
-        Self {
+        Ok(Self {
             magic,
             minor_version,
             major_version,
@@ -102,7 +157,120 @@ impl ClassFile {
             fields,
             methods,
             attributes,
+        })
+    }
+
+    /// Checks that a class file version can be loaded (JVMS 4.1).
+    fn check_version(major: u16, minor: u16, options: ReadOptions) -> Result<()> {
+        let supported = match major {
+            MIN_MAJOR_VERSION..=55 => true,
+            // From Java 12, the minor version is only used to mark preview features
+            56..=MAX_MAJOR_VERSION => match minor {
+                0 => true,
+                PREVIEW_MINOR_VERSION => major == MAX_MAJOR_VERSION && options.enable_preview,
+                _ => false,
+            },
+            _ => false,
+        };
+
+        if supported {
+            Ok(())
+        } else {
+            Err(VmError::UnsupportedClassVersion { major, minor })
+        }
+    }
+
+    pub fn major_version(&self) -> u16 {
+        self.major_version
+    }
+
+    pub fn minor_version(&self) -> u16 {
+        self.minor_version
+    }
+
+    /// Serializes the class file. Unmodified class files are written back byte for byte.
+    pub fn write(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+
+        self.magic.write(&mut buffer);
+        self.minor_version.write(&mut buffer);
+        self.major_version.write(&mut buffer);
+
+        (self.constant_pools.len() as u16).write(&mut buffer);
+        for constant in &self.constant_pools {
+            constant.write(&mut buffer);
+        }
+
+        self.access_flags.write(&mut buffer);
+        self.this_class.write(&mut buffer);
+        self.super_class.write(&mut buffer);
+
+        Self::write_table(&self.interfaces, &mut buffer);
+        Self::write_table(&self.fields, &mut buffer);
+        Self::write_table(&self.methods, &mut buffer);
+        Self::write_table(&self.attributes, &mut buffer);
+
+        buffer
+    }
+
+    fn write_table<T: StreamWrite>(items: &[T], buffer: &mut Vec<u8>) {
+        (items.len() as u16).write(buffer);
+
+        for item in items {
+            item.write(buffer);
+        }
+    }
+
+    /// Reads the constant pool entries `1..count`.
+    ///
+    /// Index 0 is never valid and `Long`/`Double` entries take up two slots (JVMS 4.4.5),
+    /// so those positions are filled with `ConstantPool::Empty` to keep indices aligned.
+    fn read_constant_pool(
+        stream: &mut ByteStream<'_>,
+        ctx: &ReaderContext,
+        count: u16,
+    ) -> Result<Vec<ConstantPool>> {
+        let mut constant_pools = Vec::with_capacity(count as usize);
+        constant_pools.push(ConstantPool::Empty);
+
+        while constant_pools.len() < count as usize {
+            let index = constant_pools.len();
+            let offset = stream.index;
+
+            let constant = stream
+                .read::<ConstantPool>(ctx)
+                .map_err(|e| e.within(format!("[{index}]")))?;
+
+            let wide = matches!(constant, ConstantPool::Long(_) | ConstantPool::Double(_));
+            constant_pools.push(constant);
+
+            if wide {
+                if constant_pools.len() >= count as usize {
+                    return Err(VmError::class_format(
+                        offset,
+                        "8 byte constant has no room for its second slot",
+                    )
+                    .within(format!("[{index}]")));
+                }
+
+                constant_pools.push(ConstantPool::Empty);
+            }
         }
+
+        Ok(constant_pools)
+    }
+
+    /// Reads a `u16` count followed by that many items.
+    fn read_table<T: StreamRead>(
+        stream: &mut ByteStream<'_>,
+        ctx: &ReaderContext,
+        name: &str,
+    ) -> Result<Vec<T>> {
+        let count = stream.read::<u16>(ctx).map_err(|e| e.within(name))?;
+
+        stream
+            .read_many(count as usize, ctx)
+            .map_err(|e| e.within(name))
     }
 
     pub fn methods(&self) -> &[MethodInfo] {
@@ -113,6 +281,48 @@ impl ClassFile {
         &self.fields
     }
 
+    pub fn access_flags(&self) -> ClassAccess {
+        self.access_flags
+    }
+
+    pub fn is_interface(&self) -> bool {
+        self.access_flags.contains(ClassAccess::INTERFACE)
+    }
+
+    pub fn is_abstract(&self) -> bool {
+        self.access_flags.contains(ClassAccess::ABSTRACT)
+    }
+
+    pub fn is_final(&self) -> bool {
+        self.access_flags.contains(ClassAccess::FINAL)
+    }
+
+    pub fn attributes(&self) -> &[AttributeInfo] {
+        &self.attributes
+    }
+
+    /// Both the runtime visible and invisible annotations on this class.
+    pub fn annotations(&self) -> impl Iterator<Item = &attribute_info::Annotation> {
+        annotations(&self.attributes)
+    }
+
+    /// Finds an annotation by its type descriptor, such as `Lcom/acme/Foo;`.
+    pub fn annotation(&self, type_descriptor: &str) -> Option<&attribute_info::Annotation> {
+        self.annotations()
+            .find(|annotation| annotation.type_descriptor == type_descriptor)
+    }
+
+    pub fn type_annotations(&self) -> impl Iterator<Item = &attribute_info::TypeAnnotation> {
+        type_annotations(&self.attributes)
+    }
+
+    /// The generic signature of this class, if it has a `Signature` attribute.
+    pub fn signature(&self) -> Result<Option<ClassSignature>> {
+        signature(self, &self.attributes)?
+            .map(ClassSignature::parse)
+            .transpose()
+    }
+
     pub fn get_str(&self, index: usize) -> &str {
         match &self.constant_pools[index] {
             ConstantPool::Utf8(str) => str.as_str(),
@@ -132,59 +342,136 @@ impl ClassFile {
     pub fn pool(&self, index: usize) -> &ConstantPool {
         &self.constant_pools[index]
     }
+
+    /// The constant pool, including the unused entry at index 0 and after `Long` and `Double`.
+    pub fn constant_pool(&self) -> &[ConstantPool] {
+        &self.constant_pools
+    }
+
+    pub fn this_class(&self) -> u16 {
+        self.this_class
+    }
+
+    /// The index of the superclass, or 0 for `java/lang/Object`.
+    pub fn super_class(&self) -> u16 {
+        self.super_class
+    }
+
+    pub fn interfaces(&self) -> &[u16] {
+        &self.interfaces
+    }
+}
+
+macro_rules! impl_access_flags {
+    ($($ty:ty),*) => {
+        $(
+            impl StreamRead for $ty {
+                fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Self> {
+                    // Reserved bits are kept so they can be written back out unchanged
+                    Ok(Self::from_bits_retain(stream.read(ctx)?))
+                }
+            }
+
+            impl StreamWrite for $ty {
+                fn write(&self, buffer: &mut Vec<u8>) {
+                    self.bits().write(buffer)
+                }
+            }
+        )*
+    };
+}
+
+bitflags! {
+    /// The access permissions to and properties of a class or interface (JVMS 4.1).
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct ClassAccess: u16 {
+        /// Declared public; may be accessed from outside its package.
+        const PUBLIC = 0x0001;
+        /// Declared final; no subclasses allowed.
+        const FINAL = 0x0010;
+        /// Treat superclass methods specially when invoked by the invokespecial instruction.
+        const SUPER = 0x0020;
+        /// Is an interface, not a class.
+        const INTERFACE = 0x0200;
+        /// Declared abstract; must not be instantiated.
+        const ABSTRACT = 0x0400;
+        /// Declared synthetic; not present in the source code.
+        const SYNTHETIC = 0x1000;
+        /// Declared as an annotation interface.
+        const ANNOTATION = 0x2000;
+        /// Declared as an enum class.
+        const ENUM = 0x4000;
+        /// Is a module, not a class or interface.
+        const MODULE = 0x8000;
+    }
 }
 
 bitflags! {
-    /// The value of the access_flags item is a mask of flags used
-    /// to denote access permissions to and properties of this class or interface.
+    /// The access permissions to and properties of a field (JVMS 4.5).
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    struct AccessFlags: u16 {
+    pub struct FieldAccess: u16 {
         /// Declared public; may be accessed from outside its package.
         const PUBLIC = 0x0001;
-        /// Declared private; usable only within the defining class.
+        /// Declared private; accessible only within the defining class and other classes
+        /// belonging to the same nest.
         const PRIVATE = 0x0002;
         /// Declared protected; may be accessed within subclasses.
         const PROTECTED = 0x0004;
         /// Declared static.
         const STATIC = 0x0008;
-        /// Declared final; no subclasses allowed.
+        /// Declared final; never directly assigned to after object construction.
         const FINAL = 0x0010;
-        /// Treat superclass methods specially when invoked by the invokespecial instruction.
-        const SUPER = 0x0020;
-        /// Declared synchronized; invocation is wrapped by a monitor use.
-        const SYNCHRONIZED = 0x0020;
         /// Declared volatile; cannot be cached.
         const VOLATILE = 0x0040;
-        /// A bridge method, generated by the compiler.
-        const BRIDGE = 0x0040;
         /// Declared transient; not written or read by a persistent object manager.
         const TRANSIENT = 0x0080;
+        /// Declared synthetic; not present in the source code.
+        const SYNTHETIC = 0x1000;
+        /// Declared as an element of an enum class.
+        const ENUM = 0x4000;
+    }
+}
+
+bitflags! {
+    /// The access permissions to and properties of a method (JVMS 4.6).
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct MethodAccess: u16 {
+        /// Declared public; may be accessed from outside its package.
+        const PUBLIC = 0x0001;
+        /// Declared private; accessible only within the defining class and other classes
+        /// belonging to the same nest.
+        const PRIVATE = 0x0002;
+        /// Declared protected; may be accessed within subclasses.
+        const PROTECTED = 0x0004;
+        /// Declared static.
+        const STATIC = 0x0008;
+        /// Declared final; must not be overridden.
+        const FINAL = 0x0010;
+        /// Declared synchronized; invocation is wrapped by a monitor use.
+        const SYNCHRONIZED = 0x0020;
+        /// A bridge method, generated by the compiler.
+        const BRIDGE = 0x0040;
         /// Declared with variable number of arguments.
         const VARARGS = 0x0080;
         /// Declared native; implemented in a language other than Java.
         const NATIVE = 0x0100;
-        /// Is an interface, not a class.
-        const INTERFACE = 0x0200;
-        /// Declared abstract; must not be instantiated.
+        /// Declared abstract; no implementation is provided.
         const ABSTRACT = 0x0400;
-        /// Declared strictfp; floating-point mode is FP-strict.
+        /// In a class file whose major version is at least 46 and at most 60:
+        /// Declared strictfp.
         const STRICT = 0x0800;
         /// Declared synthetic; not present in the source code.
         const SYNTHETIC = 0x1000;
-        /// Declared as an annotation type.
-        const ANNOTATION = 0x2000;
-        /// Declared as an enum type.
-        const ENUM = 0x4000;
     }
 }
 
-impl StreamRead for AccessFlags {
-    fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Self {
-        Self::from_bits_truncate(stream.read(ctx))
-    }
-}
+impl_access_flags!(ClassAccess, FieldAccess, MethodAccess);
 
 #[repr(u8)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Debug)]
 pub enum ConstantPool {
     Empty = 0,
@@ -201,110 +488,231 @@ pub enum ConstantPool {
     Utf8(constant_pool::Utf8) = 1,
     MethodHandle(constant_pool::MethodHandle) = 15,
     MethodType(constant_pool::MethodType) = 16,
+    Dynamic(constant_pool::InvokeDynamic) = 17,
     InvokeDynamic(constant_pool::InvokeDynamic) = 18,
+    Module(constant_pool::Module) = 19,
+    Package(constant_pool::Package) = 20,
 }
 
 impl StreamRead for ConstantPool {
-    fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Self {
-        let tag = stream.read::<u8>(ctx);
+    fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Self> {
+        let tag_offset = stream.index;
+        let tag = stream.read::<u8>(ctx)?;
+
+        // Constants introduced after the original format are only valid from their release
+        let introduced = match tag {
+            constant_pool::MethodHandle::TAG
+            | constant_pool::MethodType::TAG
+            | constant_pool::InvokeDynamic::TAG => 51,
+            constant_pool::Module::TAG | constant_pool::Package::TAG => 53,
+            constant_pool::Dynamic::TAG => 55,
+            _ => 0,
+        };
+
+        if ctx.major_version < introduced {
+            return Err(VmError::class_format(
+                tag_offset,
+                format!(
+                    "constant pool tag {} requires class file version {} (Java {}), but the class file is version {} (Java {})",
+                    tag,
+                    introduced,
+                    java_version(introduced),
+                    ctx.major_version,
+                    java_version(ctx.major_version),
+                ),
+            ));
+        }
 
-        match tag {
-            constant_pool::Class::TAG => Self::Class(constant_pool::Class::read(stream, ctx)),
-            constant_pool::FieldRef::TAG => Self::FieldRef(constant_pool::Ref::read(stream, ctx)),
-            constant_pool::MethodRef::TAG => Self::MethodRef(constant_pool::Ref::read(stream, ctx)),
+        Ok(match tag {
+            constant_pool::Class::TAG => Self::Class(constant_pool::Class::read(stream, ctx)?),
+            constant_pool::FieldRef::TAG => Self::FieldRef(constant_pool::Ref::read(stream, ctx)?),
+            constant_pool::MethodRef::TAG => Self::MethodRef(constant_pool::Ref::read(stream, ctx)?),
             constant_pool::InterfaceMethodRef::TAG => {
-                Self::InterfaceMethodRef(constant_pool::Ref::read(stream, ctx))
+                Self::InterfaceMethodRef(constant_pool::Ref::read(stream, ctx)?)
             }
-            constant_pool::String::TAG => Self::String(constant_pool::String::read(stream, ctx)),
-            constant_pool::Integer::TAG => Self::Integer(constant_pool::Numeric::read(stream, ctx)),
-            constant_pool::Float::TAG => Self::Float(constant_pool::Numeric::read(stream, ctx)),
-            constant_pool::Long::TAG => Self::Long(constant_pool::LongNumeric::read(stream, ctx)),
+            constant_pool::String::TAG => Self::String(constant_pool::String::read(stream, ctx)?),
+            constant_pool::Integer::TAG => Self::Integer(constant_pool::Numeric::read(stream, ctx)?),
+            constant_pool::Float::TAG => Self::Float(constant_pool::Numeric::read(stream, ctx)?),
+            constant_pool::Long::TAG => Self::Long(constant_pool::LongNumeric::read(stream, ctx)?),
             constant_pool::Double::TAG => {
-                Self::Double(constant_pool::LongNumeric::read(stream, ctx))
+                Self::Double(constant_pool::LongNumeric::read(stream, ctx)?)
             }
             constant_pool::NameAndType::TAG => {
-                Self::NameAndType(constant_pool::NameAndType::read(stream, ctx))
+                Self::NameAndType(constant_pool::NameAndType::read(stream, ctx)?)
             }
-            constant_pool::Utf8::TAG => Self::Utf8(constant_pool::Utf8::read(stream, ctx)),
+            constant_pool::Utf8::TAG => Self::Utf8(constant_pool::Utf8::read(stream, ctx)?),
             constant_pool::MethodHandle::TAG => {
-                Self::MethodHandle(constant_pool::MethodHandle::read(stream, ctx))
+                Self::MethodHandle(constant_pool::MethodHandle::read(stream, ctx)?)
             }
             constant_pool::MethodType::TAG => {
-                Self::MethodType(constant_pool::MethodType::read(stream, ctx))
+                Self::MethodType(constant_pool::MethodType::read(stream, ctx)?)
+            }
+            constant_pool::Dynamic::TAG => {
+                Self::Dynamic(constant_pool::InvokeDynamic::read(stream, ctx)?)
             }
             constant_pool::InvokeDynamic::TAG => {
-                Self::InvokeDynamic(constant_pool::InvokeDynamic::read(stream, ctx))
+                Self::InvokeDynamic(constant_pool::InvokeDynamic::read(stream, ctx)?)
+            }
+            constant_pool::Module::TAG => Self::Module(constant_pool::Module::read(stream, ctx)?),
+            constant_pool::Package::TAG => {
+                Self::Package(constant_pool::Package::read(stream, ctx)?)
+            }
+            _ => {
+                return Err(VmError::class_format(
+                    tag_offset,
+                    format!("unknown constant pool tag {}", tag),
+                ))
             }
-            _ => unimplemented!(),
+        })
+    }
+}
+
+impl StreamWrite for ConstantPool {
+    fn write(&self, buffer: &mut Vec<u8>) {
+        let tag = match self {
+            // Index 0 and the second slot of 8 byte constants aren't stored
+            Self::Empty => return,
+            Self::Class(_) => constant_pool::Class::TAG,
+            Self::FieldRef(_) => constant_pool::FieldRef::TAG,
+            Self::MethodRef(_) => constant_pool::MethodRef::TAG,
+            Self::InterfaceMethodRef(_) => constant_pool::InterfaceMethodRef::TAG,
+            Self::String(_) => constant_pool::String::TAG,
+            Self::Integer(_) => constant_pool::Integer::TAG,
+            Self::Float(_) => constant_pool::Float::TAG,
+            Self::Long(_) => constant_pool::Long::TAG,
+            Self::Double(_) => constant_pool::Double::TAG,
+            Self::NameAndType(_) => constant_pool::NameAndType::TAG,
+            Self::Utf8(_) => constant_pool::Utf8::TAG,
+            Self::MethodHandle(_) => constant_pool::MethodHandle::TAG,
+            Self::MethodType(_) => constant_pool::MethodType::TAG,
+            Self::Dynamic(_) => constant_pool::Dynamic::TAG,
+            Self::InvokeDynamic(_) => constant_pool::InvokeDynamic::TAG,
+            Self::Module(_) => constant_pool::Module::TAG,
+            Self::Package(_) => constant_pool::Package::TAG,
+        };
+
+        tag.write(buffer);
+
+        match self {
+            Self::Empty => (),
+            Self::Class(c) => c.write(buffer),
+            Self::FieldRef(c) | Self::MethodRef(c) | Self::InterfaceMethodRef(c) => c.write(buffer),
+            Self::String(c) => c.write(buffer),
+            Self::Integer(c) | Self::Float(c) => c.write(buffer),
+            Self::Long(c) | Self::Double(c) => c.write(buffer),
+            Self::NameAndType(c) => c.write(buffer),
+            Self::Utf8(c) => c.write(buffer),
+            Self::MethodHandle(c) => c.write(buffer),
+            Self::MethodType(c) => c.write(buffer),
+            Self::Dynamic(c) | Self::InvokeDynamic(c) => c.write(buffer),
+            Self::Module(c) => c.write(buffer),
+            Self::Package(c) => c.write(buffer),
         }
     }
 }
 
 pub mod constant_pool {
-    use jvm_macros::StreamReader;
+    use jvm_macros::{StreamReader, StreamWriter};
 
-    use crate::byte_stream::StreamRead;
+    use crate::{
+        byte_stream::{StreamRead, StreamWrite},
+        error::{Result, VmError},
+    };
 
-    #[derive(Clone, Copy, Debug, StreamReader)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
     pub struct Class {
         pub name_index: u16,
     }
 
-    #[derive(Clone, Copy, Debug, StreamReader)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
     pub struct Ref {
         pub class_index: u16,
         pub name_and_type_index: u16,
     }
 
-    #[derive(Clone, Copy, Debug, StreamReader)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
     pub struct String {
         pub string_index: u16,
     }
 
-    #[derive(Clone, Copy, Debug, StreamReader)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
     pub struct Numeric {
         pub bytes: u32,
     }
 
-    #[derive(Clone, Copy, Debug, StreamReader)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
     pub struct LongNumeric {
         pub bytes: u64,
     }
 
-    #[derive(Clone, Copy, Debug, StreamReader)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
     pub struct NameAndType {
         pub name_index: u16,
         pub descriptor_index: u16,
     }
 
+    /// A string constant, decoded from the modified UTF-8 used by class files (JVMS 4.4.7).
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     #[derive(Clone, Debug)]
     pub struct Utf8 {
         string: std::string::String,
+        /// The original bytes, kept only when encoding `string` wouldn't reproduce them.
+        raw: Option<Box<[u8]>>,
     }
 
     impl Utf8 {
+        pub fn new(string: impl Into<std::string::String>) -> Self {
+            Self {
+                string: string.into(),
+                raw: None,
+            }
+        }
+
         pub fn as_str(&self) -> &str {
             &self.string
         }
     }
 
-    #[derive(Clone, Copy, Debug, StreamReader)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
     pub struct MethodHandle {
         pub reference_kind: u8,
         pub reference_index: u16,
     }
 
-    #[derive(Clone, Copy, Debug, StreamReader)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
     pub struct MethodType {
         pub descriptor_index: u16,
     }
 
-    #[derive(Clone, Copy, Debug, StreamReader)]
+    /// Shared by `CONSTANT_InvokeDynamic` and `CONSTANT_Dynamic`.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
     pub struct InvokeDynamic {
         pub bootstrap_method_attr_index: u16,
         pub name_and_type_index: u16,
     }
 
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
+    pub struct Module {
+        pub name_index: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Clone, Copy, Debug, StreamReader, StreamWriter)]
+    pub struct Package {
+        pub name_index: u16,
+    }
+
+    pub struct Dynamic;
     pub struct MethodRef;
     pub struct FieldRef;
     pub struct InterfaceMethodRef;
@@ -317,16 +725,150 @@ pub mod constant_pool {
         fn read<'a>(
             stream: &mut crate::byte_stream::ByteStream<'a>,
             ctx: &crate::byte_stream::ReaderContext,
-        ) -> Self {
-            let len = stream.read::<u16>(ctx) as usize;
-            stream.index += len;
+        ) -> Result<Self> {
+            let len = stream.read::<u16>(ctx)? as usize;
+            let start = stream.index;
+            let data = stream.take(len)?;
+
+            let (string, canonical) = decode(data).map_err(|index| {
+                VmError::class_format(start + index, "malformed modified UTF-8 string")
+            })?;
+
+            Ok(Self {
+                string,
+                raw: (!canonical).then(|| data.into()),
+            })
+        }
+    }
+
+    impl StreamWrite for Utf8 {
+        fn write(&self, buffer: &mut Vec<u8>) {
+            let encoded;
+            let data = match &self.raw {
+                Some(raw) => raw,
+                None => {
+                    encoded = encode_modified_utf8(&self.string);
+                    &encoded[..]
+                }
+            };
 
-            let data = &stream.data[stream.index - len..stream.index];
+            (data.len() as u16).write(buffer);
+            buffer.extend_from_slice(data);
+        }
+    }
 
-            Self {
-                string: std::string::String::from_utf8_lossy(data).to_string(),
+    /// Decodes modified UTF-8, returning the index of the first malformed byte on failure.
+    ///
+    /// This differs from standard UTF-8 in that NUL is encoded as two bytes (`C0 80`)
+    /// and supplementary characters are encoded as a surrogate pair of three byte sequences.
+    /// Unpaired surrogates can't be represented in a `String` and are replaced with U+FFFD.
+    pub fn decode_modified_utf8(data: &[u8]) -> std::result::Result<std::string::String, usize> {
+        decode(data).map(|(string, _)| string)
+    }
+
+    /// Encodes a string as modified UTF-8.
+    pub fn encode_modified_utf8(string: &str) -> Vec<u8> {
+        let mut data = Vec::with_capacity(string.len());
+
+        for c in string.chars() {
+            match c as u32 {
+                0x01..=0x7F => data.push(c as u8),
+                c @ (0x00 | 0x80..=0x7FF) => {
+                    data.extend([0xC0 | (c >> 6) as u8, 0x80 | (c & 0x3F) as u8])
+                }
+                0x800..=0xFFFF => {
+                    let mut units = [0; 1];
+                    c.encode_utf16(&mut units);
+                    push3(&mut data, units[0]);
+                }
+                _ => {
+                    let mut units = [0; 2];
+                    c.encode_utf16(&mut units);
+                    push3(&mut data, units[0]);
+                    push3(&mut data, units[1]);
+                }
+            }
+        }
+
+        data
+    }
+
+    fn push3(data: &mut Vec<u8>, unit: u16) {
+        data.extend([
+            0xE0 | (unit >> 12) as u8,
+            0x80 | ((unit >> 6) & 0x3F) as u8,
+            0x80 | (unit & 0x3F) as u8,
+        ]);
+    }
+
+    /// Decodes modified UTF-8, also returning whether the input is exactly what
+    /// `encode_modified_utf8` would produce for the result.
+    fn decode(data: &[u8]) -> std::result::Result<(std::string::String, bool), usize> {
+        let mut string = std::string::String::with_capacity(data.len());
+        let mut canonical = true;
+        let mut i = 0;
+
+        // Reads a three byte sequence at `i`, returning its UTF-16 code unit
+        let read3 = |i: usize| -> Option<u32> {
+            match data.get(i..i + 3)? {
+                [a, b, c] if a & 0xF0 == 0xE0 && b & 0xC0 == 0x80 && c & 0xC0 == 0x80 => Some(
+                    ((*a as u32 & 0x0F) << 12) | ((*b as u32 & 0x3F) << 6) | (*c as u32 & 0x3F),
+                ),
+                _ => None,
+            }
+        };
+
+        while i < data.len() {
+            let a = data[i];
+
+            match a {
+                0x01..=0x7F => {
+                    string.push(a as char);
+                    i += 1;
+                }
+                0xC0..=0xDF => {
+                    let b = *data.get(i + 1).filter(|b| *b & 0xC0 == 0x80).ok_or(i)?;
+                    let c = ((a as u32 & 0x1F) << 6) | (b as u32 & 0x3F);
+
+                    canonical &= c == 0 || c >= 0x80;
+                    string.push(char::from_u32(c).ok_or(i)?);
+                    i += 2;
+                }
+                0xE0..=0xEF => {
+                    let high = read3(i).ok_or(i)?;
+
+                    match high {
+                        0xD800..=0xDBFF => match read3(i + 3) {
+                            Some(low @ 0xDC00..=0xDFFF) => {
+                                let c = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+
+                                string.push(char::from_u32(c).ok_or(i)?);
+                                i += 6;
+                            }
+                            _ => {
+                                canonical = false;
+                                string.push(char::REPLACEMENT_CHARACTER);
+                                i += 3;
+                            }
+                        },
+                        0xDC00..=0xDFFF => {
+                            canonical = false;
+                            string.push(char::REPLACEMENT_CHARACTER);
+                            i += 3;
+                        }
+                        c => {
+                            canonical &= c >= 0x800;
+                            string.push(char::from_u32(c).ok_or(i)?);
+                            i += 3;
+                        }
+                    }
+                }
+                // NUL and bytes in the range `F0..FF` can't appear in modified UTF-8
+                _ => return Err(i),
             }
         }
+
+        Ok((string, canonical))
     }
 
     impl_tag!(Class, 7);
@@ -342,12 +884,16 @@ pub mod constant_pool {
     impl_tag!(Utf8, 1);
     impl_tag!(MethodHandle, 15);
     impl_tag!(MethodType, 16);
+    impl_tag!(Dynamic, 17);
     impl_tag!(InvokeDynamic, 18);
+    impl_tag!(Module, 19);
+    impl_tag!(Package, 20);
 }
 
-#[derive(Debug, StreamReader, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, StreamReader, StreamWriter, Clone)]
 pub struct FieldInfo {
-    access_flags: AccessFlags,
+    access_flags: FieldAccess,
     pub name_index: u16,
     pub descriptor_index: u16,
     #[many(u16)]
@@ -358,11 +904,61 @@ impl FieldInfo {
     pub fn name<'a>(&'a self, file: &'a ClassFile) -> &'a str {
         file.get_str(self.name_index as usize)
     }
+
+    pub fn access_flags(&self) -> FieldAccess {
+        self.access_flags
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.access_flags.contains(FieldAccess::STATIC)
+    }
+
+    pub fn is_final(&self) -> bool {
+        self.access_flags.contains(FieldAccess::FINAL)
+    }
+
+    pub fn attributes(&self) -> &[AttributeInfo] {
+        &self.attributes
+    }
+
+    /// The constant pool index of the value a static field starts with, from its
+    /// `ConstantValue` attribute.
+    pub fn constant_value(&self) -> Option<u16> {
+        self.attributes
+            .iter()
+            .find_map(|attr| match &attr.attribute {
+                Attribute::ConstantValue(constant) => Some(constant.constantvalue_index),
+                _ => None,
+            })
+    }
+
+    /// Both the runtime visible and invisible annotations on this field.
+    pub fn annotations(&self) -> impl Iterator<Item = &attribute_info::Annotation> {
+        annotations(&self.attributes)
+    }
+
+    /// Finds an annotation by its type descriptor, such as `Lcom/acme/Foo;`.
+    pub fn annotation(&self, type_descriptor: &str) -> Option<&attribute_info::Annotation> {
+        self.annotations()
+            .find(|annotation| annotation.type_descriptor == type_descriptor)
+    }
+
+    pub fn type_annotations(&self) -> impl Iterator<Item = &attribute_info::TypeAnnotation> {
+        type_annotations(&self.attributes)
+    }
+
+    /// The generic type of this field, if it has a `Signature` attribute.
+    pub fn signature(&self, file: &ClassFile) -> Result<Option<GenericType>> {
+        signature(file, &self.attributes)?
+            .map(GenericType::parse_field)
+            .transpose()
+    }
 }
 
-#[derive(Debug, StreamReader, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, StreamReader, StreamWriter, Clone)]
 pub struct MethodInfo {
-    access_flags: AccessFlags,
+    access_flags: MethodAccess,
     pub name_index: u16,
     pub descriptor_index: u16,
     #[many(u16)]
@@ -374,6 +970,26 @@ impl MethodInfo {
         file.get_str(self.name_index as usize)
     }
 
+    pub fn access_flags(&self) -> MethodAccess {
+        self.access_flags
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.access_flags.contains(MethodAccess::STATIC)
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.access_flags.contains(MethodAccess::NATIVE)
+    }
+
+    pub fn is_abstract(&self) -> bool {
+        self.access_flags.contains(MethodAccess::ABSTRACT)
+    }
+
+    pub fn attributes(&self) -> &[AttributeInfo] {
+        &self.attributes
+    }
+
     pub fn code(&self) -> Option<attribute_info::Code> {
         self.attributes
             .iter()
@@ -382,69 +998,342 @@ impl MethodInfo {
                 _ => None,
             })
     }
-}
-
-#[derive(Debug, Clone)]
-pub struct AttributeInfo {
-    attribute_name_index: u16,
-    attribute: Attribute,
-}
-
-impl StreamRead for AttributeInfo {
-    fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Self {
-        let attribute_name_index = stream.read(ctx);
-        let len = stream.read::<u32>(ctx) as usize;
 
-        let attr = &ctx.constant_pool[attribute_name_index as usize];
+    /// Both the runtime visible and invisible annotations on this method.
+    pub fn annotations(&self) -> impl Iterator<Item = &attribute_info::Annotation> {
+        annotations(&self.attributes)
+    }
 
-        let old_index = stream.index;
+    /// Finds an annotation by its type descriptor, such as `Lcom/acme/Foo;`.
+    pub fn annotation(&self, type_descriptor: &str) -> Option<&attribute_info::Annotation> {
+        self.annotations()
+            .find(|annotation| annotation.type_descriptor == type_descriptor)
+    }
 
-        let attribute = match attr {
-            ConstantPool::Utf8(str) => match str.as_str() {
-                attribute_info::Code::TAG => {
-                    Attribute::Code(attribute_info::Code::read(stream, ctx))
-                }
-                attribute_info::LineNumberTable::TAG => {
-                    Attribute::LineNumberTable(attribute_info::LineNumberTable::read(stream, ctx))
-                }
-                attribute_info::SourceFile::TAG => {
-                    Attribute::SourceFile(attribute_info::SourceFile::read(stream, ctx))
-                }
-                attribute_info::StackMapTable::TAG => {
-                    stream.index += len;
-                    Attribute::StackMapTable(StackMapTable {})
+    /// Both the runtime visible and invisible annotations on the parameter at `index`.
+    ///
+    /// Note that the index is into the parameters that javac chose to describe, which may
+    /// exclude synthetic or implicit parameters (JVMS 4.7.18).
+    pub fn parameter_annotations(
+        &self,
+        index: usize,
+    ) -> impl Iterator<Item = &attribute_info::Annotation> {
+        self.attributes
+            .iter()
+            .filter_map(move |attr| match &attr.attribute {
+                Attribute::RuntimeVisibleParameterAnnotations(annotations)
+                | Attribute::RuntimeInvisibleParameterAnnotations(annotations) => {
+                    annotations.parameter_annotations.get(index)
                 }
-                _ => panic!("Unknown attribute '{}'!", str.as_str()),
-            },
-            _ => panic!("Expected string in attribute index!"),
-        };
+                _ => None,
+            })
+            .flat_map(|parameter| parameter.annotations.iter())
+    }
 
-        let dlength = stream.index - old_index;
-        assert_eq!(dlength, len);
+    pub fn type_annotations(&self) -> impl Iterator<Item = &attribute_info::TypeAnnotation> {
+        type_annotations(&self.attributes)
+    }
 
-        AttributeInfo {
-            attribute_name_index,
-            attribute,
-        }
+    /// The default value of an annotation interface element.
+    pub fn annotation_default(&self) -> Option<&attribute_info::ElementValue> {
+        self.attributes
+            .iter()
+            .find_map(|attr| match &attr.attribute {
+                Attribute::AnnotationDefault(default) => Some(&default.default_value),
+                _ => None,
+            })
+    }
+
+    /// The generic signature of this method, if it has a `Signature` attribute.
+    pub fn signature(&self, file: &ClassFile) -> Result<Option<MethodSignature>> {
+        signature(file, &self.attributes)?
+            .map(MethodSignature::parse)
+            .transpose()
     }
 }
 
+fn annotations(attributes: &[AttributeInfo]) -> impl Iterator<Item = &attribute_info::Annotation> {
+    attributes
+        .iter()
+        .filter_map(|attr| match &attr.attribute {
+            Attribute::RuntimeVisibleAnnotations(annotations)
+            | Attribute::RuntimeInvisibleAnnotations(annotations) => Some(&annotations.annotations),
+            _ => None,
+        })
+        .flatten()
+}
+
+fn type_annotations(
+    attributes: &[AttributeInfo],
+) -> impl Iterator<Item = &attribute_info::TypeAnnotation> {
+    attributes
+        .iter()
+        .filter_map(|attr| match &attr.attribute {
+            Attribute::RuntimeVisibleTypeAnnotations(annotations)
+            | Attribute::RuntimeInvisibleTypeAnnotations(annotations) => {
+                Some(&annotations.annotations)
+            }
+            _ => None,
+        })
+        .flatten()
+}
+
+/// The contents of the `Signature` attribute among `attributes`, if there is one.
+fn signature<'a>(file: &'a ClassFile, attributes: &[AttributeInfo]) -> Result<Option<&'a str>> {
+    attributes
+        .iter()
+        .find_map(|attr| match &attr.attribute {
+            Attribute::Signature(signature) => Some(signature.signature_index),
+            _ => None,
+        })
+        .map(|index| file.utf8(index).map_err(|e| e.within("signature_index")))
+        .transpose()
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone)]
+pub struct AttributeInfo {
+    attribute_name_index: u16,
+    attribute: Attribute,
+}
+
+impl AttributeInfo {
+    pub fn name_index(&self) -> u16 {
+        self.attribute_name_index
+    }
+
+    pub fn attribute(&self) -> &Attribute {
+        &self.attribute
+    }
+}
+
+impl StreamRead for AttributeInfo {
+    fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Self> {
+        let name_offset = stream.index;
+        let attribute_name_index = stream.read::<u16>(ctx)?;
+        let len = stream.read::<u32>(ctx)? as usize;
+
+        let name = match ctx.utf8(attribute_name_index) {
+            Some(name) => name,
+            None => {
+                return Err(VmError::class_format(
+                    name_offset,
+                    format!(
+                        "attribute name index {} is not a Utf8 constant",
+                        attribute_name_index
+                    ),
+                ))
+            }
+        };
+        let name: &str = &name;
+
+        // Parse the body from a window ending at the declared length so it can't read past it
+        let start = stream.index;
+        stream.take(len).map_err(|e| e.within(name))?;
+        let mut body_stream = ByteStream {
+            index: start,
+            data: &stream.data[..stream.index],
+        };
+
+        // Attributes are listed as `Variant` or `Variant(Type)` when they share a body type
+        macro_rules! body_type {
+            ($variant:ident) => { attribute_info::$variant };
+            ($variant:ident $ty:ident) => { attribute_info::$ty };
+        }
+
+        macro_rules! read_attribute {
+            ($($variant:ident $(($ty:ident))?),* $(,)?) => {
+                match name {
+                    $(
+                        attribute_info::$variant::TAG => {
+                            <body_type!($variant $($ty)?)>::read(&mut body_stream, ctx)
+                                .map(Attribute::$variant)
+                        }
+                    )*
+                    // Attributes we don't understand must be silently ignored (JVMS 4.7.1),
+                    // but keep their contents around so they aren't lost.
+                    _ => {
+                        let bytes = body_stream.take(len)?.to_vec();
+
+                        Ok(Attribute::Unknown {
+                            name: name.to_string(),
+                            bytes,
+                        })
+                    }
+                }
+            };
+        }
+
+        let code_offset = body_stream.index + 8;
+
+        let attribute = read_attribute!(
+            ConstantValue,
+            Code,
+            StackMapTable,
+            Exceptions,
+            SourceFile,
+            LineNumberTable,
+            LocalVariableTable,
+            LocalVariableTypeTable,
+            InnerClasses,
+            EnclosingMethod,
+            Synthetic,
+            Signature,
+            SourceDebugExtension,
+            Deprecated,
+            RuntimeVisibleAnnotations(Annotations),
+            RuntimeInvisibleAnnotations(Annotations),
+            RuntimeVisibleParameterAnnotations(ParameterAnnotations),
+            RuntimeInvisibleParameterAnnotations(ParameterAnnotations),
+            RuntimeVisibleTypeAnnotations(TypeAnnotations),
+            RuntimeInvisibleTypeAnnotations(TypeAnnotations),
+            AnnotationDefault,
+            BootstrapMethods,
+            MethodParameters,
+            Module,
+            ModulePackages,
+            ModuleMainClass,
+            NestHost,
+            NestMembers,
+            Record,
+            PermittedSubclasses,
+        )
+        .and_then(|attribute| match &attribute {
+            Attribute::Code(code) => code.check_version(ctx, code_offset).map(|_| attribute),
+            _ => Ok(attribute),
+        })
+        .map_err(|e| e.within(name))?;
+
+        if body_stream.has_next() {
+            return Err(VmError::class_format(
+                body_stream.index,
+                format!(
+                    "attribute length is {} but only {} bytes were read",
+                    len,
+                    body_stream.index - start
+                ),
+            )
+            .within(name));
+        }
+
+        Ok(AttributeInfo {
+            attribute_name_index,
+            attribute,
+        })
+    }
+}
+
+impl StreamWrite for AttributeInfo {
+    fn write(&self, buffer: &mut Vec<u8>) {
+        self.attribute_name_index.write(buffer);
+
+        // The length isn't known until the body has been written
+        let len_index = buffer.len();
+        0u32.write(buffer);
+
+        self.attribute.write(buffer);
+
+        let len = (buffer.len() - len_index - 4) as u32;
+        buffer[len_index..len_index + 4].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+impl StreamWrite for Attribute {
+    fn write(&self, buffer: &mut Vec<u8>) {
+        match self {
+            Self::ConstantValue(a) => a.write(buffer),
+            Self::Code(a) => a.write(buffer),
+            Self::StackMapTable(a) => a.write(buffer),
+            Self::Exceptions(a) => a.write(buffer),
+            Self::SourceFile(a) => a.write(buffer),
+            Self::LineNumberTable(a) => a.write(buffer),
+            Self::LocalVariableTable(a) => a.write(buffer),
+            Self::LocalVariableTypeTable(a) => a.write(buffer),
+            Self::InnerClasses(a) => a.write(buffer),
+            Self::EnclosingMethod(a) => a.write(buffer),
+            Self::Synthetic(a) => a.write(buffer),
+            Self::Signature(a) => a.write(buffer),
+            Self::SourceDebugExtension(a) => a.write(buffer),
+            Self::Deprecated(a) => a.write(buffer),
+            Self::RuntimeVisibleAnnotations(a) | Self::RuntimeInvisibleAnnotations(a) => {
+                a.write(buffer)
+            }
+            Self::RuntimeVisibleParameterAnnotations(a)
+            | Self::RuntimeInvisibleParameterAnnotations(a) => a.write(buffer),
+            Self::RuntimeVisibleTypeAnnotations(a) | Self::RuntimeInvisibleTypeAnnotations(a) => {
+                a.write(buffer)
+            }
+            Self::AnnotationDefault(a) => a.write(buffer),
+            Self::BootstrapMethods(a) => a.write(buffer),
+            Self::MethodParameters(a) => a.write(buffer),
+            Self::Module(a) => a.write(buffer),
+            Self::ModulePackages(a) => a.write(buffer),
+            Self::ModuleMainClass(a) => a.write(buffer),
+            Self::NestHost(a) => a.write(buffer),
+            Self::NestMembers(a) => a.write(buffer),
+            Self::Record(a) => a.write(buffer),
+            Self::PermittedSubclasses(a) => a.write(buffer),
+            Self::Unknown { bytes, .. } => buffer.extend_from_slice(bytes),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone)]
 pub enum Attribute {
+    ConstantValue(attribute_info::ConstantValue),
     Code(attribute_info::Code),
-    LineNumberTable(attribute_info::LineNumberTable),
-    SourceFile(attribute_info::SourceFile),
     StackMapTable(attribute_info::StackMapTable),
+    Exceptions(attribute_info::Exceptions),
+    SourceFile(attribute_info::SourceFile),
+    LineNumberTable(attribute_info::LineNumberTable),
+    LocalVariableTable(attribute_info::LocalVariableTable),
+    LocalVariableTypeTable(attribute_info::LocalVariableTypeTable),
+    InnerClasses(attribute_info::InnerClasses),
+    EnclosingMethod(attribute_info::EnclosingMethod),
+    Synthetic(attribute_info::Synthetic),
+    Signature(attribute_info::Signature),
+    SourceDebugExtension(attribute_info::SourceDebugExtension),
+    Deprecated(attribute_info::Deprecated),
+    RuntimeVisibleAnnotations(attribute_info::Annotations),
+    RuntimeInvisibleAnnotations(attribute_info::Annotations),
+    RuntimeVisibleParameterAnnotations(attribute_info::ParameterAnnotations),
+    RuntimeInvisibleParameterAnnotations(attribute_info::ParameterAnnotations),
+    RuntimeVisibleTypeAnnotations(attribute_info::TypeAnnotations),
+    RuntimeInvisibleTypeAnnotations(attribute_info::TypeAnnotations),
+    AnnotationDefault(attribute_info::AnnotationDefault),
+    BootstrapMethods(attribute_info::BootstrapMethods),
+    MethodParameters(attribute_info::MethodParameters),
+    Module(attribute_info::Module),
+    ModulePackages(attribute_info::ModulePackages),
+    ModuleMainClass(attribute_info::ModuleMainClass),
+    NestHost(attribute_info::NestHost),
+    NestMembers(attribute_info::NestMembers),
+    Record(attribute_info::Record),
+    PermittedSubclasses(attribute_info::PermittedSubclasses),
+    /// An attribute not defined by the JVMS, such as those emitted by other JVM languages.
+    Unknown { name: String, bytes: Vec<u8> },
 }
 
 pub mod attribute_info {
-    use jvm_macros::StreamReader;
+    use jvm_macros::{StreamReader, StreamWriter};
+
+    use crate::{
+        byte_stream::{ByteStream, ReaderContext, StreamRead, StreamWrite},
+        error::{Result, VmError},
+        instructions,
+    };
 
-    use crate::byte_stream::StreamRead;
+    use super::{java_version, Attribute, AttributeInfo, ExceptionEntry};
 
-    use super::{AttributeInfo, ExceptionEntry};
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct ConstantValue {
+        pub constantvalue_index: u16,
+    }
 
-    #[derive(Debug, Clone, StreamReader)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
     pub struct Code {
         pub max_stack: u16,
         pub max_locals: u16,
@@ -459,45 +1348,1737 @@ pub mod attribute_info {
         pub attributes: Vec<AttributeInfo>,
     }
 
-    #[derive(Debug, Clone, StreamReader)]
+    impl Code {
+        /// Enforces the rules for code that depend on the class file version (JVMS 4.9.1).
+        ///
+        /// `offset` is where the instructions start in the class file, used to report errors.
+        pub(super) fn check_version(&self, ctx: &ReaderContext, offset: usize) -> Result<()> {
+            if ctx.major_version < 50 {
+                return Ok(());
+            }
+
+            let mut has_branches = !self.exception_table.is_empty();
+            let mut pc = 0;
+
+            while pc < self.instructions.len() {
+                let opcode = self.instructions[pc];
+
+                let Some(len) = instructions::instruction_length(&self.instructions, pc) else {
+                    return Err(VmError::class_format(
+                        offset + pc,
+                        format!("invalid or truncated instruction with opcode 0x{:x}", opcode),
+                    ));
+                };
+
+                let is_jsr = matches!(opcode, 0xa8 | 0xa9 | 0xc9)
+                    || (opcode == 0xc4 && self.instructions[pc + 1] == 0xa9);
+
+                if is_jsr && ctx.major_version >= 51 {
+                    return Err(VmError::class_format(
+                        offset + pc,
+                        format!(
+                            "jsr and ret instructions are not allowed in class file version {} (Java {})",
+                            ctx.major_version,
+                            java_version(ctx.major_version)
+                        ),
+                    ));
+                }
+
+                has_branches |= instructions::is_branch(opcode);
+                pc += len;
+            }
+
+            if has_branches && self.stack_map_table().is_none() {
+                return Err(VmError::class_format(
+                    offset,
+                    format!(
+                        "code with branches requires a StackMapTable in class file version {} (Java {})",
+                        ctx.major_version,
+                        java_version(ctx.major_version)
+                    ),
+                ));
+            }
+
+            Ok(())
+        }
+
+        pub fn stack_map_table(&self) -> Option<&StackMapTable> {
+            self.attributes
+                .iter()
+                .find_map(|attr| match &attr.attribute {
+                    Attribute::StackMapTable(table) => Some(table),
+                    _ => None,
+                })
+        }
+
+        /// Iterates over the stack map frames along with the bytecode offset each applies to.
+        pub fn stack_map_frames(&self) -> impl Iterator<Item = (usize, &StackMapFrame)> {
+            self.stack_map_table()
+                .map(|table| table.frames())
+                .into_iter()
+                .flatten()
+        }
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct StackMapTable {
+        #[many(u16)]
+        pub entries: Vec<StackMapFrame>,
+    }
+
+    impl StackMapTable {
+        /// Iterates over the frames along with the bytecode offset each applies to.
+        ///
+        /// The first frame is at `offset_delta`, and each following frame is at
+        /// `offset_delta + 1` past the previous one (JVMS 4.7.4).
+        pub fn frames(&self) -> impl Iterator<Item = (usize, &StackMapFrame)> {
+            self.entries.iter().scan(None, |previous: &mut Option<usize>, frame| {
+                let offset = match previous {
+                    Some(previous) => *previous + frame.offset_delta() as usize + 1,
+                    None => frame.offset_delta() as usize,
+                };
+
+                *previous = Some(offset);
+                Some((offset, frame))
+            })
+        }
+    }
+
+    /// A single `stack_map_frame`. Each variant corresponds to a range of `frame_type` values.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone)]
+    pub enum StackMapFrame {
+        /// `frame_type` 0-63: same locals as the previous frame and an empty stack.
+        Same { offset_delta: u8 },
+        /// `frame_type` 64-127: same locals as the previous frame and one stack item.
+        SameLocals1StackItem {
+            offset_delta: u8,
+            stack: VerificationType,
+        },
+        /// `frame_type` 247
+        SameLocals1StackItemExtended {
+            offset_delta: u16,
+            stack: VerificationType,
+        },
+        /// `frame_type` 248-250: the last `k` locals are absent and the stack is empty.
+        Chop { k: u8, offset_delta: u16 },
+        /// `frame_type` 251
+        SameExtended { offset_delta: u16 },
+        /// `frame_type` 252-254: `locals` are added to the previous frame's locals.
+        Append {
+            offset_delta: u16,
+            locals: Vec<VerificationType>,
+        },
+        /// `frame_type` 255
+        Full {
+            offset_delta: u16,
+            locals: Vec<VerificationType>,
+            stack: Vec<VerificationType>,
+        },
+    }
+
+    impl StackMapFrame {
+        pub fn offset_delta(&self) -> u16 {
+            match self {
+                Self::Same { offset_delta } | Self::SameLocals1StackItem { offset_delta, .. } => {
+                    *offset_delta as u16
+                }
+                Self::SameLocals1StackItemExtended { offset_delta, .. }
+                | Self::Chop { offset_delta, .. }
+                | Self::SameExtended { offset_delta }
+                | Self::Append { offset_delta, .. }
+                | Self::Full { offset_delta, .. } => *offset_delta,
+            }
+        }
+
+        pub fn frame_type(&self) -> u8 {
+            match self {
+                Self::Same { offset_delta } => *offset_delta,
+                Self::SameLocals1StackItem { offset_delta, .. } => 64 + offset_delta,
+                Self::SameLocals1StackItemExtended { .. } => 247,
+                Self::Chop { k, .. } => 251 - k,
+                Self::SameExtended { .. } => 251,
+                Self::Append { locals, .. } => 251 + locals.len() as u8,
+                Self::Full { .. } => 255,
+            }
+        }
+    }
+
+    impl StreamRead for StackMapFrame {
+        fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Self> {
+            let offset = stream.index;
+            let frame_type = stream.read::<u8>(ctx)?;
+
+            Ok(match frame_type {
+                0..=63 => Self::Same {
+                    offset_delta: frame_type,
+                },
+                64..=127 => Self::SameLocals1StackItem {
+                    offset_delta: frame_type - 64,
+                    stack: stream.read(ctx)?,
+                },
+                247 => Self::SameLocals1StackItemExtended {
+                    offset_delta: stream.read(ctx)?,
+                    stack: stream.read(ctx)?,
+                },
+                248..=250 => Self::Chop {
+                    k: 251 - frame_type,
+                    offset_delta: stream.read(ctx)?,
+                },
+                251 => Self::SameExtended {
+                    offset_delta: stream.read(ctx)?,
+                },
+                252..=254 => Self::Append {
+                    offset_delta: stream.read(ctx)?,
+                    locals: stream
+                        .read_many(frame_type as usize - 251, ctx)
+                        .map_err(|e| e.within("locals"))?,
+                },
+                255 => {
+                    let offset_delta = stream.read(ctx)?;
+
+                    let count = stream.read::<u16>(ctx)?;
+                    let locals = stream
+                        .read_many(count as usize, ctx)
+                        .map_err(|e| e.within("locals"))?;
+
+                    let count = stream.read::<u16>(ctx)?;
+                    let stack = stream
+                        .read_many(count as usize, ctx)
+                        .map_err(|e| e.within("stack"))?;
+
+                    Self::Full {
+                        offset_delta,
+                        locals,
+                        stack,
+                    }
+                }
+                _ => {
+                    return Err(VmError::class_format(
+                        offset,
+                        format!("reserved stack map frame type {}", frame_type),
+                    ))
+                }
+            })
+        }
+    }
+
+    impl StreamWrite for StackMapFrame {
+        fn write(&self, buffer: &mut Vec<u8>) {
+            self.frame_type().write(buffer);
+
+            match self {
+                Self::Same { .. } => (),
+                Self::SameLocals1StackItem { stack, .. } => stack.write(buffer),
+                Self::SameLocals1StackItemExtended {
+                    offset_delta,
+                    stack,
+                } => {
+                    offset_delta.write(buffer);
+                    stack.write(buffer);
+                }
+                Self::Chop { offset_delta, .. } | Self::SameExtended { offset_delta } => {
+                    offset_delta.write(buffer)
+                }
+                Self::Append {
+                    offset_delta,
+                    locals,
+                } => {
+                    offset_delta.write(buffer);
+                    locals.iter().for_each(|local| local.write(buffer));
+                }
+                Self::Full {
+                    offset_delta,
+                    locals,
+                    stack,
+                } => {
+                    offset_delta.write(buffer);
+                    (locals.len() as u16).write(buffer);
+                    locals.iter().for_each(|local| local.write(buffer));
+                    (stack.len() as u16).write(buffer);
+                    stack.iter().for_each(|item| item.write(buffer));
+                }
+            }
+        }
+    }
+
+    /// A `verification_type_info` entry.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum VerificationType {
+        Top,
+        Integer,
+        Float,
+        Double,
+        Long,
+        Null,
+        UninitializedThis,
+        /// An instance of the class at `cpool_index` in the constant pool.
+        Object { cpool_index: u16 },
+        /// An object created by the `new` instruction at `offset` that hasn't been initialized yet.
+        Uninitialized { offset: u16 },
+    }
+
+    impl VerificationType {
+        pub fn tag(&self) -> u8 {
+            match self {
+                Self::Top => 0,
+                Self::Integer => 1,
+                Self::Float => 2,
+                Self::Double => 3,
+                Self::Long => 4,
+                Self::Null => 5,
+                Self::UninitializedThis => 6,
+                Self::Object { .. } => 7,
+                Self::Uninitialized { .. } => 8,
+            }
+        }
+    }
+
+    impl StreamWrite for VerificationType {
+        fn write(&self, buffer: &mut Vec<u8>) {
+            self.tag().write(buffer);
+
+            match self {
+                Self::Object { cpool_index } => cpool_index.write(buffer),
+                Self::Uninitialized { offset } => offset.write(buffer),
+                _ => (),
+            }
+        }
+    }
+
+    impl StreamRead for VerificationType {
+        fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Self> {
+            let offset = stream.index;
+            let tag = stream.read::<u8>(ctx)?;
+
+            Ok(match tag {
+                0 => Self::Top,
+                1 => Self::Integer,
+                2 => Self::Float,
+                3 => Self::Double,
+                4 => Self::Long,
+                5 => Self::Null,
+                6 => Self::UninitializedThis,
+                7 => Self::Object {
+                    cpool_index: stream.read(ctx)?,
+                },
+                8 => Self::Uninitialized {
+                    offset: stream.read(ctx)?,
+                },
+                _ => {
+                    return Err(VmError::class_format(
+                        offset,
+                        format!("unknown verification type tag {}", tag),
+                    ))
+                }
+            })
+        }
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct Exceptions {
+        #[many(u16)]
+        pub exception_index_table: Vec<u16>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct SourceFile {
+        pub source_file_index: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
     pub struct LineNumberTable {
         #[many(u16)]
-        entries: Vec<LineNumber>,
+        pub entries: Vec<LineNumber>,
     }
 
-    #[derive(Debug, Clone, StreamReader)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
     pub struct LineNumber {
-        start_pc: u16,
-        line_number: u16,
+        pub start_pc: u16,
+        pub line_number: u16,
     }
 
-    #[derive(Debug, Clone, StreamReader)]
-    pub struct SourceFile {
-        source_file_index: u16,
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct LocalVariableTable {
+        #[many(u16)]
+        pub entries: Vec<LocalVariable>,
     }
 
-    #[derive(Debug, Clone, StreamReader)]
-    pub struct StackMapTable {
-        // #[many(u16)]
-        // entries: Vec<StackMapFrame>,
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct LocalVariable {
+        pub start_pc: u16,
+        pub length: u16,
+        pub name_index: u16,
+        pub descriptor_index: u16,
+        pub index: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct LocalVariableTypeTable {
+        #[many(u16)]
+        pub entries: Vec<LocalVariableType>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct LocalVariableType {
+        pub start_pc: u16,
+        pub length: u16,
+        pub name_index: u16,
+        pub signature_index: u16,
+        pub index: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct InnerClasses {
+        #[many(u16)]
+        pub classes: Vec<InnerClass>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct InnerClass {
+        pub inner_class_info_index: u16,
+        pub outer_class_info_index: u16,
+        pub inner_name_index: u16,
+        pub inner_class_access_flags: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct EnclosingMethod {
+        pub class_index: u16,
+        pub method_index: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct Synthetic {}
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct Signature {
+        pub signature_index: u16,
+    }
+
+    /// Extended debugging information, which has no meaning to the JVM.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone)]
+    pub struct SourceDebugExtension {
+        pub debug_extension: Vec<u8>,
+    }
+
+    impl StreamRead for SourceDebugExtension {
+        fn read<'a>(stream: &mut ByteStream<'a>, _ctx: &ReaderContext) -> Result<Self> {
+            // The stream only spans the attribute, so the rest of it is the extension
+            let debug_extension = stream.take(stream.data.len() - stream.index)?.to_vec();
+
+            Ok(Self { debug_extension })
+        }
+    }
+
+    impl StreamWrite for SourceDebugExtension {
+        fn write(&self, buffer: &mut Vec<u8>) {
+            buffer.extend_from_slice(&self.debug_extension);
+        }
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct Deprecated {}
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct BootstrapMethods {
+        #[many(u16)]
+        pub bootstrap_methods: Vec<BootstrapMethod>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct BootstrapMethod {
+        pub bootstrap_method_ref: u16,
+        #[many(u16)]
+        pub bootstrap_arguments: Vec<u16>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct MethodParameters {
+        #[many(u8)]
+        pub parameters: Vec<MethodParameter>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct MethodParameter {
+        pub name_index: u16,
+        pub access_flags: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct Module {
+        pub module_name_index: u16,
+        pub module_flags: u16,
+        pub module_version_index: u16,
+
+        #[many(u16)]
+        pub requires: Vec<ModuleRequires>,
+        #[many(u16)]
+        pub exports: Vec<ModuleExports>,
+        #[many(u16)]
+        pub opens: Vec<ModuleOpens>,
+        #[many(u16)]
+        pub uses_index: Vec<u16>,
+        #[many(u16)]
+        pub provides: Vec<ModuleProvides>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct ModuleRequires {
+        pub requires_index: u16,
+        pub requires_flags: u16,
+        pub requires_version_index: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct ModuleExports {
+        pub exports_index: u16,
+        pub exports_flags: u16,
+        #[many(u16)]
+        pub exports_to_index: Vec<u16>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct ModuleOpens {
+        pub opens_index: u16,
+        pub opens_flags: u16,
+        #[many(u16)]
+        pub opens_to_index: Vec<u16>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct ModuleProvides {
+        pub provides_index: u16,
+        #[many(u16)]
+        pub provides_with_index: Vec<u16>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct ModulePackages {
+        #[many(u16)]
+        pub package_index: Vec<u16>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct ModuleMainClass {
+        pub main_class_index: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct NestHost {
+        pub host_class_index: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct NestMembers {
+        #[many(u16)]
+        pub classes: Vec<u16>,
     }
 
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct Record {
+        #[many(u16)]
+        pub components: Vec<RecordComponent>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct RecordComponent {
+        pub name_index: u16,
+        pub descriptor_index: u16,
+        #[many(u16)]
+        pub attributes: Vec<AttributeInfo>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct PermittedSubclasses {
+        #[many(u16)]
+        pub classes: Vec<u16>,
+    }
+
+    /// The contents of `RuntimeVisibleAnnotations` and `RuntimeInvisibleAnnotations`.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct Annotations {
+        #[many(u16)]
+        pub annotations: Vec<Annotation>,
+    }
+
+    /// The contents of `RuntimeVisibleParameterAnnotations` and
+    /// `RuntimeInvisibleParameterAnnotations`.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct ParameterAnnotations {
+        #[many(u8)]
+        pub parameter_annotations: Vec<Annotations>,
+    }
+
+    /// The contents of `RuntimeVisibleTypeAnnotations` and `RuntimeInvisibleTypeAnnotations`.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct TypeAnnotations {
+        #[many(u16)]
+        pub annotations: Vec<TypeAnnotation>,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct AnnotationDefault {
+        pub default_value: ElementValue,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     #[derive(Debug, Clone)]
-    pub struct StackMapFrame {}
+    pub struct Annotation {
+        pub type_index: u16,
+        /// The field descriptor of the annotation interface, such as `Lcom/acme/Foo;`.
+        pub type_descriptor: String,
+        pub elements: Vec<ElementValuePair>,
+    }
 
+    impl Annotation {
+        pub fn element(&self, name: &str) -> Option<&ElementValue> {
+            self.elements
+                .iter()
+                .find(|element| element.name == name)
+                .map(|element| &element.value)
+        }
+    }
+
+    /// How deeply annotations and arrays can nest inside an element value. Reading them is
+    /// recursive, so without a limit a crafted class file could overflow the stack.
+    pub const MAX_ELEMENT_DEPTH: usize = 256;
+
+    impl Annotation {
+        /// Reads an annotation that is nested `depth` levels deep in element values.
+        fn read_nested(stream: &mut ByteStream, ctx: &ReaderContext, depth: usize) -> Result<Self> {
+            let offset = stream.index;
+            let type_index = stream.read(ctx)?;
+            let type_descriptor = utf8(ctx, offset, type_index)?;
+
+            let count = stream.read::<u16>(ctx)?;
+            let elements = (0..count)
+                .map(|i| {
+                    ElementValuePair::read_nested(stream, ctx, depth)
+                        .map_err(|e| e.within(format!("[{i}]")))
+                })
+                .collect::<Result<_>>()
+                .map_err(|e| e.within("elements"))?;
+
+            Ok(Self {
+                type_index,
+                type_descriptor,
+                elements,
+            })
+        }
+    }
+
+    impl StreamRead for Annotation {
+        fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Self> {
+            Self::read_nested(stream, ctx, 0)
+        }
+    }
+
+    impl StreamWrite for Annotation {
+        fn write(&self, buffer: &mut Vec<u8>) {
+            self.type_index.write(buffer);
+            (self.elements.len() as u16).write(buffer);
+            self.elements.iter().for_each(|element| element.write(buffer));
+        }
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone)]
+    pub struct ElementValuePair {
+        pub name_index: u16,
+        pub name: String,
+        pub value: ElementValue,
+    }
+
+    impl ElementValuePair {
+        fn read_nested(stream: &mut ByteStream, ctx: &ReaderContext, depth: usize) -> Result<Self> {
+            let offset = stream.index;
+            let name_index = stream.read(ctx)?;
+            let name = utf8(ctx, offset, name_index)?;
+
+            let value = ElementValue::read_nested(stream, ctx, depth)
+                .map_err(|e| e.within(name.as_str()))?;
+
+            Ok(Self {
+                name_index,
+                name,
+                value,
+            })
+        }
+    }
+
+    impl StreamRead for ElementValuePair {
+        fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Self> {
+            Self::read_nested(stream, ctx, 0)
+        }
+    }
+
+    impl StreamWrite for ElementValuePair {
+        fn write(&self, buffer: &mut Vec<u8>) {
+            self.name_index.write(buffer);
+            self.value.write(buffer);
+        }
+    }
+
+    /// An `element_value`, the value of a single annotation element (JVMS 4.7.16.1).
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone)]
+    pub enum ElementValue {
+        /// A primitive or `String` constant. `tag` is one of `BCDFIJSZs` and
+        /// `const_value_index` points at the matching constant pool entry.
+        Const { tag: u8, const_value_index: u16 },
+        Enum {
+            type_name_index: u16,
+            const_name_index: u16,
+        },
+        Class { class_info_index: u16 },
+        Annotation(Annotation),
+        Array(Vec<ElementValue>),
+    }
+
+    impl ElementValue {
+        pub fn tag(&self) -> u8 {
+            match self {
+                Self::Const { tag, .. } => *tag,
+                Self::Enum { .. } => b'e',
+                Self::Class { .. } => b'c',
+                Self::Annotation(_) => b'@',
+                Self::Array(_) => b'[',
+            }
+        }
+    }
+
+    impl ElementValue {
+        /// Reads an element value inside `depth` enclosing annotations and arrays.
+        fn read_nested(stream: &mut ByteStream, ctx: &ReaderContext, depth: usize) -> Result<Self> {
+            let offset = stream.index;
+            let tag = stream.read::<u8>(ctx)?;
+
+            Ok(match tag {
+                b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' | b's' => Self::Const {
+                    tag,
+                    const_value_index: stream.read(ctx)?,
+                },
+                b'e' => Self::Enum {
+                    type_name_index: stream.read(ctx)?,
+                    const_name_index: stream.read(ctx)?,
+                },
+                b'c' => Self::Class {
+                    class_info_index: stream.read(ctx)?,
+                },
+                b'@' | b'[' if depth >= MAX_ELEMENT_DEPTH => {
+                    return Err(VmError::class_format(
+                        offset,
+                        format!(
+                            "element values nested more than {} levels deep",
+                            MAX_ELEMENT_DEPTH
+                        ),
+                    ))
+                }
+                b'@' => Self::Annotation(Annotation::read_nested(stream, ctx, depth + 1)?),
+                b'[' => {
+                    let count = stream.read::<u16>(ctx)?;
+                    let values = (0..count)
+                        .map(|i| {
+                            Self::read_nested(stream, ctx, depth + 1)
+                                .map_err(|e| e.within(format!("[{i}]")))
+                        })
+                        .collect::<Result<_>>()?;
+
+                    Self::Array(values)
+                }
+                _ => {
+                    return Err(VmError::class_format(
+                        offset,
+                        format!("unknown element value tag '{}'", tag.escape_ascii()),
+                    ))
+                }
+            })
+        }
+    }
+
+    impl StreamRead for ElementValue {
+        fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Self> {
+            Self::read_nested(stream, ctx, 0)
+        }
+    }
+
+    impl StreamWrite for ElementValue {
+        fn write(&self, buffer: &mut Vec<u8>) {
+            self.tag().write(buffer);
+
+            match self {
+                Self::Const {
+                    const_value_index, ..
+                } => const_value_index.write(buffer),
+                Self::Enum {
+                    type_name_index,
+                    const_name_index,
+                } => {
+                    type_name_index.write(buffer);
+                    const_name_index.write(buffer);
+                }
+                Self::Class { class_info_index } => class_info_index.write(buffer),
+                Self::Annotation(annotation) => annotation.write(buffer),
+                Self::Array(values) => {
+                    (values.len() as u16).write(buffer);
+                    values.iter().for_each(|value| value.write(buffer));
+                }
+            }
+        }
+    }
+
+    /// A `type_annotation`, an annotation on a use of a type (JVMS 4.7.20).
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone)]
+    pub struct TypeAnnotation {
+        pub target_type: u8,
+        pub target_info: TargetInfo,
+        /// The part of the target type that is annotated, such as a type argument.
+        pub target_path: Vec<TypePathEntry>,
+        pub annotation: Annotation,
+    }
+
+    impl StreamRead for TypeAnnotation {
+        fn read<'a>(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Self> {
+            let offset = stream.index;
+            let target_type = stream.read::<u8>(ctx)?;
+
+            let target_info = match target_type {
+                0x00 | 0x01 => TargetInfo::TypeParameter {
+                    type_parameter_index: stream.read(ctx)?,
+                },
+                0x10 => TargetInfo::Supertype {
+                    supertype_index: stream.read(ctx)?,
+                },
+                0x11 | 0x12 => TargetInfo::TypeParameterBound {
+                    type_parameter_index: stream.read(ctx)?,
+                    bound_index: stream.read(ctx)?,
+                },
+                0x13..=0x15 => TargetInfo::Empty,
+                0x16 => TargetInfo::FormalParameter {
+                    formal_parameter_index: stream.read(ctx)?,
+                },
+                0x17 => TargetInfo::Throws {
+                    throws_type_index: stream.read(ctx)?,
+                },
+                0x40 | 0x41 => {
+                    let count = stream.read::<u16>(ctx)?;
+                    TargetInfo::LocalVar {
+                        table: stream.read_many(count as usize, ctx)?,
+                    }
+                }
+                0x42 => TargetInfo::Catch {
+                    exception_table_index: stream.read(ctx)?,
+                },
+                0x43..=0x46 => TargetInfo::Offset {
+                    offset: stream.read(ctx)?,
+                },
+                0x47..=0x4B => TargetInfo::TypeArgument {
+                    offset: stream.read(ctx)?,
+                    type_argument_index: stream.read(ctx)?,
+                },
+                _ => {
+                    return Err(VmError::class_format(
+                        offset,
+                        format!("unknown type annotation target type 0x{:x}", target_type),
+                    ))
+                }
+            };
+
+            let count = stream.read::<u8>(ctx)?;
+            let target_path = stream
+                .read_many(count as usize, ctx)
+                .map_err(|e| e.within("target_path"))?;
+
+            let annotation = stream.read(ctx)?;
+
+            Ok(Self {
+                target_type,
+                target_info,
+                target_path,
+                annotation,
+            })
+        }
+    }
+
+    impl StreamWrite for TypeAnnotation {
+        fn write(&self, buffer: &mut Vec<u8>) {
+            self.target_type.write(buffer);
+
+            match &self.target_info {
+                TargetInfo::TypeParameter {
+                    type_parameter_index,
+                } => type_parameter_index.write(buffer),
+                TargetInfo::Supertype { supertype_index } => supertype_index.write(buffer),
+                TargetInfo::TypeParameterBound {
+                    type_parameter_index,
+                    bound_index,
+                } => {
+                    type_parameter_index.write(buffer);
+                    bound_index.write(buffer);
+                }
+                TargetInfo::Empty => (),
+                TargetInfo::FormalParameter {
+                    formal_parameter_index,
+                } => formal_parameter_index.write(buffer),
+                TargetInfo::Throws { throws_type_index } => throws_type_index.write(buffer),
+                TargetInfo::LocalVar { table } => {
+                    (table.len() as u16).write(buffer);
+                    table.iter().for_each(|entry| entry.write(buffer));
+                }
+                TargetInfo::Catch {
+                    exception_table_index,
+                } => exception_table_index.write(buffer),
+                TargetInfo::Offset { offset } => offset.write(buffer),
+                TargetInfo::TypeArgument {
+                    offset,
+                    type_argument_index,
+                } => {
+                    offset.write(buffer);
+                    type_argument_index.write(buffer);
+                }
+            }
+
+            (self.target_path.len() as u8).write(buffer);
+            self.target_path.iter().for_each(|entry| entry.write(buffer));
+
+            self.annotation.write(buffer);
+        }
+    }
+
+    /// The `target_info` of a type annotation, which is determined by its `target_type`.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone)]
+    pub enum TargetInfo {
+        TypeParameter {
+            type_parameter_index: u8,
+        },
+        /// An `extends` or `implements` clause. 65535 is the superclass, otherwise an
+        /// index into `interfaces`.
+        Supertype {
+            supertype_index: u16,
+        },
+        TypeParameterBound {
+            type_parameter_index: u8,
+            bound_index: u8,
+        },
+        /// A field type, method return type, or receiver type.
+        Empty,
+        FormalParameter {
+            formal_parameter_index: u8,
+        },
+        Throws {
+            throws_type_index: u16,
+        },
+        LocalVar {
+            table: Vec<LocalVarTarget>,
+        },
+        Catch {
+            exception_table_index: u16,
+        },
+        /// An `instanceof`, `new`, or method reference expression at `offset`.
+        Offset {
+            offset: u16,
+        },
+        TypeArgument {
+            offset: u16,
+            type_argument_index: u8,
+        },
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct LocalVarTarget {
+        pub start_pc: u16,
+        pub length: u16,
+        pub index: u16,
+    }
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[derive(Debug, Clone, StreamReader, StreamWriter)]
+    pub struct TypePathEntry {
+        pub type_path_kind: u8,
+        pub type_argument_index: u8,
+    }
+
+    pub struct RuntimeVisibleAnnotations;
+    pub struct RuntimeInvisibleAnnotations;
+    pub struct RuntimeVisibleParameterAnnotations;
+    pub struct RuntimeInvisibleParameterAnnotations;
+    pub struct RuntimeVisibleTypeAnnotations;
+    pub struct RuntimeInvisibleTypeAnnotations;
+
+    /// Reads the Utf8 constant at `index`, reporting errors at `offset`.
+    fn utf8(ctx: &ReaderContext, offset: usize, index: u16) -> Result<String> {
+        match ctx.utf8(index) {
+            Some(str) => Ok(str.into_owned()),
+            None => Err(VmError::class_format(
+                offset,
+                format!("index {} is not a Utf8 constant", index),
+            )),
+        }
+    }
+
+    impl_tag!(@str ConstantValue, "ConstantValue");
     impl_tag!(@str Code, "Code");
-    impl_tag!(@str LineNumberTable, "LineNumberTable");
-    impl_tag!(@str SourceFile, "SourceFile");
     impl_tag!(@str StackMapTable, "StackMapTable");
+    impl_tag!(@str Exceptions, "Exceptions");
+    impl_tag!(@str SourceFile, "SourceFile");
+    impl_tag!(@str LineNumberTable, "LineNumberTable");
+    impl_tag!(@str LocalVariableTable, "LocalVariableTable");
+    impl_tag!(@str LocalVariableTypeTable, "LocalVariableTypeTable");
+    impl_tag!(@str InnerClasses, "InnerClasses");
+    impl_tag!(@str EnclosingMethod, "EnclosingMethod");
+    impl_tag!(@str Synthetic, "Synthetic");
+    impl_tag!(@str Signature, "Signature");
+    impl_tag!(@str SourceDebugExtension, "SourceDebugExtension");
+    impl_tag!(@str Deprecated, "Deprecated");
+    impl_tag!(@str RuntimeVisibleAnnotations, "RuntimeVisibleAnnotations");
+    impl_tag!(@str RuntimeInvisibleAnnotations, "RuntimeInvisibleAnnotations");
+    impl_tag!(@str RuntimeVisibleParameterAnnotations, "RuntimeVisibleParameterAnnotations");
+    impl_tag!(@str RuntimeInvisibleParameterAnnotations, "RuntimeInvisibleParameterAnnotations");
+    impl_tag!(@str RuntimeVisibleTypeAnnotations, "RuntimeVisibleTypeAnnotations");
+    impl_tag!(@str RuntimeInvisibleTypeAnnotations, "RuntimeInvisibleTypeAnnotations");
+    impl_tag!(@str AnnotationDefault, "AnnotationDefault");
+    impl_tag!(@str BootstrapMethods, "BootstrapMethods");
+    impl_tag!(@str MethodParameters, "MethodParameters");
+    impl_tag!(@str Module, "Module");
+    impl_tag!(@str ModulePackages, "ModulePackages");
+    impl_tag!(@str ModuleMainClass, "ModuleMainClass");
+    impl_tag!(@str NestHost, "NestHost");
+    impl_tag!(@str NestMembers, "NestMembers");
+    impl_tag!(@str Record, "Record");
+    impl_tag!(@str PermittedSubclasses, "PermittedSubclasses");
 }
 
-#[derive(Debug, Clone, StreamReader)]
+#[derive(Debug, Clone, StreamReader, StreamWriter)]
 pub struct Op(u8);
 
-#[derive(Debug, Clone, StreamReader)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, StreamReader, StreamWriter)]
 pub struct ExceptionEntry {
-    start_pc: u16,
-    end_pc: u16,
-    handler_pc: u16,
-    catch_type: u16,
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    /// The class of exceptions caught, or 0 to catch any exception.
+    pub catch_type: u16,
+}
+
+/// Builds version 52.0 class files for tests, such as hierarchies and members that javac won't
+/// compile. Members are given as `(access_flags, name, descriptor)` and have no attributes.
+#[cfg(test)]
+pub(crate) struct ClassBuilder<'a> {
+    access_flags: u16,
+    name: &'a str,
+    super_class: &'a str,
+    interfaces: &'a [&'a str],
+    fields: &'a [(u16, &'a str, &'a str)],
+    methods: &'a [(u16, &'a str, &'a str)],
+}
+
+#[cfg(test)]
+impl<'a> ClassBuilder<'a> {
+    pub fn new(access_flags: u16, name: &'a str, super_class: &'a str) -> Self {
+        Self {
+            access_flags,
+            name,
+            super_class,
+            interfaces: &[],
+            fields: &[],
+            methods: &[],
+        }
+    }
+
+    pub fn with_interfaces(mut self, interfaces: &'a [&'a str]) -> Self {
+        self.interfaces = interfaces;
+        self
+    }
+
+    pub fn with_fields(mut self, fields: &'a [(u16, &'a str, &'a str)]) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    pub fn with_methods(mut self, methods: &'a [(u16, &'a str, &'a str)]) -> Self {
+        self.methods = methods;
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let class_names: Vec<_> = [self.name, self.super_class]
+            .into_iter()
+            .chain(self.interfaces.iter().copied())
+            .collect();
+        let member_strings = (self.fields.iter().chain(self.methods))
+            .flat_map(|&(_, name, descriptor)| [name, descriptor]);
+
+        // Each class name is followed by its CONSTANT_Class, then come the members' strings
+        let mut constant_pools = vec![ConstantPool::Empty];
+        for &name in &class_names {
+            constant_pools.push(ConstantPool::Utf8(constant_pool::Utf8::new(name)));
+            constant_pools.push(ConstantPool::Class(constant_pool::Class {
+                name_index: constant_pools.len() as u16 - 1,
+            }));
+        }
+        constant_pools.extend(
+            member_strings.map(|string| ConstantPool::Utf8(constant_pool::Utf8::new(string))),
+        );
+
+        let mut next_index = 2 * class_names.len() as u16 + 1;
+        let mut member_indices = || {
+            next_index += 2;
+            (next_index - 2, next_index - 1)
+        };
+        let fields = self
+            .fields
+            .iter()
+            .map(|&(access_flags, _, _)| {
+                let (name_index, descriptor_index) = member_indices();
+                FieldInfo {
+                    access_flags: FieldAccess::from_bits_retain(access_flags),
+                    name_index,
+                    descriptor_index,
+                    attributes: Vec::new(),
+                }
+            })
+            .collect();
+        let methods = self
+            .methods
+            .iter()
+            .map(|&(access_flags, _, _)| {
+                let (name_index, descriptor_index) = member_indices();
+                MethodInfo {
+                    access_flags: MethodAccess::from_bits_retain(access_flags),
+                    name_index,
+                    descriptor_index,
+                    attributes: Vec::new(),
+                }
+            })
+            .collect();
+
+        ClassFile {
+            magic: 0xCAFEBABE,
+            minor_version: 0,
+            major_version: 52,
+            constant_pools,
+            access_flags: ClassAccess::from_bits_retain(self.access_flags),
+            this_class: 2,
+            super_class: 4,
+            interfaces: (0..self.interfaces.len() as u16)
+                .map(|i| 2 * i + 6)
+                .collect(),
+            fields,
+            methods,
+            attributes: Vec::new(),
+        }
+        .write()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use crate::{
+        byte_stream::{ByteStream, ReaderContext, StreamWrite},
+        error::VmError,
+    };
+
+    use super::{
+        attribute_info::{Annotations, ElementValue, StackMapFrame, StackMapTable, VerificationType},
+        constant_pool, Attribute, ClassAccess, ClassBuilder, ClassFile, ClassView, ConstantPool,
+        FieldAccess, MemberKind, MemberRef, MethodAccess, ReadOptions,
+    };
+
+    /// A minimal `public class A` with no members.
+    fn minimal_class() -> Vec<u8> {
+        vec![
+            0xCA, 0xFE, 0xBA, 0xBE, // magic
+            0x00, 0x00, 0x00, 0x34, // version 52.0
+            0x00, 0x03, // constant_pool_count
+            0x01, 0x00, 0x01, b'A', // #1 Utf8 "A"
+            0x07, 0x00, 0x01, // #2 Class #1
+            0x00, 0x21, // access_flags
+            0x00, 0x02, // this_class
+            0x00, 0x00, // super_class
+            0x00, 0x00, // interfaces_count
+            0x00, 0x00, // fields_count
+            0x00, 0x00, // methods_count
+            0x00, 0x00, // attributes_count
+        ]
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let bytes = minimal_class();
+
+        let mut stream = ByteStream::new(&bytes);
+        assert!(ClassFile::read(&mut stream).is_ok());
+
+        for len in 0..bytes.len() {
+            let mut stream = ByteStream::new(&bytes[..len]);
+            assert!(matches!(
+                ClassFile::read(&mut stream),
+                Err(VmError::ClassFormat { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_read_unknown_tag() {
+        let mut bytes = minimal_class();
+        bytes[14] = 0x02;
+
+        let mut stream = ByteStream::new(&bytes);
+        let Err(VmError::ClassFormat {
+            offset, structure, ..
+        }) = ClassFile::read(&mut stream)
+        else {
+            panic!("Expected class format error!");
+        };
+
+        assert_eq!(offset, 14);
+        assert_eq!(structure, "constant_pool[2]");
+    }
+
+    #[test]
+    fn test_read_version() {
+        let with_version = |major: u16, minor: u16| {
+            let mut bytes = minimal_class();
+            bytes[4..6].copy_from_slice(&minor.to_be_bytes());
+            bytes[6..8].copy_from_slice(&major.to_be_bytes());
+            bytes
+        };
+        let read = |bytes: &[u8], enable_preview| {
+            ClassFile::read_with_options(&mut ByteStream::new(bytes), ReadOptions { enable_preview })
+        };
+
+        assert!(read(&with_version(45, 3), false).is_ok());
+        assert!(read(&with_version(65, 0), false).is_ok());
+        assert!(read(&with_version(65, 0xFFFF), true).is_ok());
+
+        let err = read(&with_version(66, 0), false).unwrap_err();
+        assert!(matches!(err, VmError::UnsupportedClassVersion { major: 66, minor: 0 }));
+        assert_eq!(
+            err.to_string(),
+            "Class file compiled for Java 22 (66.0), supported up to Java 21 (65.0)"
+        );
+
+        for (major, minor) in [(44, 0), (65, 0xFFFF), (64, 0xFFFF), (61, 1)] {
+            assert!(matches!(
+                read(&with_version(major, minor), false),
+                Err(VmError::UnsupportedClassVersion { .. })
+            ));
+        }
+
+        let mut bytes = minimal_class();
+        bytes[0] = 0xCB;
+        assert!(matches!(
+            read(&bytes, false),
+            Err(VmError::ClassFormat { offset: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_wide_constants() {
+        let mut bytes = minimal_class();
+        // Prepend a Long to the pool, which shifts the following entries by two
+        bytes[9] = 0x05;
+        bytes.splice(10..10, [0x05, 0, 0, 0, 0, 0, 0, 0, 0x2A]);
+        bytes[10 + 9 + 6] = 0x03; // Class #3
+        bytes[10 + 9 + 10] = 0x04; // this_class #4
+
+        let mut stream = ByteStream::new(&bytes);
+        let file = ClassFile::read(&mut stream).unwrap();
+
+        assert!(matches!(
+            file.pool(1),
+            ConstantPool::Long(constant_pool::LongNumeric { bytes: 42 })
+        ));
+        assert!(matches!(file.pool(2), ConstantPool::Empty));
+        assert_eq!(file.class_name(), "A");
+    }
+
+    #[test]
+    fn test_read_unknown_attribute() {
+        let mut bytes = minimal_class();
+        let len = bytes.len();
+        bytes[len - 1] = 0x01;
+        // An attribute named "A" (#1) with two bytes of content
+        bytes.extend([0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0xAB, 0xCD]);
+
+        let mut stream = ByteStream::new(&bytes);
+        let file = ClassFile::read(&mut stream).unwrap();
+
+        let Attribute::Unknown { name, bytes } = file.attributes()[0].attribute() else {
+            panic!("Expected unknown attribute!");
+        };
+
+        assert_eq!(name, "A");
+        assert_eq!(bytes, &[0xAB, 0xCD]);
+    }
+
+    #[test]
+    fn test_read_stack_map_table() {
+        let bytes = [
+            0x00, 0x04, // number_of_entries
+            0x05, // same
+            0x47, 0x07, 0x00, 0x02, // same_locals_1_stack_item, Object #2
+            0xFD, 0x00, 0x10, 0x01, 0x08, 0x00, 0x03, // append, Integer, Uninitialized(3)
+            0xF9, 0x00, 0x01, // chop 2
+        ];
+
+        let ctx = ReaderContext {
+            constant_pool: Vec::new(),
+            major_version: 0,
+            view: None,
+        };
+        let mut stream = ByteStream::new(&bytes);
+        let table = stream.read::<StackMapTable>(&ctx).unwrap();
+
+        let frames: Vec<_> = table.frames().collect();
+        assert_eq!(frames.iter().map(|(o, _)| *o).collect::<Vec<_>>(), [5, 13, 30, 32]);
+
+        assert!(matches!(
+            frames[1].1,
+            StackMapFrame::SameLocals1StackItem {
+                offset_delta: 7,
+                stack: VerificationType::Object { cpool_index: 2 },
+            }
+        ));
+        assert!(matches!(
+            frames[2].1,
+            StackMapFrame::Append { locals, .. }
+                if locals == &[VerificationType::Integer, VerificationType::Uninitialized { offset: 3 }]
+        ));
+        assert!(matches!(frames[3].1, StackMapFrame::Chop { k: 2, offset_delta: 1 }));
+    }
+
+    #[test]
+    fn test_read_annotations() {
+        let utf8 = |s: &str| {
+            let bytes = [&[0x01, 0x00, s.len() as u8][..], s.as_bytes()].concat();
+            ByteStream::new(&bytes)
+                .read(&ReaderContext {
+                    constant_pool: Vec::new(),
+                    major_version: 0,
+                    view: None,
+                })
+                .unwrap()
+        };
+
+        let ctx = ReaderContext {
+            constant_pool: vec![ConstantPool::Empty, utf8("Lcom/acme/Foo;"), utf8("value")],
+            major_version: 0,
+            view: None,
+        };
+
+        let bytes = [
+            0x00, 0x01, // num_annotations
+            0x00, 0x01, 0x00, 0x01, // @Foo with one element
+            0x00, 0x02, b'[', 0x00, 0x02, // value = [
+            b'I', 0x00, 0x03, //   int constant #3,
+            b'@', 0x00, 0x01, 0x00, 0x00, //   @Foo
+            // ]
+        ];
+
+        let mut stream = ByteStream::new(&bytes);
+        let annotations = stream.read::<Annotations>(&ctx).unwrap();
+        let annotation = &annotations.annotations[0];
+
+        assert_eq!(annotation.type_descriptor, "Lcom/acme/Foo;");
+
+        let Some(ElementValue::Array(values)) = annotation.element("value") else {
+            panic!("Expected array element!");
+        };
+
+        assert!(matches!(
+            values[0],
+            ElementValue::Const {
+                tag: b'I',
+                const_value_index: 3
+            }
+        ));
+        assert!(
+            matches!(&values[1], ElementValue::Annotation(nested) if nested.elements.is_empty())
+        );
+    }
+
+    #[test]
+    fn test_read_nested_element_values() {
+        let ctx = ReaderContext {
+            constant_pool: Vec::new(),
+            major_version: 0,
+            view: None,
+        };
+        // `depth` single-element arrays around an int constant
+        let nested = |depth: usize| {
+            [&b"[\x00\x01".repeat(depth)[..], &[b'I', 0x00, 0x01]].concat()
+        };
+
+        let bytes = nested(super::attribute_info::MAX_ELEMENT_DEPTH);
+        assert!(ByteStream::new(&bytes).read::<ElementValue>(&ctx).is_ok());
+
+        let bytes = nested(super::attribute_info::MAX_ELEMENT_DEPTH + 1);
+        assert!(matches!(
+            ByteStream::new(&bytes).read::<ElementValue>(&ctx),
+            Err(VmError::ClassFormat { .. })
+        ));
+
+        // Deep enough to overflow the stack if reading recursed without a limit
+        let bytes = nested(1_000_000);
+        assert!(matches!(
+            ByteStream::new(&bytes).read::<ElementValue>(&ctx),
+            Err(VmError::ClassFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let bytes = include_bytes!("../../examples/Main.class");
+
+        let mut stream = ByteStream::new(bytes);
+        let file = ClassFile::read(&mut stream).unwrap();
+
+        assert_eq!(file.write(), bytes);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let bytes = include_bytes!("../../examples/Main.class");
+        let file = ClassFile::read(&mut ByteStream::new(bytes)).unwrap();
+
+        let json = serde_json::to_string(&file).unwrap();
+        let file: ClassFile = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(file.write(), bytes);
+    }
+
+    #[test]
+    fn test_check_format() {
+        let bytes = include_bytes!("../../examples/Main.class");
+        let file = ClassFile::read(&mut ByteStream::new(bytes)).unwrap();
+        assert!(file.check_format().is_ok());
+
+        // this_class refers to the Utf8 at #1 instead of the Class at #2
+        let mut bytes = minimal_class();
+        bytes[20] = 0x01;
+
+        let file = ClassFile::read(&mut ByteStream::new(&bytes)).unwrap();
+        match file.check_format() {
+            Err(VmError::FormatCheck { structure, reason }) => {
+                assert_eq!(structure, "this_class");
+                assert_eq!(
+                    reason,
+                    "constant pool index 1 should refer to a CONSTANT_Class but refers to a CONSTANT_Utf8"
+                );
+            }
+            result => panic!("expected a format check error, found {:?}", result),
+        }
+
+        const OBJECT: &str = "java/lang/Object";
+        const CLASS: u16 = 0x0021;
+        const ABSTRACT_CLASS: u16 = 0x0421;
+        const INTERFACE: u16 = 0x0601;
+        const NATIVE: u16 = 0x0100;
+        let class = |access_flags| ClassBuilder::new(access_flags, "A", OBJECT);
+
+        // Fields and methods can share a name as long as their descriptors differ
+        for bytes in [
+            class(CLASS).with_fields(&[(0, "x", "I"), (0, "x", "J")]).build(),
+            class(CLASS).with_methods(&[(NATIVE, "m", "()V"), (NATIVE, "m", "(I)V")]).build(),
+            class(INTERFACE)
+                .with_fields(&[(0x0019, "X", "I")])
+                .with_methods(&[(0x0401, "m", "()V")])
+                .build(),
+        ] {
+            let file = ClassFile::read(&mut ByteStream::new(&bytes)).unwrap();
+            assert!(file.check_format().is_ok(), "{:?}", file.check_format());
+        }
+
+        for (bytes, expected_structure, expected_reason) in [
+            (
+                class(CLASS).with_fields(&[(0, "x", "I"), (0, "x", "I")]).build(),
+                "fields[1]",
+                "duplicate field 'x' with descriptor 'I'",
+            ),
+            (
+                class(CLASS).with_methods(&[(NATIVE, "m", "()V"), (NATIVE, "m", "()V")]).build(),
+                "methods[1]",
+                "duplicate method 'm' with descriptor '()V'",
+            ),
+            (
+                class(CLASS).with_fields(&[(0x0003, "x", "I")]).build(),
+                "fields[0].access_flags",
+                "a field can have at most one of ACC_PUBLIC, ACC_PRIVATE and ACC_PROTECTED",
+            ),
+            (
+                class(CLASS).with_fields(&[(0x0050, "x", "I")]).build(),
+                "fields[0].access_flags",
+                "a field cannot be both ACC_FINAL and ACC_VOLATILE",
+            ),
+            (
+                class(INTERFACE).with_fields(&[(0x0001, "X", "I")]).build(),
+                "fields[0].access_flags",
+                "an interface field must be exactly ACC_PUBLIC, ACC_STATIC and ACC_FINAL",
+            ),
+            (
+                class(ABSTRACT_CLASS).with_methods(&[(0x0408, "m", "()V")]).build(),
+                "methods[0].access_flags",
+                "an abstract method must not be ACC_PRIVATE, ACC_STATIC, ACC_FINAL, \
+                 ACC_SYNCHRONIZED, ACC_NATIVE or ACC_STRICT",
+            ),
+            (
+                class(CLASS).with_methods(&[(0x0106, "m", "()V")]).build(),
+                "methods[0].access_flags",
+                "a method can have at most one of ACC_PUBLIC, ACC_PRIVATE and ACC_PROTECTED",
+            ),
+            (
+                class(0x0431).build(),
+                "access_flags",
+                "a class cannot be both ACC_FINAL and ACC_ABSTRACT",
+            ),
+            (
+                class(0x0201).build(),
+                "access_flags",
+                "an interface must be ACC_ABSTRACT",
+            ),
+            (
+                class(CLASS).with_fields(&[(0, "a;b", "I")]).build(),
+                "fields[0].name_index",
+                "invalid field name 'a;b'",
+            ),
+            (
+                class(CLASS).with_methods(&[(NATIVE, "m<", "()V")]).build(),
+                "methods[0].name_index",
+                "invalid method name 'm<'",
+            ),
+            (
+                class(CLASS).with_fields(&[(0, "x", "Q")]).build(),
+                "fields[0].descriptor_index",
+                "invalid field descriptor 'Q'",
+            ),
+            (
+                class(CLASS).with_methods(&[(NATIVE, "m", "(I")]).build(),
+                "methods[0].descriptor_index",
+                "invalid method descriptor '(I'",
+            ),
+            (
+                ClassBuilder::new(INTERFACE, "A", "B").build(),
+                "super_class",
+                "the superclass of an interface must be java/lang/Object",
+            ),
+        ] {
+            let file = ClassFile::read(&mut ByteStream::new(&bytes)).unwrap();
+            match file.check_format() {
+                Err(VmError::FormatCheck { structure, reason }) => {
+                    assert_eq!(structure, expected_structure);
+                    assert_eq!(reason, expected_reason);
+                }
+                result => panic!("expected '{}', found {:?}", expected_reason, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_access_flags() {
+        let bytes = ClassBuilder::new(0x0421, "A", "java/lang/Object")
+            .with_fields(&[(0x0019, "X", "I"), (0x0042, "y", "J")])
+            .with_methods(&[(0x0109, "m", "()V"), (0x0441, "n", "()V")])
+            .build();
+        let file = ClassFile::read(&mut ByteStream::new(&bytes)).unwrap();
+
+        assert_eq!(
+            file.access_flags(),
+            ClassAccess::PUBLIC | ClassAccess::SUPER | ClassAccess::ABSTRACT
+        );
+        assert!(file.is_abstract());
+        assert!(!file.is_interface());
+        assert!(!file.is_final());
+
+        let [x, y] = file.fields() else {
+            panic!("expected two fields");
+        };
+        assert!(x.is_static() && x.is_final());
+        assert_eq!(y.access_flags(), FieldAccess::PRIVATE | FieldAccess::VOLATILE);
+        assert!(!y.is_static() && !y.is_final());
+
+        // 0x0040 is ACC_VOLATILE on a field but ACC_BRIDGE on a method
+        let [m, n] = file.methods() else {
+            panic!("expected two methods");
+        };
+        assert_eq!(
+            m.access_flags(),
+            MethodAccess::PUBLIC | MethodAccess::STATIC | MethodAccess::NATIVE
+        );
+        assert!(m.is_static() && m.is_native() && !m.is_abstract());
+        assert_eq!(
+            n.access_flags(),
+            MethodAccess::PUBLIC | MethodAccess::BRIDGE | MethodAccess::ABSTRACT
+        );
+        assert!(n.is_abstract() && !n.is_static() && !n.is_native());
+    }
+
+    #[test]
+    fn test_member_ref() {
+        let bytes = include_bytes!("../../examples/Main.class");
+        let file = ClassFile::read(&mut ByteStream::new(bytes)).unwrap();
+
+        assert_eq!(
+            file.member_ref(17).unwrap(),
+            MemberRef {
+                kind: MemberKind::Field,
+                class_name: "Test/Main",
+                name: "i",
+                descriptor: "I",
+            }
+        );
+        assert_eq!(file.member_ref(7).unwrap().kind, MemberKind::Method);
+        assert_eq!(file.class_ref(2).unwrap(), "java/lang/Object");
+
+        match file.member_ref(8) {
+            Err(VmError::FormatCheck { reason, .. }) => assert_eq!(
+                reason,
+                "constant pool index 8 should refer to a CONSTANT_Fieldref, CONSTANT_Methodref or \
+                 CONSTANT_InterfaceMethodref but refers to a CONSTANT_Class"
+            ),
+            result => panic!("expected a format check error, found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_class_view() {
+        let bytes = include_bytes!("../../examples/Main.class");
+        let file = ClassFile::read(&mut ByteStream::new(bytes)).unwrap();
+        let view = ClassView::parse(bytes).unwrap();
+
+        assert!(matches!(view.class_name().unwrap(), Cow::Borrowed("Test/Main")));
+        assert_eq!(view.super_class_name().unwrap().as_deref(), Some("java/lang/Object"));
+
+        let methods: Vec<_> = view.methods().map(|m| m.name().unwrap()).collect();
+        let expected: Vec<_> = file.methods().iter().map(|m| m.name(&file)).collect();
+        assert_eq!(methods, expected);
+
+        let fields: Vec<_> = view
+            .fields()
+            .map(|f| (f.access_flags(), f.name().unwrap(), f.descriptor().unwrap()))
+            .collect();
+        assert_eq!(fields, [(FieldAccess::STATIC, "i".into(), "I".into())]);
+
+        for (method, info) in view.methods().zip(file.methods()) {
+            assert_eq!(method.access_flags(), info.access_flags());
+            assert_eq!(
+                method.descriptor().unwrap(),
+                file.get_str(info.descriptor_index as usize)
+            );
+
+            // Attributes are only decoded when asked for, into the same thing `ClassFile` reads
+            let attributes: Vec<_> = method.attributes().collect();
+            assert_eq!(attributes.len(), info.attributes().len());
+            for (attribute, info) in attributes.iter().zip(info.attributes()) {
+                assert_eq!(attribute.name_index(), info.name_index());
+                assert_eq!(
+                    format!("{:?}", attribute.decode().unwrap()),
+                    format!("{:?}", info.attribute())
+                );
+            }
+            assert!(method.annotations().unwrap().is_empty());
+        }
+
+        let source_file = view
+            .attributes()
+            .find(|a| a.name().unwrap() == "SourceFile")
+            .unwrap();
+        let index = u16::from_be_bytes(source_file.bytes().try_into().unwrap());
+        assert_eq!(view.utf8(index).unwrap(), "Main.java");
+
+        assert_eq!(view.to_class_file().unwrap().write(), bytes);
+    }
+
+    #[test]
+    fn test_write_utf8() {
+        let cases: [&[u8]; 4] = [
+            b"abc",
+            &[b'a', 0xC0, 0x80, b'b'],
+            &[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80],
+            // Lone surrogates and overlong forms are preserved as is
+            &[0xED, 0xA0, 0xBD, 0xC1, 0x81],
+        ];
+
+        let ctx = ReaderContext {
+            constant_pool: Vec::new(),
+            major_version: 0,
+            view: None,
+        };
+
+        for case in cases {
+            let bytes = [&(case.len() as u16).to_be_bytes()[..], case].concat();
+            let utf8 = ByteStream::new(&bytes)
+                .read::<constant_pool::Utf8>(&ctx)
+                .unwrap();
+
+            let mut buffer = Vec::new();
+            utf8.write(&mut buffer);
+            assert_eq!(buffer, bytes);
+        }
+
+        assert_eq!(
+            constant_pool::encode_modified_utf8("a\0\u{1F600}"),
+            [b'a', 0xC0, 0x80, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]
+        );
+    }
+
+    #[test]
+    fn test_decode_modified_utf8() {
+        use constant_pool::decode_modified_utf8;
+
+        assert_eq!(decode_modified_utf8(b"abc").unwrap(), "abc");
+        assert_eq!(decode_modified_utf8(&[b'a', 0xC0, 0x80, b'b']).unwrap(), "a\0b");
+        assert_eq!(
+            decode_modified_utf8(&[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]).unwrap(),
+            "\u{1F600}"
+        );
+        assert_eq!(decode_modified_utf8(&[0xED, 0xA0, 0xBD]).unwrap(), "\u{FFFD}");
+        assert_eq!(decode_modified_utf8(&[b'a', 0x00]), Err(1));
+        assert_eq!(decode_modified_utf8(&[0xF0, 0x9F, 0x98, 0x80]), Err(0));
+    }
 }
@@ -0,0 +1,1162 @@
+//! Format checking of parsed class files (JVMS 4.8).
+//!
+//! Parsing only guarantees that a class file has the right shape. The checks here make sure
+//! its contents are consistent, so that the rest of the VM can follow constant pool indices
+//! and parse names and descriptors without having to handle malformed input.
+
+use std::collections::HashSet;
+
+use crate::error::{Result, VmError};
+
+use super::{
+    attribute_info::{Annotation, ElementValue, StackMapFrame, VerificationType},
+    Attribute, AttributeInfo, ClassAccess, ClassFile, ConstantPool, FieldAccess, FieldInfo,
+    MethodAccess, MethodInfo,
+};
+
+fn error(reason: impl Into<String>) -> VmError {
+    VmError::FormatCheck {
+        structure: String::new(),
+        reason: reason.into(),
+    }
+}
+
+/// The name of the kind of constant pool entry, as used by the JVMS.
+fn kind(entry: &ConstantPool) -> &'static str {
+    match entry {
+        ConstantPool::Empty => "unusable entry",
+        ConstantPool::Class(_) => "CONSTANT_Class",
+        ConstantPool::FieldRef(_) => "CONSTANT_Fieldref",
+        ConstantPool::MethodRef(_) => "CONSTANT_Methodref",
+        ConstantPool::InterfaceMethodRef(_) => "CONSTANT_InterfaceMethodref",
+        ConstantPool::String(_) => "CONSTANT_String",
+        ConstantPool::Integer(_) => "CONSTANT_Integer",
+        ConstantPool::Float(_) => "CONSTANT_Float",
+        ConstantPool::Long(_) => "CONSTANT_Long",
+        ConstantPool::Double(_) => "CONSTANT_Double",
+        ConstantPool::NameAndType(_) => "CONSTANT_NameAndType",
+        ConstantPool::Utf8(_) => "CONSTANT_Utf8",
+        ConstantPool::MethodHandle(_) => "CONSTANT_MethodHandle",
+        ConstantPool::MethodType(_) => "CONSTANT_MethodType",
+        ConstantPool::Dynamic(_) => "CONSTANT_Dynamic",
+        ConstantPool::InvokeDynamic(_) => "CONSTANT_InvokeDynamic",
+        ConstantPool::Module(_) => "CONSTANT_Module",
+        ConstantPool::Package(_) => "CONSTANT_Package",
+    }
+}
+
+/// An unqualified name of a field, local variable or formal parameter (JVMS 4.2.2).
+fn is_unqualified_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['.', ';', '[', '/'])
+}
+
+/// A method name, which additionally can't contain `<` or `>` unless it is one of the
+/// special initialization method names (JVMS 4.2.2).
+fn is_method_name(name: &str) -> bool {
+    name == "<init>"
+        || name == "<clinit>"
+        || (is_unqualified_name(name) && !name.contains(['<', '>']))
+}
+
+/// A class or interface name in its internal form, such as `java/lang/Object` (JVMS 4.2.1).
+fn is_binary_name(name: &str) -> bool {
+    name.split('/').all(is_unqualified_name)
+}
+
+/// The name in a `CONSTANT_Class`, which is either a binary name or an array descriptor.
+fn is_class_name(name: &str) -> bool {
+    if name.starts_with('[') {
+        is_field_descriptor(name)
+    } else {
+        is_binary_name(name)
+    }
+}
+
+/// Parses a single field type at the start of `descriptor`, returning the rest of it.
+fn field_type(descriptor: &str) -> Option<&str> {
+    let element = descriptor.trim_start_matches('[');
+
+    // An array type descriptor is only valid if it represents 255 or fewer dimensions
+    if descriptor.len() - element.len() > 255 {
+        return None;
+    }
+
+    let mut chars = element.chars();
+    match chars.next()? {
+        'B' | 'C' | 'D' | 'F' | 'I' | 'J' | 'S' | 'Z' => Some(chars.as_str()),
+        'L' => {
+            let (name, rest) = chars.as_str().split_once(';')?;
+            is_binary_name(name).then_some(rest)
+        }
+        _ => None,
+    }
+}
+
+/// A field descriptor (JVMS 4.3.2).
+fn is_field_descriptor(descriptor: &str) -> bool {
+    field_type(descriptor) == Some("")
+}
+
+/// Parses a method descriptor (JVMS 4.3.3), returning the number of local variable slots
+/// taken up by its parameters and its return descriptor.
+fn method_descriptor(descriptor: &str) -> Option<(usize, &str)> {
+    let mut rest = descriptor.strip_prefix('(')?;
+    let mut slots = 0;
+
+    while !rest.starts_with(')') {
+        slots += if rest.starts_with(['J', 'D']) { 2 } else { 1 };
+        rest = field_type(rest)?;
+    }
+
+    let ret = &rest[1..];
+    (ret == "V" || is_field_descriptor(ret)).then_some((slots, ret))
+}
+
+fn is_method_descriptor(descriptor: &str) -> bool {
+    method_descriptor(descriptor).is_some()
+}
+
+impl ClassFile {
+    /// Checks that the class file is consistent (JVMS 4.8).
+    ///
+    /// This validates every constant pool index against the kind of entry it must refer to,
+    /// the syntax of names and descriptors, the combinations of access flags and that fields
+    /// and methods are unique. Errors report the path to the offending item.
+    pub fn check_format(&self) -> Result<()> {
+        let checker = Checker { class: self };
+
+        for (i, entry) in self.constant_pools.iter().enumerate() {
+            checker
+                .constant(entry)
+                .map_err(|e| e.within(format!("[{i}]")).within("constant_pool"))?;
+        }
+
+        checker.class()?;
+
+        let mut fields = HashSet::new();
+        for (i, field) in self.fields.iter().enumerate() {
+            checker
+                .field(field, &mut fields)
+                .map_err(|e| e.within(format!("[{i}]")).within("fields"))?;
+        }
+
+        let mut methods = HashSet::new();
+        for (i, method) in self.methods.iter().enumerate() {
+            checker
+                .method(method, &mut methods)
+                .map_err(|e| e.within(format!("[{i}]")).within("methods"))?;
+        }
+
+        checker
+            .attributes(&self.attributes, None)
+            .map_err(|e| e.within("attributes"))
+    }
+}
+
+pub(super) struct Checker<'a> {
+    pub(super) class: &'a ClassFile,
+}
+
+impl<'a> Checker<'a> {
+    pub(super) fn entry(&self, index: u16) -> Result<&'a ConstantPool> {
+        match self.class.constant_pools.get(index as usize) {
+            None | Some(ConstantPool::Empty) => Err(error(format!(
+                "{} is not a valid constant pool index",
+                index
+            ))),
+            Some(entry) => Ok(entry),
+        }
+    }
+
+    pub(super) fn mismatch(index: u16, expected: &str, entry: &ConstantPool) -> VmError {
+        error(format!(
+            "constant pool index {} should refer to a {} but refers to a {}",
+            index,
+            expected,
+            kind(entry)
+        ))
+    }
+
+    pub(super) fn utf8(&self, index: u16) -> Result<&'a str> {
+        match self.entry(index)? {
+            ConstantPool::Utf8(utf8) => Ok(utf8.as_str()),
+            entry => Err(Self::mismatch(index, "CONSTANT_Utf8", entry)),
+        }
+    }
+
+    /// Returns the name of the `CONSTANT_Class` at `index`.
+    pub(super) fn class_ref(&self, index: u16) -> Result<&'a str> {
+        match self.entry(index)? {
+            ConstantPool::Class(class) => self.utf8(class.name_index),
+            entry => Err(Self::mismatch(index, "CONSTANT_Class", entry)),
+        }
+    }
+
+    /// Returns the name and descriptor of the `CONSTANT_NameAndType` at `index`.
+    pub(super) fn name_and_type(&self, index: u16) -> Result<(&'a str, &'a str)> {
+        match self.entry(index)? {
+            ConstantPool::NameAndType(nat) => {
+                Ok((self.utf8(nat.name_index)?, self.utf8(nat.descriptor_index)?))
+            }
+            entry => Err(Self::mismatch(index, "CONSTANT_NameAndType", entry)),
+        }
+    }
+
+    /// Checks an index that may be zero to indicate that the item is absent.
+    fn optional<T>(&self, index: u16, check: impl FnOnce(u16) -> Result<T>) -> Result<Option<T>> {
+        match index {
+            0 => Ok(None),
+            index => check(index).map(Some),
+        }
+    }
+
+    fn expect(
+        &self,
+        index: u16,
+        expected: &str,
+        valid: impl Fn(&ConstantPool) -> bool,
+    ) -> Result<()> {
+        match self.entry(index)? {
+            entry if valid(entry) => Ok(()),
+            entry => Err(Self::mismatch(index, expected, entry)),
+        }
+    }
+
+    fn loadable(&self, index: u16) -> Result<()> {
+        self.expect(index, "loadable constant", |entry| {
+            matches!(
+                entry,
+                ConstantPool::Integer(_)
+                    | ConstantPool::Float(_)
+                    | ConstantPool::Long(_)
+                    | ConstantPool::Double(_)
+                    | ConstantPool::Class(_)
+                    | ConstantPool::String(_)
+                    | ConstantPool::MethodHandle(_)
+                    | ConstantPool::MethodType(_)
+                    | ConstantPool::Dynamic(_)
+            )
+        })
+    }
+
+    fn bootstrap_method_count(&self) -> usize {
+        self.class
+            .attributes
+            .iter()
+            .find_map(|attr| match &attr.attribute {
+                Attribute::BootstrapMethods(methods) => Some(methods.bootstrap_methods.len()),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    fn constant(&self, entry: &ConstantPool) -> Result<()> {
+        match entry {
+            ConstantPool::Class(class) => {
+                let name = self
+                    .utf8(class.name_index)
+                    .map_err(|e| e.within("name_index"))?;
+
+                if !is_class_name(name) {
+                    return Err(error(format!("invalid class name '{}'", name)));
+                }
+            }
+            ConstantPool::FieldRef(member)
+            | ConstantPool::MethodRef(member)
+            | ConstantPool::InterfaceMethodRef(member) => {
+                self.class_ref(member.class_index)
+                    .map_err(|e| e.within("class_index"))?;
+
+                let (name, descriptor) = self
+                    .name_and_type(member.name_and_type_index)
+                    .map_err(|e| e.within("name_and_type_index"))?;
+
+                match entry {
+                    ConstantPool::FieldRef(_) => {
+                        if !is_unqualified_name(name) {
+                            return Err(error(format!("invalid field name '{}'", name)));
+                        }
+
+                        if !is_field_descriptor(descriptor) {
+                            return Err(error(format!(
+                                "invalid field descriptor '{}'",
+                                descriptor
+                            )));
+                        }
+                    }
+                    _ => {
+                        let Some((_, ret)) = method_descriptor(descriptor) else {
+                            return Err(error(format!(
+                                "invalid method descriptor '{}'",
+                                descriptor
+                            )));
+                        };
+
+                        // `<init>` is the only special method that can be referred to, and only
+                        // by a CONSTANT_Methodref that returns void
+                        let init = matches!(entry, ConstantPool::MethodRef(_))
+                            && name == "<init>"
+                            && ret == "V";
+
+                        let named = is_method_name(name) && !name.starts_with('<');
+                        if !init && !named {
+                            return Err(error(format!("invalid method name '{}'", name)));
+                        }
+                    }
+                }
+            }
+            ConstantPool::String(string) => {
+                self.utf8(string.string_index)
+                    .map_err(|e| e.within("string_index"))?;
+            }
+            ConstantPool::NameAndType(nat) => {
+                let name = self
+                    .utf8(nat.name_index)
+                    .map_err(|e| e.within("name_index"))?;
+                if !is_method_name(name) {
+                    return Err(error(format!("invalid name '{}'", name)));
+                }
+
+                let descriptor = self
+                    .utf8(nat.descriptor_index)
+                    .map_err(|e| e.within("descriptor_index"))?;
+                if !is_field_descriptor(descriptor) && !is_method_descriptor(descriptor) {
+                    return Err(error(format!("invalid descriptor '{}'", descriptor)));
+                }
+            }
+            ConstantPool::MethodHandle(handle) => {
+                self.method_handle(handle.reference_kind, handle.reference_index)?
+            }
+            ConstantPool::MethodType(method_type) => {
+                let descriptor = self
+                    .utf8(method_type.descriptor_index)
+                    .map_err(|e| e.within("descriptor_index"))?;
+
+                if !is_method_descriptor(descriptor) {
+                    return Err(error(format!("invalid method descriptor '{}'", descriptor)));
+                }
+            }
+            ConstantPool::Dynamic(dynamic) | ConstantPool::InvokeDynamic(dynamic) => {
+                let count = self.bootstrap_method_count();
+                if dynamic.bootstrap_method_attr_index as usize >= count {
+                    return Err(error(format!(
+                        "bootstrap method {} does not exist, the class has {} bootstrap methods",
+                        dynamic.bootstrap_method_attr_index, count
+                    ))
+                    .within("bootstrap_method_attr_index"));
+                }
+
+                let (name, descriptor) = self
+                    .name_and_type(dynamic.name_and_type_index)
+                    .map_err(|e| e.within("name_and_type_index"))?;
+
+                let valid = match entry {
+                    ConstantPool::Dynamic(_) => is_field_descriptor(descriptor),
+                    _ => is_method_descriptor(descriptor),
+                };
+
+                if !valid {
+                    return Err(error(format!("invalid descriptor '{}'", descriptor)));
+                }
+
+                if !is_method_name(name) || name.starts_with('<') {
+                    return Err(error(format!("invalid name '{}'", name)));
+                }
+            }
+            ConstantPool::Module(module) => {
+                let name = self
+                    .utf8(module.name_index)
+                    .map_err(|e| e.within("name_index"))?;
+                if name.is_empty() {
+                    return Err(error("module name is empty"));
+                }
+            }
+            ConstantPool::Package(package) => {
+                let name = self
+                    .utf8(package.name_index)
+                    .map_err(|e| e.within("name_index"))?;
+                if !is_binary_name(name) {
+                    return Err(error(format!("invalid package name '{}'", name)));
+                }
+            }
+            ConstantPool::Empty
+            | ConstantPool::Integer(_)
+            | ConstantPool::Float(_)
+            | ConstantPool::Long(_)
+            | ConstantPool::Double(_)
+            | ConstantPool::Utf8(_) => (),
+        }
+
+        Ok(())
+    }
+
+    /// Checks a method handle's reference against its kind (JVMS 4.4.8).
+    fn method_handle(&self, kind: u8, index: u16) -> Result<()> {
+        let entry = self.entry(index).map_err(|e| e.within("reference_index"))?;
+
+        let (expected, valid) = match kind {
+            // REF_getField, REF_getStatic, REF_putField, REF_putStatic
+            1..=4 => (
+                "CONSTANT_Fieldref",
+                matches!(entry, ConstantPool::FieldRef(_)),
+            ),
+            // REF_invokeVirtual, REF_newInvokeSpecial
+            5 | 8 => (
+                "CONSTANT_Methodref",
+                matches!(entry, ConstantPool::MethodRef(_)),
+            ),
+            // REF_invokeStatic, REF_invokeSpecial
+            6 | 7 if self.class.major_version < 52 => (
+                "CONSTANT_Methodref",
+                matches!(entry, ConstantPool::MethodRef(_)),
+            ),
+            6 | 7 => (
+                "CONSTANT_Methodref or CONSTANT_InterfaceMethodref",
+                matches!(
+                    entry,
+                    ConstantPool::MethodRef(_) | ConstantPool::InterfaceMethodRef(_)
+                ),
+            ),
+            // REF_invokeInterface
+            9 => (
+                "CONSTANT_InterfaceMethodref",
+                matches!(entry, ConstantPool::InterfaceMethodRef(_)),
+            ),
+            _ => {
+                return Err(
+                    error(format!("invalid method handle kind {}", kind)).within("reference_kind")
+                )
+            }
+        };
+
+        if !valid {
+            return Err(Self::mismatch(index, expected, entry).within("reference_index"));
+        }
+
+        let (ConstantPool::MethodRef(member) | ConstantPool::InterfaceMethodRef(member)) = entry
+        else {
+            return Ok(());
+        };
+
+        let (name, _) = self.name_and_type(member.name_and_type_index)?;
+        if (kind == 8) != (name == "<init>") {
+            return Err(error(format!(
+                "method handle of kind {} cannot refer to method '{}'",
+                kind, name
+            ))
+            .within("reference_index"));
+        }
+
+        Ok(())
+    }
+
+    fn class(&self) -> Result<()> {
+        let class = self.class;
+        let flags = class.access_flags;
+
+        let name = self
+            .class_ref(class.this_class)
+            .map_err(|e| e.within("this_class"))?;
+        if name.starts_with('[') {
+            return Err(
+                error(format!("class cannot be an array type '{}'", name)).within("this_class")
+            );
+        }
+
+        if flags.contains(ClassAccess::MODULE) {
+            if flags.intersection(ClassAccess::all()) != ClassAccess::MODULE {
+                return Err(
+                    error("a module must not have any other access flags").within("access_flags")
+                );
+            }
+
+            return Ok(());
+        }
+
+        if flags.contains(ClassAccess::INTERFACE) {
+            if !flags.contains(ClassAccess::ABSTRACT) {
+                return Err(error("an interface must be ACC_ABSTRACT").within("access_flags"));
+            }
+
+            if flags.intersects(ClassAccess::FINAL | ClassAccess::SUPER | ClassAccess::ENUM) {
+                return Err(
+                    error("an interface must not be ACC_FINAL, ACC_SUPER or ACC_ENUM")
+                        .within("access_flags"),
+                );
+            }
+        } else {
+            if flags.contains(ClassAccess::ANNOTATION) {
+                return Err(error("only an interface can be ACC_ANNOTATION").within("access_flags"));
+            }
+
+            if flags.contains(ClassAccess::FINAL | ClassAccess::ABSTRACT) {
+                return Err(error("a class cannot be both ACC_FINAL and ACC_ABSTRACT")
+                    .within("access_flags"));
+            }
+        }
+
+        match self.optional(class.super_class, |index| self.class_ref(index)) {
+            Ok(None) if name != "java/lang/Object" => {
+                return Err(error("only java/lang/Object has no superclass").within("super_class"))
+            }
+            Ok(Some(super_name)) if super_name.starts_with('[') => {
+                return Err(error(format!(
+                    "superclass cannot be an array type '{}'",
+                    super_name
+                ))
+                .within("super_class"))
+            }
+            Ok(Some(super_name))
+                if flags.contains(ClassAccess::INTERFACE) && super_name != "java/lang/Object" =>
+            {
+                return Err(
+                    error("the superclass of an interface must be java/lang/Object")
+                        .within("super_class"),
+                )
+            }
+            Err(e) => return Err(e.within("super_class")),
+            _ => (),
+        }
+
+        for (i, index) in class.interfaces.iter().enumerate() {
+            let interface = self
+                .class_ref(*index)
+                .map_err(|e| e.within(format!("[{i}]")).within("interfaces"))?;
+
+            if interface.starts_with('[') {
+                return Err(
+                    error(format!("interface cannot be an array type '{}'", interface))
+                        .within(format!("[{i}]"))
+                        .within("interfaces"),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn field(&self, field: &'a FieldInfo, fields: &mut HashSet<(&'a str, &'a str)>) -> Result<()> {
+        let flags = field.access_flags;
+
+        let name = self
+            .utf8(field.name_index)
+            .map_err(|e| e.within("name_index"))?;
+        if !is_unqualified_name(name) {
+            return Err(error(format!("invalid field name '{}'", name)).within("name_index"));
+        }
+
+        let descriptor = self
+            .utf8(field.descriptor_index)
+            .map_err(|e| e.within("descriptor_index"))?;
+        if !is_field_descriptor(descriptor) {
+            return Err(error(format!("invalid field descriptor '{}'", descriptor))
+                .within("descriptor_index"));
+        }
+
+        if !fields.insert((name, descriptor)) {
+            return Err(error(format!(
+                "duplicate field '{}' with descriptor '{}'",
+                name, descriptor
+            )));
+        }
+
+        let visibility = FieldAccess::PUBLIC | FieldAccess::PRIVATE | FieldAccess::PROTECTED;
+        if flags.intersection(visibility).bits().count_ones() > 1 {
+            return Err(error(
+                "a field can have at most one of ACC_PUBLIC, ACC_PRIVATE and ACC_PROTECTED",
+            )
+            .within("access_flags"));
+        }
+
+        if flags.contains(FieldAccess::FINAL | FieldAccess::VOLATILE) {
+            return Err(
+                error("a field cannot be both ACC_FINAL and ACC_VOLATILE").within("access_flags")
+            );
+        }
+
+        if self.class.is_interface() {
+            let required = FieldAccess::PUBLIC | FieldAccess::STATIC | FieldAccess::FINAL;
+
+            if !flags.contains(required)
+                || flags.intersects(FieldAccess::all() - required - FieldAccess::SYNTHETIC)
+            {
+                return Err(error(
+                    "an interface field must be exactly ACC_PUBLIC, ACC_STATIC and ACC_FINAL",
+                )
+                .within("access_flags"));
+            }
+        }
+
+        self.attributes(&field.attributes, Some(descriptor))
+            .map_err(|e| e.within("attributes"))
+    }
+
+    fn method(
+        &self,
+        method: &'a MethodInfo,
+        methods: &mut HashSet<(&'a str, &'a str)>,
+    ) -> Result<()> {
+        let flags = method.access_flags;
+        let major_version = self.class.major_version;
+
+        let name = self
+            .utf8(method.name_index)
+            .map_err(|e| e.within("name_index"))?;
+        if !is_method_name(name) {
+            return Err(error(format!("invalid method name '{}'", name)).within("name_index"));
+        }
+
+        let descriptor = self
+            .utf8(method.descriptor_index)
+            .map_err(|e| e.within("descriptor_index"))?;
+        let Some((slots, ret)) = method_descriptor(descriptor) else {
+            return Err(error(format!("invalid method descriptor '{}'", descriptor))
+                .within("descriptor_index"));
+        };
+
+        if !methods.insert((name, descriptor)) {
+            return Err(error(format!(
+                "duplicate method '{}' with descriptor '{}'",
+                name, descriptor
+            )));
+        }
+
+        // `this` takes up a slot for instance methods
+        if slots + !method.is_static() as usize > 255 {
+            return Err(error("a method can have at most 255 slots of parameters")
+                .within("descriptor_index"));
+        }
+
+        if name == "<clinit>" {
+            if descriptor != "()V" {
+                return Err(
+                    error("a class initialization method must have the descriptor ()V")
+                        .within("descriptor_index"),
+                );
+            }
+
+            // Other flags are ignored for class initialization methods
+            if major_version >= 51 && !method.is_static() {
+                return Err(error("a class initialization method must be ACC_STATIC")
+                    .within("access_flags"));
+            }
+        } else {
+            self.method_access(flags, name, ret)
+                .map_err(|e| e.within("access_flags"))?;
+        }
+
+        let code_count = method
+            .attributes
+            .iter()
+            .filter(|attr| matches!(attr.attribute, Attribute::Code(_)))
+            .count();
+
+        if method.is_native() || method.is_abstract() {
+            if code_count != 0 {
+                return Err(error(
+                    "a native or abstract method must not have a Code attribute",
+                ));
+            }
+        } else if code_count != 1 {
+            return Err(error(format!(
+                "a method must have exactly one Code attribute, found {}",
+                code_count
+            )));
+        }
+
+        self.attributes(&method.attributes, None)
+            .map_err(|e| e.within("attributes"))
+    }
+
+    /// Checks the access flags of any method other than `<clinit>` (JVMS 4.6).
+    fn method_access(&self, flags: MethodAccess, name: &str, ret: &str) -> Result<()> {
+        let major_version = self.class.major_version;
+
+        let visibility = MethodAccess::PUBLIC | MethodAccess::PRIVATE | MethodAccess::PROTECTED;
+        let visibility_count = flags.intersection(visibility).bits().count_ones();
+
+        if visibility_count > 1 {
+            return Err(error(
+                "a method can have at most one of ACC_PUBLIC, ACC_PRIVATE and ACC_PROTECTED",
+            ));
+        }
+
+        if self.class.is_interface() {
+            if major_version < 52 {
+                if !flags.contains(MethodAccess::PUBLIC | MethodAccess::ABSTRACT) {
+                    return Err(error(
+                        "an interface method must be ACC_PUBLIC and ACC_ABSTRACT",
+                    ));
+                }
+            } else if flags.intersects(
+                MethodAccess::PROTECTED
+                    | MethodAccess::FINAL
+                    | MethodAccess::SYNCHRONIZED
+                    | MethodAccess::NATIVE,
+            ) || !flags.intersects(MethodAccess::PUBLIC | MethodAccess::PRIVATE)
+            {
+                return Err(error(
+                    "an interface method must be ACC_PUBLIC or ACC_PRIVATE and must not be \
+                     ACC_PROTECTED, ACC_FINAL, ACC_SYNCHRONIZED or ACC_NATIVE",
+                ));
+            }
+        }
+
+        if flags.contains(MethodAccess::ABSTRACT) {
+            let mut forbidden = MethodAccess::PRIVATE
+                | MethodAccess::STATIC
+                | MethodAccess::FINAL
+                | MethodAccess::SYNCHRONIZED
+                | MethodAccess::NATIVE;
+
+            // ACC_STRICT only has a meaning from Java 1.2 until Java 16
+            if (46..=60).contains(&major_version) {
+                forbidden |= MethodAccess::STRICT;
+            }
+
+            if flags.intersects(forbidden) {
+                return Err(error(
+                    "an abstract method must not be ACC_PRIVATE, ACC_STATIC, ACC_FINAL, \
+                     ACC_SYNCHRONIZED, ACC_NATIVE or ACC_STRICT",
+                ));
+            }
+        }
+
+        if name == "<init>" {
+            let allowed =
+                visibility | MethodAccess::VARARGS | MethodAccess::STRICT | MethodAccess::SYNTHETIC;
+
+            if flags.intersects(MethodAccess::all() - allowed) {
+                return Err(error(
+                    "an instance initialization method can only be ACC_PUBLIC, ACC_PRIVATE, \
+                     ACC_PROTECTED, ACC_VARARGS, ACC_STRICT or ACC_SYNTHETIC",
+                ));
+            }
+
+            if ret != "V" {
+                return Err(error("an instance initialization method must return void"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks a table of attributes. `field_descriptor` is the descriptor of the field that
+    /// owns the attributes, used to check the type of a `ConstantValue`.
+    fn attributes(
+        &self,
+        attributes: &[AttributeInfo],
+        field_descriptor: Option<&str>,
+    ) -> Result<()> {
+        for (i, info) in attributes.iter().enumerate() {
+            let name = self
+                .utf8(info.attribute_name_index)
+                .map_err(|e| e.within("attribute_name_index").within(format!("[{i}]")))?;
+
+            self.attribute(&info.attribute, field_descriptor)
+                .map_err(|e| e.within(name).within(format!("[{i}]")))?;
+        }
+
+        Ok(())
+    }
+
+    fn attribute(&self, attribute: &Attribute, field_descriptor: Option<&str>) -> Result<()> {
+        match attribute {
+            Attribute::ConstantValue(constant) => {
+                let index = constant.constantvalue_index;
+                let entry = self
+                    .entry(index)
+                    .map_err(|e| e.within("constantvalue_index"))?;
+
+                let (expected, valid) = match field_descriptor {
+                    Some("J") => ("CONSTANT_Long", matches!(entry, ConstantPool::Long(_))),
+                    Some("F") => ("CONSTANT_Float", matches!(entry, ConstantPool::Float(_))),
+                    Some("D") => ("CONSTANT_Double", matches!(entry, ConstantPool::Double(_))),
+                    Some("I" | "S" | "C" | "B" | "Z") => (
+                        "CONSTANT_Integer",
+                        matches!(entry, ConstantPool::Integer(_)),
+                    ),
+                    Some("Ljava/lang/String;") => {
+                        ("CONSTANT_String", matches!(entry, ConstantPool::String(_)))
+                    }
+                    _ => (
+                        "constant value",
+                        matches!(
+                            entry,
+                            ConstantPool::Long(_)
+                                | ConstantPool::Float(_)
+                                | ConstantPool::Double(_)
+                                | ConstantPool::Integer(_)
+                                | ConstantPool::String(_)
+                        ),
+                    ),
+                };
+
+                if !valid {
+                    return Err(
+                        Self::mismatch(index, expected, entry).within("constantvalue_index")
+                    );
+                }
+            }
+            Attribute::Code(code) => {
+                for (i, entry) in code.exception_table.iter().enumerate() {
+                    self.optional(entry.catch_type, |index| self.class_ref(index))
+                        .map_err(|e| {
+                            e.within("catch_type")
+                                .within(format!("[{i}]"))
+                                .within("exception_table")
+                        })?;
+                }
+
+                self.attributes(&code.attributes, None)
+                    .map_err(|e| e.within("attributes"))?;
+            }
+            Attribute::StackMapTable(table) => {
+                for (i, frame) in table.entries.iter().enumerate() {
+                    let types: Box<dyn Iterator<Item = &VerificationType>> = match frame {
+                        StackMapFrame::SameLocals1StackItem { stack, .. }
+                        | StackMapFrame::SameLocals1StackItemExtended { stack, .. } => {
+                            Box::new(std::iter::once(stack))
+                        }
+                        StackMapFrame::Append { locals, .. } => Box::new(locals.iter()),
+                        StackMapFrame::Full { locals, stack, .. } => {
+                            Box::new(locals.iter().chain(stack.iter()))
+                        }
+                        _ => Box::new(std::iter::empty()),
+                    };
+
+                    for ty in types {
+                        if let VerificationType::Object { cpool_index } = ty {
+                            self.class_ref(*cpool_index)
+                                .map_err(|e| e.within(format!("[{i}]")).within("entries"))?;
+                        }
+                    }
+                }
+            }
+            Attribute::Exceptions(exceptions) => {
+                self.class_refs(&exceptions.exception_index_table)
+                    .map_err(|e| e.within("exception_index_table"))?;
+            }
+            Attribute::SourceFile(source) => {
+                self.utf8(source.source_file_index)
+                    .map_err(|e| e.within("source_file_index"))?;
+            }
+            Attribute::LocalVariableTable(table) => {
+                for (i, variable) in table.entries.iter().enumerate() {
+                    self.local_variable(variable.name_index, variable.descriptor_index, true)
+                        .map_err(|e| e.within(format!("[{i}]")).within("entries"))?;
+                }
+            }
+            Attribute::LocalVariableTypeTable(table) => {
+                for (i, variable) in table.entries.iter().enumerate() {
+                    self.local_variable(variable.name_index, variable.signature_index, false)
+                        .map_err(|e| e.within(format!("[{i}]")).within("entries"))?;
+                }
+            }
+            Attribute::InnerClasses(inner) => {
+                for (i, class) in inner.classes.iter().enumerate() {
+                    (|| {
+                        self.class_ref(class.inner_class_info_index)
+                            .map_err(|e| e.within("inner_class_info_index"))?;
+                        self.optional(class.outer_class_info_index, |index| self.class_ref(index))
+                            .map_err(|e| e.within("outer_class_info_index"))?;
+                        self.optional(class.inner_name_index, |index| self.utf8(index))
+                            .map_err(|e| e.within("inner_name_index"))?;
+
+                        Ok(())
+                    })()
+                    .map_err(|e: VmError| e.within(format!("[{i}]")).within("classes"))?;
+                }
+            }
+            Attribute::EnclosingMethod(enclosing) => {
+                self.class_ref(enclosing.class_index)
+                    .map_err(|e| e.within("class_index"))?;
+                self.optional(enclosing.method_index, |index| self.name_and_type(index))
+                    .map_err(|e| e.within("method_index"))?;
+            }
+            Attribute::Signature(signature) => {
+                self.utf8(signature.signature_index)
+                    .map_err(|e| e.within("signature_index"))?;
+            }
+            Attribute::RuntimeVisibleAnnotations(annotations)
+            | Attribute::RuntimeInvisibleAnnotations(annotations) => {
+                self.annotations(&annotations.annotations)
+                    .map_err(|e| e.within("annotations"))?;
+            }
+            Attribute::RuntimeVisibleParameterAnnotations(parameters)
+            | Attribute::RuntimeInvisibleParameterAnnotations(parameters) => {
+                for (i, annotations) in parameters.parameter_annotations.iter().enumerate() {
+                    self.annotations(&annotations.annotations)
+                        .map_err(|e| e.within(format!("[{i}]")).within("parameter_annotations"))?;
+                }
+            }
+            Attribute::RuntimeVisibleTypeAnnotations(annotations)
+            | Attribute::RuntimeInvisibleTypeAnnotations(annotations) => {
+                for (i, annotation) in annotations.annotations.iter().enumerate() {
+                    self.annotation(&annotation.annotation)
+                        .map_err(|e| e.within(format!("[{i}]")).within("annotations"))?;
+                }
+            }
+            Attribute::AnnotationDefault(default) => {
+                self.element_value(&default.default_value)
+                    .map_err(|e| e.within("default_value"))?;
+            }
+            Attribute::BootstrapMethods(methods) => {
+                for (i, method) in methods.bootstrap_methods.iter().enumerate() {
+                    (|| {
+                        self.expect(
+                            method.bootstrap_method_ref,
+                            "CONSTANT_MethodHandle",
+                            |entry| matches!(entry, ConstantPool::MethodHandle(_)),
+                        )
+                        .map_err(|e| e.within("bootstrap_method_ref"))?;
+
+                        for (j, argument) in method.bootstrap_arguments.iter().enumerate() {
+                            self.loadable(*argument).map_err(|e| {
+                                e.within(format!("[{j}]")).within("bootstrap_arguments")
+                            })?;
+                        }
+
+                        Ok(())
+                    })()
+                    .map_err(|e: VmError| e.within(format!("[{i}]")).within("bootstrap_methods"))?;
+                }
+            }
+            Attribute::MethodParameters(parameters) => {
+                for (i, parameter) in parameters.parameters.iter().enumerate() {
+                    self.optional(parameter.name_index, |index| self.utf8(index))
+                        .map_err(|e| {
+                            e.within("name_index")
+                                .within(format!("[{i}]"))
+                                .within("parameters")
+                        })?;
+                }
+            }
+            Attribute::Module(module) => {
+                let is_module = |entry: &ConstantPool| matches!(entry, ConstantPool::Module(_));
+                let is_package = |entry: &ConstantPool| matches!(entry, ConstantPool::Package(_));
+
+                self.expect(module.module_name_index, "CONSTANT_Module", is_module)
+                    .map_err(|e| e.within("module_name_index"))?;
+                self.optional(module.module_version_index, |index| self.utf8(index))
+                    .map_err(|e| e.within("module_version_index"))?;
+
+                for (i, requires) in module.requires.iter().enumerate() {
+                    (|| {
+                        self.expect(requires.requires_index, "CONSTANT_Module", is_module)
+                            .map_err(|e| e.within("requires_index"))?;
+                        self.optional(requires.requires_version_index, |index| self.utf8(index))
+                            .map_err(|e| e.within("requires_version_index"))?;
+
+                        Ok(())
+                    })()
+                    .map_err(|e: VmError| e.within(format!("[{i}]")).within("requires"))?;
+                }
+
+                let packages = module
+                    .exports
+                    .iter()
+                    .map(|exports| (exports.exports_index, &exports.exports_to_index, "exports"))
+                    .chain(
+                        module
+                            .opens
+                            .iter()
+                            .map(|opens| (opens.opens_index, &opens.opens_to_index, "opens")),
+                    );
+
+                for (package, to, table) in packages {
+                    self.expect(package, "CONSTANT_Package", is_package)
+                        .map_err(|e| e.within(table))?;
+
+                    for index in to {
+                        self.expect(*index, "CONSTANT_Module", is_module)
+                            .map_err(|e| e.within(table))?;
+                    }
+                }
+
+                self.class_refs(&module.uses_index)
+                    .map_err(|e| e.within("uses_index"))?;
+
+                for (i, provides) in module.provides.iter().enumerate() {
+                    self.class_ref(provides.provides_index)
+                        .and_then(|_| self.class_refs(&provides.provides_with_index))
+                        .map_err(|e| e.within(format!("[{i}]")).within("provides"))?;
+                }
+            }
+            Attribute::ModulePackages(packages) => {
+                for (i, index) in packages.package_index.iter().enumerate() {
+                    self.expect(*index, "CONSTANT_Package", |entry| {
+                        matches!(entry, ConstantPool::Package(_))
+                    })
+                    .map_err(|e| e.within(format!("[{i}]")).within("package_index"))?;
+                }
+            }
+            Attribute::ModuleMainClass(main) => {
+                self.class_ref(main.main_class_index)
+                    .map_err(|e| e.within("main_class_index"))?;
+            }
+            Attribute::NestHost(host) => {
+                self.class_ref(host.host_class_index)
+                    .map_err(|e| e.within("host_class_index"))?;
+            }
+            Attribute::NestMembers(members) => {
+                self.class_refs(&members.classes)
+                    .map_err(|e| e.within("classes"))?;
+            }
+            Attribute::PermittedSubclasses(subclasses) => {
+                self.class_refs(&subclasses.classes)
+                    .map_err(|e| e.within("classes"))?;
+            }
+            Attribute::Record(record) => {
+                for (i, component) in record.components.iter().enumerate() {
+                    (|| {
+                        let name = self
+                            .utf8(component.name_index)
+                            .map_err(|e| e.within("name_index"))?;
+                        if !is_unqualified_name(name) {
+                            return Err(error(format!("invalid record component name '{}'", name))
+                                .within("name_index"));
+                        }
+
+                        let descriptor = self
+                            .utf8(component.descriptor_index)
+                            .map_err(|e| e.within("descriptor_index"))?;
+                        if !is_field_descriptor(descriptor) {
+                            return Err(error(format!(
+                                "invalid field descriptor '{}'",
+                                descriptor
+                            ))
+                            .within("descriptor_index"));
+                        }
+
+                        self.attributes(&component.attributes, None)
+                            .map_err(|e| e.within("attributes"))
+                    })()
+                    .map_err(|e| e.within(format!("[{i}]")).within("components"))?;
+                }
+            }
+            Attribute::Synthetic(_)
+            | Attribute::Deprecated(_)
+            | Attribute::SourceDebugExtension(_)
+            | Attribute::LineNumberTable(_)
+            | Attribute::Unknown { .. } => (),
+        }
+
+        Ok(())
+    }
+
+    fn class_refs(&self, indices: &[u16]) -> Result<()> {
+        for (i, index) in indices.iter().enumerate() {
+            self.class_ref(*index)
+                .map_err(|e| e.within(format!("[{i}]")))?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks a local variable's name and either its descriptor or its signature.
+    fn local_variable(&self, name_index: u16, type_index: u16, is_descriptor: bool) -> Result<()> {
+        let name = self.utf8(name_index).map_err(|e| e.within("name_index"))?;
+        if !is_unqualified_name(name) {
+            return Err(
+                error(format!("invalid local variable name '{}'", name)).within("name_index")
+            );
+        }
+
+        if is_descriptor {
+            let descriptor = self
+                .utf8(type_index)
+                .map_err(|e| e.within("descriptor_index"))?;
+
+            if !is_field_descriptor(descriptor) {
+                return Err(error(format!("invalid field descriptor '{}'", descriptor))
+                    .within("descriptor_index"));
+            }
+        } else {
+            self.utf8(type_index)
+                .map_err(|e| e.within("signature_index"))?;
+        }
+
+        Ok(())
+    }
+
+    fn annotations(&self, annotations: &[Annotation]) -> Result<()> {
+        for (i, annotation) in annotations.iter().enumerate() {
+            self.annotation(annotation)
+                .map_err(|e| e.within(format!("[{i}]")))?;
+        }
+
+        Ok(())
+    }
+
+    fn annotation(&self, annotation: &Annotation) -> Result<()> {
+        if !is_field_descriptor(&annotation.type_descriptor) {
+            return Err(error(format!(
+                "invalid annotation type '{}'",
+                annotation.type_descriptor
+            ))
+            .within("type_index"));
+        }
+
+        for (i, pair) in annotation.elements.iter().enumerate() {
+            self.element_value(&pair.value).map_err(|e| {
+                e.within("value")
+                    .within(format!("[{i}]"))
+                    .within("element_value_pairs")
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn element_value(&self, value: &ElementValue) -> Result<()> {
+        match value {
+            ElementValue::Const {
+                tag,
+                const_value_index,
+            } => {
+                let index = *const_value_index;
+                let entry = self
+                    .entry(index)
+                    .map_err(|e| e.within("const_value_index"))?;
+
+                let (expected, valid) = match tag {
+                    b'B' | b'C' | b'I' | b'S' | b'Z' => (
+                        "CONSTANT_Integer",
+                        matches!(entry, ConstantPool::Integer(_)),
+                    ),
+                    b'D' => ("CONSTANT_Double", matches!(entry, ConstantPool::Double(_))),
+                    b'F' => ("CONSTANT_Float", matches!(entry, ConstantPool::Float(_))),
+                    b'J' => ("CONSTANT_Long", matches!(entry, ConstantPool::Long(_))),
+                    _ => ("CONSTANT_Utf8", matches!(entry, ConstantPool::Utf8(_))),
+                };
+
+                if !valid {
+                    return Err(Self::mismatch(index, expected, entry).within("const_value_index"));
+                }
+            }
+            ElementValue::Enum {
+                type_name_index,
+                const_name_index,
+            } => {
+                self.utf8(*type_name_index)
+                    .map_err(|e| e.within("type_name_index"))?;
+                self.utf8(*const_name_index)
+                    .map_err(|e| e.within("const_name_index"))?;
+            }
+            ElementValue::Class { class_info_index } => {
+                self.utf8(*class_info_index)
+                    .map_err(|e| e.within("class_info_index"))?;
+            }
+            ElementValue::Annotation(annotation) => self.annotation(annotation)?,
+            ElementValue::Array(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    self.element_value(value)
+                        .map_err(|e| e.within(format!("[{i}]")).within("values"))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
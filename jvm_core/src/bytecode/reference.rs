@@ -0,0 +1,303 @@
+//! Typed access to the symbolic references in a class file's constant pool (JVMS 5.1).
+
+use std::fmt::{Display, LowerExp};
+
+use crate::error::{Result, VmError};
+
+use super::{format_check::Checker, ClassFile, ConstantPool};
+
+/// The kind of member a `MemberRef` refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberKind {
+    Field,
+    Method,
+    InterfaceMethod,
+}
+
+/// A symbolic reference to a field or method, from a `CONSTANT_Fieldref`,
+/// `CONSTANT_Methodref` or `CONSTANT_InterfaceMethodref`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemberRef<'a> {
+    pub kind: MemberKind,
+    pub class_name: &'a str,
+    pub name: &'a str,
+    pub descriptor: &'a str,
+}
+
+/// The kind of a method handle, which determines how its member is accessed (JVMS 5.4.3.5).
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReferenceKind {
+    GetField = 1,
+    GetStatic = 2,
+    PutField = 3,
+    PutStatic = 4,
+    InvokeVirtual = 5,
+    InvokeStatic = 6,
+    InvokeSpecial = 7,
+    NewInvokeSpecial = 8,
+    InvokeInterface = 9,
+}
+
+impl ReferenceKind {
+    pub fn from_u8(kind: u8) -> Option<Self> {
+        Some(match kind {
+            1 => Self::GetField,
+            2 => Self::GetStatic,
+            3 => Self::PutField,
+            4 => Self::PutStatic,
+            5 => Self::InvokeVirtual,
+            6 => Self::InvokeStatic,
+            7 => Self::InvokeSpecial,
+            8 => Self::NewInvokeSpecial,
+            9 => Self::InvokeInterface,
+            _ => return None,
+        })
+    }
+}
+
+impl Display for ReferenceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::GetField => "REF_getField",
+            Self::GetStatic => "REF_getStatic",
+            Self::PutField => "REF_putField",
+            Self::PutStatic => "REF_putStatic",
+            Self::InvokeVirtual => "REF_invokeVirtual",
+            Self::InvokeStatic => "REF_invokeStatic",
+            Self::InvokeSpecial => "REF_invokeSpecial",
+            Self::NewInvokeSpecial => "REF_newInvokeSpecial",
+            Self::InvokeInterface => "REF_invokeInterface",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// A symbolic reference to a method handle, from a `CONSTANT_MethodHandle`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MethodHandleRef<'a> {
+    pub kind: ReferenceKind,
+    pub member: MemberRef<'a>,
+}
+
+/// Adds the path to the constant pool entry at `index` to an error.
+fn at(index: u16) -> impl FnOnce(VmError) -> VmError {
+    move |e| e.within(format!("[{index}]")).within("constant_pool")
+}
+
+impl ClassFile {
+    fn checker(&self) -> Checker<'_> {
+        Checker { class: self }
+    }
+
+    /// Returns the Utf8 constant at `index`.
+    pub fn utf8(&self, index: u16) -> Result<&str> {
+        self.checker().utf8(index)
+    }
+
+    /// Returns the name of the class or interface referred to by the `CONSTANT_Class` at `index`.
+    pub fn class_ref(&self, index: u16) -> Result<&str> {
+        self.checker().class_ref(index)
+    }
+
+    /// Returns the name and descriptor of the `CONSTANT_NameAndType` at `index`.
+    pub fn name_and_type(&self, index: u16) -> Result<(&str, &str)> {
+        self.checker().name_and_type(index)
+    }
+
+    /// Returns the field or method referred to at `index`.
+    pub fn member_ref(&self, index: u16) -> Result<MemberRef<'_>> {
+        let checker = self.checker();
+
+        let (kind, member) = match checker.entry(index)? {
+            ConstantPool::FieldRef(member) => (MemberKind::Field, member),
+            ConstantPool::MethodRef(member) => (MemberKind::Method, member),
+            ConstantPool::InterfaceMethodRef(member) => (MemberKind::InterfaceMethod, member),
+            entry => {
+                return Err(Checker::mismatch(
+                    index,
+                    "CONSTANT_Fieldref, CONSTANT_Methodref or CONSTANT_InterfaceMethodref",
+                    entry,
+                ))
+            }
+        };
+
+        let class_name = checker
+            .class_ref(member.class_index)
+            .map_err(|e| e.within("class_index"))
+            .map_err(at(index))?;
+
+        let (name, descriptor) = checker
+            .name_and_type(member.name_and_type_index)
+            .map_err(|e| e.within("name_and_type_index"))
+            .map_err(at(index))?;
+
+        Ok(MemberRef {
+            kind,
+            class_name,
+            name,
+            descriptor,
+        })
+    }
+
+    /// Returns the contents of the `CONSTANT_String` at `index`.
+    pub fn string_constant(&self, index: u16) -> Result<&str> {
+        let checker = self.checker();
+
+        match checker.entry(index)? {
+            ConstantPool::String(string) => checker
+                .utf8(string.string_index)
+                .map_err(|e| e.within("string_index"))
+                .map_err(at(index)),
+            entry => Err(Checker::mismatch(index, "CONSTANT_String", entry)),
+        }
+    }
+
+    /// Returns the method handle at `index`.
+    pub fn method_handle(&self, index: u16) -> Result<MethodHandleRef<'_>> {
+        let checker = self.checker();
+
+        let handle = match checker.entry(index)? {
+            ConstantPool::MethodHandle(handle) => handle,
+            entry => return Err(Checker::mismatch(index, "CONSTANT_MethodHandle", entry)),
+        };
+
+        let kind = ReferenceKind::from_u8(handle.reference_kind).ok_or_else(|| {
+            at(index)(
+                VmError::FormatCheck {
+                    structure: String::new(),
+                    reason: format!("invalid method handle kind {}", handle.reference_kind),
+                }
+                .within("reference_kind"),
+            )
+        })?;
+
+        Ok(MethodHandleRef {
+            kind,
+            member: self.member_ref(handle.reference_index)?,
+        })
+    }
+
+    /// Describes the constant at `index` the way javap does, such as
+    /// `java/lang/Object."<init>":()V` for a method reference or `-1l` for a long.
+    ///
+    /// With `relative` set, members of this class leave out the class name, as javap does in
+    /// disassembled code.
+    pub fn describe_constant(&self, index: u16, relative: bool) -> Result<String> {
+        let checker = self.checker();
+
+        Ok(match checker.entry(index)? {
+            ConstantPool::Empty => unreachable!("entry() rejects empty constants"),
+            ConstantPool::Utf8(utf8) => escape(utf8.as_str()),
+            ConstantPool::Integer(n) => (n.bytes as i32).to_string(),
+            ConstantPool::Float(n) => format!("{}f", java_float(f32::from_bits(n.bytes))),
+            ConstantPool::Long(n) => format!("{}l", n.bytes as i64),
+            ConstantPool::Double(n) => format!("{}d", java_float(f64::from_bits(n.bytes))),
+            ConstantPool::Class(class) => quote_class(checker.utf8(class.name_index)?),
+            ConstantPool::String(string) => escape(checker.utf8(string.string_index)?),
+            ConstantPool::FieldRef(_)
+            | ConstantPool::MethodRef(_)
+            | ConstantPool::InterfaceMethodRef(_) => {
+                let member = self.member_ref(index)?;
+                let name = quote_name(member.name);
+
+                if relative && member.class_name == self.class_ref(self.this_class)? {
+                    format!("{}:{}", name, member.descriptor)
+                } else {
+                    let class_name = quote_class(member.class_name);
+                    format!("{}.{}:{}", class_name, name, member.descriptor)
+                }
+            }
+            ConstantPool::NameAndType(_) => {
+                let (name, descriptor) = checker.name_and_type(index)?;
+                format!("{}:{}", quote_name(name), descriptor)
+            }
+            ConstantPool::MethodHandle(handle) => format!(
+                "{} {}",
+                self.method_handle(index)?.kind,
+                self.describe_constant(handle.reference_index, false)?
+            ),
+            ConstantPool::MethodType(method_type) => {
+                checker.utf8(method_type.descriptor_index)?.to_string()
+            }
+            ConstantPool::Dynamic(dynamic) | ConstantPool::InvokeDynamic(dynamic) => format!(
+                "#{}:{}",
+                dynamic.bootstrap_method_attr_index,
+                self.describe_constant(dynamic.name_and_type_index, false)?
+            ),
+            ConstantPool::Module(module) => checker.utf8(module.name_index)?.to_string(),
+            ConstantPool::Package(package) => checker.utf8(package.name_index)?.to_string(),
+        })
+    }
+}
+
+/// Escapes control characters, quotes and backslashes as they would be in a Java literal.
+fn escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+
+    for c in string.chars() {
+        match c {
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\'' => escaped.push_str("\\'"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Array class names are quoted since they aren't valid Java names.
+fn quote_class(name: &str) -> String {
+    match name.starts_with('[') {
+        true => format!("\"{}\"", name),
+        false => name.to_string(),
+    }
+}
+
+/// Special method names like `<init>` are quoted.
+fn quote_name(name: &str) -> String {
+    match name.starts_with('<') {
+        true => format!("\"{}\"", name),
+        false => name.to_string(),
+    }
+}
+
+/// Formats a floating point number the way Java's `Float.toString` and `Double.toString` do.
+fn java_float<T: Copy + Display + LowerExp + Into<f64>>(value: T) -> String {
+    let number: f64 = value.into();
+
+    if number.is_nan() {
+        return "NaN".to_string();
+    }
+    if number.is_infinite() {
+        let sign = if number < 0.0 { "-" } else { "" };
+        return format!("{}Infinity", sign);
+    }
+
+    // Java switches to scientific notation outside of [10^-3, 10^7)
+    let magnitude = number.abs();
+    if magnitude == 0.0 || (1e-3..1e7).contains(&magnitude) {
+        let decimal = value.to_string();
+
+        return match decimal.contains('.') {
+            true => decimal,
+            false => format!("{}.0", decimal),
+        };
+    }
+
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+
+    match mantissa.contains('.') {
+        true => format!("{}E{}", mantissa, exponent),
+        false => format!("{}.0E{}", mantissa, exponent),
+    }
+}
@@ -0,0 +1,461 @@
+//! A borrowed view of a class file that decodes its contents on demand.
+//!
+//! Parsing a `ClassView` only walks the structure of the class file to find where each
+//! constant, member and attribute starts. Nothing is copied out of the underlying buffer
+//! until it's asked for, which makes scanning many classes for their names, supertypes or
+//! annotations much cheaper than reading a `ClassFile`.
+
+use std::{borrow::Cow, marker::PhantomData};
+
+use bitflags::Flags;
+
+use crate::{
+    byte_stream::{ByteStream, ReaderContext, StreamRead},
+    error::{Result, VmError},
+};
+
+use super::{
+    attribute_info::Annotation, constant_pool, Attribute, AttributeInfo, ClassAccess, ClassFile,
+    ConstantPool, FieldAccess, MethodAccess, ReadOptions, MAGIC,
+};
+
+pub struct ClassView<'a> {
+    data: &'a [u8],
+    options: ReadOptions,
+
+    minor_version: u16,
+    major_version: u16,
+
+    /// The offset of each constant pool entry's tag, or zero for unusable entries.
+    constants: Vec<usize>,
+    access_flags: ClassAccess,
+    this_class: u16,
+    super_class: u16,
+
+    /// The offset of the first interface index.
+    interfaces: usize,
+    interfaces_count: u16,
+    /// The offsets of each `field_info` and `method_info`.
+    fields: Vec<usize>,
+    methods: Vec<usize>,
+    /// The offset of the class's `attributes_count`.
+    attributes: usize,
+}
+
+impl<'a> ClassView<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        Self::parse_with_options(data, ReadOptions::default())
+    }
+
+    /// Walks the structure of the class file, checking that everything lies within `data`.
+    ///
+    /// The header is validated the same way as `ClassFile::read` does, but the contents of
+    /// constants and attributes are only checked once they are decoded.
+    pub fn parse_with_options(data: &'a [u8], options: ReadOptions) -> Result<Self> {
+        let mut stream = ByteStream::new(data);
+        let mut ctx = ReaderContext {
+            constant_pool: Vec::new(),
+            major_version: 0,
+            view: None,
+        };
+
+        let magic = stream.read::<u32>(&ctx).map_err(|e| e.within("magic"))?;
+        if magic != MAGIC {
+            return Err(VmError::class_format(
+                0,
+                format!("bad magic 0x{:X}, expected 0x{:X}", magic, MAGIC),
+            )
+            .within("magic"));
+        }
+
+        let minor_version = stream.read(&ctx).map_err(|e| e.within("minor_version"))?;
+        let major_version = stream.read(&ctx).map_err(|e| e.within("major_version"))?;
+        ClassFile::check_version(major_version, minor_version, options)?;
+        ctx.major_version = major_version;
+
+        let constant_pool_count = stream
+            .read::<u16>(&ctx)
+            .map_err(|e| e.within("constant_pool_count"))?;
+        let constants = Self::scan_constant_pool(&mut stream, &ctx, constant_pool_count)
+            .map_err(|e| e.within("constant_pool"))?;
+
+        let access_flags = stream.read(&ctx).map_err(|e| e.within("access_flags"))?;
+        let this_class = stream.read(&ctx).map_err(|e| e.within("this_class"))?;
+        let super_class = stream.read(&ctx).map_err(|e| e.within("super_class"))?;
+
+        let interfaces_count = stream
+            .read::<u16>(&ctx)
+            .map_err(|e| e.within("interfaces_count"))?;
+        let interfaces = stream.index;
+        stream
+            .take(interfaces_count as usize * 2)
+            .map_err(|e| e.within("interfaces"))?;
+
+        let fields = Self::scan_members(&mut stream, &ctx).map_err(|e| e.within("fields"))?;
+        let methods = Self::scan_members(&mut stream, &ctx).map_err(|e| e.within("methods"))?;
+
+        let attributes = stream.index;
+        Self::scan_attributes(&mut stream, &ctx).map_err(|e| e.within("attributes"))?;
+
+        if stream.has_next() {
+            return Err(VmError::class_format(
+                stream.index,
+                format!(
+                    "{} unexpected trailing bytes after the class file",
+                    data.len() - stream.index
+                ),
+            ));
+        }
+
+        Ok(ClassView {
+            data,
+            options,
+            minor_version,
+            major_version,
+            constants,
+            access_flags,
+            this_class,
+            super_class,
+            interfaces,
+            interfaces_count,
+            fields,
+            methods,
+            attributes,
+        })
+    }
+
+    fn scan_constant_pool(
+        stream: &mut ByteStream<'a>,
+        ctx: &ReaderContext,
+        count: u16,
+    ) -> Result<Vec<usize>> {
+        let mut constants = Vec::with_capacity(count as usize);
+        constants.push(0);
+
+        while constants.len() < count as usize {
+            let index = constants.len();
+            let offset = stream.index;
+
+            // Utf8 constants are skipped over so they are only decoded if they're used. The
+            // other constants are small, so they are read to validate their tag and version.
+            let wide = if stream.data.get(offset) == Some(&constant_pool::Utf8::TAG) {
+                stream.index += 1;
+                let len = stream.read::<u16>(ctx)?;
+                stream.take(len as usize).map(|_| false)
+            } else {
+                stream.read::<ConstantPool>(ctx).map(|constant| {
+                    matches!(constant, ConstantPool::Long(_) | ConstantPool::Double(_))
+                })
+            }
+            .map_err(|e| e.within(format!("[{index}]")))?;
+
+            constants.push(offset);
+
+            if wide {
+                if constants.len() >= count as usize {
+                    return Err(VmError::class_format(
+                        offset,
+                        "8 byte constant has no room for its second slot",
+                    )
+                    .within(format!("[{index}]")));
+                }
+
+                constants.push(0);
+            }
+        }
+
+        Ok(constants)
+    }
+
+    fn scan_members(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<Vec<usize>> {
+        let count = stream.read::<u16>(ctx)?;
+        let mut members = Vec::with_capacity(count as usize);
+
+        for i in 0..count {
+            members.push(stream.index);
+
+            // access_flags, name_index and descriptor_index
+            stream
+                .take(6)
+                .and_then(|_| {
+                    Self::scan_attributes(stream, ctx).map_err(|e| e.within("attributes"))
+                })
+                .map_err(|e| e.within(format!("[{i}]")))?;
+        }
+
+        Ok(members)
+    }
+
+    fn scan_attributes(stream: &mut ByteStream<'a>, ctx: &ReaderContext) -> Result<()> {
+        let count = stream.read::<u16>(ctx)?;
+
+        for i in 0..count {
+            (|| {
+                stream.read::<u16>(ctx)?;
+                let len = stream.read::<u32>(ctx)?;
+                stream.take(len as usize).map(|_| ())
+            })()
+            .map_err(|e| e.within(format!("[{i}]")))?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a u16 at an offset that was checked to be in bounds while parsing.
+    fn u16_at(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.data[offset], self.data[offset + 1]])
+    }
+
+    fn ctx(&self) -> ReaderContext<'_> {
+        ReaderContext {
+            constant_pool: Vec::new(),
+            major_version: self.major_version,
+            view: Some(self),
+        }
+    }
+
+    pub fn major_version(&self) -> u16 {
+        self.major_version
+    }
+
+    pub fn minor_version(&self) -> u16 {
+        self.minor_version
+    }
+
+    pub fn access_flags(&self) -> ClassAccess {
+        self.access_flags
+    }
+
+    /// The bytes of the class file this is a view of.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Decodes the constant pool entry at `index`.
+    pub fn constant(&self, index: u16) -> Result<ConstantPool> {
+        let offset = match self.constants.get(index as usize) {
+            Some(0) | None => {
+                return Err(VmError::class_format(
+                    0,
+                    format!("{} is not a valid constant pool index", index),
+                ))
+            }
+            Some(offset) => *offset,
+        };
+
+        ConstantPool::read(
+            &mut ByteStream {
+                index: offset,
+                data: self.data,
+            },
+            &self.ctx(),
+        )
+        .map_err(|e| e.within(format!("[{index}]")).within("constant_pool"))
+    }
+
+    /// Returns the Utf8 constant at `index`, borrowing it from the class file when its
+    /// modified UTF-8 encoding is also valid UTF-8.
+    pub fn utf8(&self, index: u16) -> Result<Cow<'a, str>> {
+        let offset = self.constants.get(index as usize).copied().unwrap_or(0);
+
+        if offset == 0 || self.data[offset] != constant_pool::Utf8::TAG {
+            return Err(VmError::class_format(
+                offset,
+                format!("index {} is not a Utf8 constant", index),
+            ));
+        }
+
+        let len = self.u16_at(offset + 1) as usize;
+        let bytes = &self.data[offset + 3..offset + 3 + len];
+
+        // NUL and four byte sequences are valid UTF-8 but aren't valid modified UTF-8, while
+        // the encodings only modified UTF-8 uses aren't valid UTF-8
+        if !bytes.iter().any(|b| *b == 0 || *b >= 0xF0) {
+            if let Ok(str) = std::str::from_utf8(bytes) {
+                return Ok(Cow::Borrowed(str));
+            }
+        }
+
+        constant_pool::decode_modified_utf8(bytes)
+            .map(Cow::Owned)
+            .map_err(|i| {
+                VmError::class_format(offset + 3 + i, "malformed modified UTF-8 string")
+                    .within(format!("[{index}]"))
+                    .within("constant_pool")
+            })
+    }
+
+    /// Returns the name of the `CONSTANT_Class` at `index`.
+    pub fn class_ref(&self, index: u16) -> Result<Cow<'a, str>> {
+        match self.constant(index)? {
+            ConstantPool::Class(class) => self.utf8(class.name_index),
+            _ => Err(VmError::class_format(
+                self.constants[index as usize],
+                format!("index {} is not a Class constant", index),
+            )),
+        }
+    }
+
+    pub fn class_name(&self) -> Result<Cow<'a, str>> {
+        self.class_ref(self.this_class)
+            .map_err(|e| e.within("this_class"))
+    }
+
+    /// The name of the superclass, which is only absent for `java/lang/Object`.
+    pub fn super_class_name(&self) -> Result<Option<Cow<'a, str>>> {
+        match self.super_class {
+            0 => Ok(None),
+            index => self
+                .class_ref(index)
+                .map(Some)
+                .map_err(|e| e.within("super_class")),
+        }
+    }
+
+    pub fn interface_names(&self) -> impl Iterator<Item = Result<Cow<'a, str>>> + '_ {
+        (0..self.interfaces_count as usize).map(|i| {
+            self.class_ref(self.u16_at(self.interfaces + i * 2))
+                .map_err(|e| e.within(format!("[{i}]")).within("interfaces"))
+        })
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = MemberView<'_, 'a, FieldAccess>> {
+        self.fields
+            .iter()
+            .map(|offset| MemberView::new(self, *offset))
+    }
+
+    pub fn methods(&self) -> impl Iterator<Item = MemberView<'_, 'a, MethodAccess>> {
+        self.methods
+            .iter()
+            .map(|offset| MemberView::new(self, *offset))
+    }
+
+    pub fn attributes(&self) -> impl Iterator<Item = AttributeView<'_, 'a>> {
+        AttributeView::table(self, self.attributes)
+    }
+
+    /// Both the runtime visible and invisible annotations on this class.
+    pub fn annotations(&self) -> Result<Vec<Annotation>> {
+        annotations(self.attributes())
+    }
+
+    /// Reads the whole class file into its owned form.
+    pub fn to_class_file(&self) -> Result<ClassFile> {
+        let mut stream = ByteStream::new(self.data);
+        ClassFile::read_with_options(&mut stream, self.options)
+    }
+}
+
+/// A field or method, with access flags of type `F`.
+pub struct MemberView<'v, 'a, F> {
+    view: &'v ClassView<'a>,
+    offset: usize,
+    flags: PhantomData<F>,
+}
+
+impl<'v, 'a, F: Flags<Bits = u16>> MemberView<'v, 'a, F> {
+    fn new(view: &'v ClassView<'a>, offset: usize) -> Self {
+        Self {
+            view,
+            offset,
+            flags: PhantomData,
+        }
+    }
+
+    pub fn access_flags(&self) -> F {
+        F::from_bits_retain(self.view.u16_at(self.offset))
+    }
+
+    pub fn name(&self) -> Result<Cow<'a, str>> {
+        self.view.utf8(self.view.u16_at(self.offset + 2))
+    }
+
+    pub fn descriptor(&self) -> Result<Cow<'a, str>> {
+        self.view.utf8(self.view.u16_at(self.offset + 4))
+    }
+
+    pub fn attributes(&self) -> impl Iterator<Item = AttributeView<'v, 'a>> {
+        AttributeView::table(self.view, self.offset + 6)
+    }
+
+    /// Both the runtime visible and invisible annotations on this member.
+    pub fn annotations(&self) -> Result<Vec<Annotation>> {
+        annotations(self.attributes())
+    }
+}
+
+/// An attribute whose body is only decoded when asked for.
+pub struct AttributeView<'v, 'a> {
+    view: &'v ClassView<'a>,
+    offset: usize,
+}
+
+impl<'v, 'a> AttributeView<'v, 'a> {
+    /// Iterates over the attribute table whose `attributes_count` is at `offset`.
+    fn table(view: &'v ClassView<'a>, offset: usize) -> impl Iterator<Item = Self> {
+        let count = view.u16_at(offset);
+
+        (0..count).scan(offset + 2, move |offset, _| {
+            let attribute = AttributeView {
+                view,
+                offset: *offset,
+            };
+            *offset += 6 + attribute.bytes().len();
+
+            Some(attribute)
+        })
+    }
+
+    pub fn name_index(&self) -> u16 {
+        self.view.u16_at(self.offset)
+    }
+
+    pub fn name(&self) -> Result<Cow<'a, str>> {
+        self.view.utf8(self.name_index())
+    }
+
+    /// The undecoded body of the attribute.
+    pub fn bytes(&self) -> &'a [u8] {
+        let data = self.view.data;
+        let len = u32::from_be_bytes([
+            data[self.offset + 2],
+            data[self.offset + 3],
+            data[self.offset + 4],
+            data[self.offset + 5],
+        ]) as usize;
+
+        &data[self.offset + 6..self.offset + 6 + len]
+    }
+
+    pub fn decode(&self) -> Result<Attribute> {
+        let mut stream = ByteStream {
+            index: self.offset,
+            data: self.view.data,
+        };
+
+        AttributeInfo::read(&mut stream, &self.view.ctx()).map(|info| info.attribute)
+    }
+}
+
+fn annotations<'v, 'a: 'v>(
+    attributes: impl Iterator<Item = AttributeView<'v, 'a>>,
+) -> Result<Vec<Annotation>> {
+    let mut annotations = Vec::new();
+
+    for attribute in attributes {
+        let name = attribute.name()?;
+
+        if name == super::attribute_info::RuntimeVisibleAnnotations::TAG
+            || name == super::attribute_info::RuntimeInvisibleAnnotations::TAG
+        {
+            if let Attribute::RuntimeVisibleAnnotations(decoded)
+            | Attribute::RuntimeInvisibleAnnotations(decoded) = attribute.decode()?
+            {
+                annotations.extend(decoded.annotations);
+            }
+        }
+    }
+
+    Ok(annotations)
+}
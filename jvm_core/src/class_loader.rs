@@ -0,0 +1,103 @@
+//! Class loaders, and the identity of the classes they define (JVMS 5.3).
+//!
+//! A class is identified by its name together with the loader that defined it, so two loaders can
+//! each define their own `a/b/C`. Loaders ask their parent for a class before looking for it
+//! themselves, so classes such as `java/lang/Object` are only ever defined once. A user-defined
+//! loader can load classes through a `LoaderDelegate` instead, as Java loaders do in `loadClass`.
+
+use std::{collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{classpath::ClassPath, error::Result, runtime::Runtime};
+
+/// A class loader known to a `Runtime`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LoaderId(pub(crate) usize);
+
+impl LoaderId {
+    /// Loads platform classes such as `java/lang/Object`, and has no parent.
+    pub const BOOTSTRAP: LoaderId = LoaderId(0);
+    /// Loads the rest of the JDK, after asking the bootstrap loader.
+    pub const PLATFORM: LoaderId = LoaderId(1);
+    /// Loads the application from the class path, after asking the platform loader.
+    pub const APPLICATION: LoaderId = LoaderId(2);
+
+    /// The JDK's own loaders, which are the only ones allowed to define classes in `java/*`.
+    pub fn is_builtin(self) -> bool {
+        self == Self::BOOTSTRAP || self == Self::PLATFORM
+    }
+}
+
+/// The runtime identity of a class: its binary name and its defining loader.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClassId {
+    pub loader: LoaderId,
+    pub name: Rc<str>,
+}
+
+impl ClassId {
+    pub fn new(loader: LoaderId, name: impl Into<Rc<str>>) -> Self {
+        Self {
+            loader,
+            name: name.into(),
+        }
+    }
+}
+
+impl Display for ClassId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Loads classes for a user-defined class loader, in place of its Java `loadClass` method. The
+/// interpreter can implement this by calling `loadClass` on the loader's object.
+pub trait LoaderDelegate {
+    /// Finds the class `name` for `loader`, either by defining it with
+    /// `runtime.define_class(loader, ..)` or by asking another loader, such as its parent, with
+    /// `runtime.load_class`. Fails with `ClassNotFound` if there's no such class.
+    fn load_class(&self, runtime: &mut Runtime, loader: LoaderId, name: &str) -> Result<ClassId>;
+}
+
+impl<F> LoaderDelegate for F
+where
+    F: Fn(&mut Runtime, LoaderId, &str) -> Result<ClassId>,
+{
+    fn load_class(&self, runtime: &mut Runtime, loader: LoaderId, name: &str) -> Result<ClassId> {
+        self(runtime, loader, name)
+    }
+}
+
+pub struct ClassLoader {
+    pub name: String,
+    pub parent: Option<LoaderId>,
+    pub class_path: ClassPath,
+    /// Loads classes instead of the parent and `class_path`, for loaders implemented in Java.
+    pub delegate: Option<Rc<dyn LoaderDelegate>>,
+    /// Every class this loader has been the initiating loader of, and the loader that defined
+    /// it. Once a name is here it always resolves to the same class.
+    pub classes: HashMap<Rc<str>, LoaderId>,
+}
+
+impl std::fmt::Debug for ClassLoader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClassLoader")
+            .field("name", &self.name)
+            .field("parent", &self.parent)
+            .field("class_path", &self.class_path)
+            .field("delegate", &self.delegate.is_some())
+            .field("classes", &self.classes.len())
+            .finish()
+    }
+}
+
+impl ClassLoader {
+    pub fn new(name: impl Into<String>, parent: Option<LoaderId>, class_path: ClassPath) -> Self {
+        Self {
+            name: name.into(),
+            parent,
+            class_path,
+            delegate: None,
+            classes: HashMap::new(),
+        }
+    }
+}
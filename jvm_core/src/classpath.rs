@@ -0,0 +1,225 @@
+//! Where the runtime finds class files.
+//!
+//! A `ClassPath` is an ordered list of `ClassSource`s. Looking up a class asks each source in
+//! turn and takes the first match, so earlier entries shadow later ones like `-cp` does.
+
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Result, VmError};
+
+use self::jar::{Archive, JarSource};
+
+pub mod jar;
+pub mod jdk;
+pub mod jimage;
+
+/// Something class files can be loaded from, such as a directory.
+///
+/// Embedders can implement this to load classes from anywhere else, such as a database.
+pub trait ClassSource {
+    /// Reads the class file for the binary name `name`, such as `java/lang/Object`.
+    ///
+    /// Returns `Ok(None)` if this source doesn't have the class, so the next entry on the class
+    /// path is tried. Errors are reserved for classes that exist but couldn't be read.
+    fn find(&self, name: &str) -> Result<Option<Vec<u8>>>;
+}
+
+/// A directory of class files laid out by package, so `a/b/C` is read from `a/b/C.class`.
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ClassSource for DirectorySource {
+    fn find(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        // Names come from constant pools, so don't let them escape the root
+        if name
+            .split('/')
+            .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+        {
+            return Ok(None);
+        }
+
+        let path = self.root.join(format!("{}.class", name));
+
+        match std::fs::read(&path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(VmError::Io {
+                path: path.display().to_string(),
+                reason: e.to_string(),
+            }),
+        }
+    }
+}
+
+/// Class files held in memory, keyed by binary name.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    classes: HashMap<String, Vec<u8>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, data: Vec<u8>) {
+        self.classes.insert(name.into(), data);
+    }
+
+    pub fn with(mut self, name: impl Into<String>, data: Vec<u8>) -> Self {
+        self.insert(name, data);
+        self
+    }
+}
+
+impl ClassSource for MemorySource {
+    fn find(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.classes.get(name).cloned())
+    }
+}
+
+#[derive(Default)]
+pub struct ClassPath {
+    entries: Vec<Box<dyn ClassSource>>,
+}
+
+impl std::fmt::Debug for ClassPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClassPath")
+            .field("entries", &self.entries.len())
+            .finish()
+    }
+}
+
+impl ClassPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a class path in the platform's `-cp` syntax, such as `classes:lib/app.jar` on
+    /// Unix.
+    pub fn parse(class_path: &str) -> Result<Self> {
+        let mut path = Self::new();
+
+        for entry in std::env::split_paths(class_path) {
+            path.push_path(&entry)?;
+        }
+
+        Ok(path)
+    }
+
+    /// The class path `java -jar` uses: the jar itself, followed by the entries in its
+    /// manifest's `Class-Path`, which are relative to the jar's directory.
+    pub fn for_jar(jar: JarSource) -> Result<Self> {
+        let base = jar.path().parent().unwrap_or(Path::new(""));
+        let entries: Vec<_> = jar
+            .manifest()
+            .into_iter()
+            .flat_map(|manifest| manifest.class_path())
+            .map(|entry| base.join(entry))
+            .collect();
+
+        let mut path = Self::new().with(jar);
+        for entry in entries {
+            path.push_path(&entry)?;
+        }
+
+        Ok(path)
+    }
+
+    /// Adds a directory, or a jar or zip file, after the existing entries. Entries that don't
+    /// exist are skipped, as `java` does.
+    pub fn push_path(&mut self, entry: &Path) -> Result<()> {
+        if is_archive(entry) {
+            if entry.is_file() {
+                self.push(JarSource::open(entry)?);
+            }
+        } else if entry.is_dir() {
+            self.push(DirectorySource::new(entry));
+        }
+
+        Ok(())
+    }
+
+    /// Adds a source after the existing ones.
+    pub fn push(&mut self, source: impl ClassSource + 'static) {
+        self.entries.push(Box::new(source));
+    }
+
+    pub fn with(mut self, source: impl ClassSource + 'static) -> Self {
+        self.push(source);
+        self
+    }
+
+    /// Reads the class file for `name` from the first entry that has it.
+    pub fn find(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        for entry in &self.entries {
+            if let Some(data) = entry.find(name)? {
+                return Ok(Some(data));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn is_archive(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("jar") || extension.eq_ignore_ascii_case("zip")
+    })
+}
+
+/// Reads a class file named on the command line. Besides paths, this accepts entries in jars
+/// the way javap does, as `app.jar!/a/b/C.class` or `jar:file:app.jar!/a/b/C.class`.
+pub fn read_class_file(name: &str) -> Result<Vec<u8>> {
+    let not_found = || VmError::ClassNotFound(name.to_string());
+
+    match name.split_once("!/") {
+        Some((jar, entry)) => {
+            let jar = jar.strip_prefix("jar:").unwrap_or(jar);
+            let jar = jar.strip_prefix("file:").unwrap_or(jar);
+
+            Archive::open(jar)?.read(entry)?.ok_or_else(not_found)
+        }
+        None => std::fs::read(name).map_err(|_| not_found()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ClassPath, DirectorySource, MemorySource};
+
+    #[test]
+    fn test_class_path_order() {
+        let root = std::env::temp_dir().join(format!("jvm-rs-classpath-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/b/C.class"), b"directory").unwrap();
+        std::fs::write(root.join("D.class"), b"directory").unwrap();
+
+        let path = ClassPath::new()
+            .with(MemorySource::new().with("D", b"memory".to_vec()))
+            .with(DirectorySource::new(&root));
+
+        assert_eq!(
+            path.find("a/b/C").unwrap().as_deref(),
+            Some(&b"directory"[..])
+        );
+        assert_eq!(path.find("D").unwrap().as_deref(), Some(&b"memory"[..]));
+        assert_eq!(path.find("a/b/E").unwrap(), None);
+        assert_eq!(path.find("a/../D").unwrap(), None);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,577 @@
+//! Jar and zip files on the class path.
+//!
+//! Only the central directory is read when a jar is opened. Entries are read and inflated the
+//! first time they're asked for.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    io::{ErrorKind, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bytecode::MAX_MAJOR_VERSION,
+    error::{Result, VmError},
+};
+
+use super::ClassSource;
+
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+
+/// The extra field holding sizes and offsets that don't fit in the zip headers.
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+
+/// The end of central directory record is 22 bytes, followed by a comment of up to 64 KiB.
+const MAX_END_RECORD: u64 = 22 + u16::MAX as u64;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// A little-endian cursor over zip structures, which returns `None` once it runs out of data.
+struct Reader<'a> {
+    data: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, index: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let data = self.data.get(self.index..self.index.checked_add(len)?)?;
+        self.index += len;
+        Some(data)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    flags: u16,
+    method: u16,
+    compressed_size: u64,
+    size: u64,
+    /// Where the entry's local header starts in the file.
+    header_offset: u64,
+}
+
+/// A zip file whose central directory has been read.
+pub struct Archive {
+    path: PathBuf,
+    file: RefCell<File>,
+    len: u64,
+    entries: HashMap<String, Entry>,
+}
+
+impl std::fmt::Debug for Archive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Archive")
+            .field("path", &self.path)
+            .field("entries", &self.entries.len())
+            .finish()
+    }
+}
+
+impl Archive {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let io_error = |e: std::io::Error| VmError::Io {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        };
+
+        let file = File::open(&path).map_err(io_error)?;
+        let len = file.metadata().map_err(io_error)?.len();
+
+        let mut archive = Self {
+            path,
+            file: RefCell::new(file),
+            len,
+            entries: HashMap::new(),
+        };
+
+        archive.read_central_directory()?;
+        Ok(archive)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The names of every entry, such as `java/lang/Object.class` and `META-INF/`.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Reads and decompresses the entry called `name`.
+    pub fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let Some(entry) = self.entries.get(name) else {
+            return Ok(None);
+        };
+
+        if entry.flags & 1 != 0 {
+            return Err(self.invalid(format!("entry '{}' is encrypted", name)));
+        }
+
+        // The local header has its own copy of the name and extra field, which can differ in
+        // length from those in the central directory
+        let header = self.read_at(entry.header_offset, 30)?;
+        let mut reader = Reader::new(&header);
+        if reader.u32() != Some(LOCAL_FILE_HEADER) {
+            return Err(self.invalid(format!("entry '{}' has no local header", name)));
+        }
+
+        reader.take(22);
+        let (Some(name_len), Some(extra_len)) = (reader.u16(), reader.u16()) else {
+            unreachable!("the header was read in full");
+        };
+
+        let start = entry.header_offset + 30 + name_len as u64 + extra_len as u64;
+        let data = self.read_at(start, entry.compressed_size)?;
+
+        let data = match entry.method {
+            STORED => data,
+            DEFLATED => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(&data, entry.size as usize)
+                    .map_err(|e| {
+                        self.invalid(format!("entry '{}' could not be inflated: {}", name, e))
+                    })?
+            }
+            method => {
+                return Err(self.invalid(format!(
+                    "entry '{}' uses unsupported compression method {}",
+                    name, method
+                )))
+            }
+        };
+
+        if data.len() as u64 != entry.size {
+            return Err(self.invalid(format!(
+                "entry '{}' is {} bytes, but the central directory says {}",
+                name,
+                data.len(),
+                entry.size
+            )));
+        }
+
+        Ok(Some(data))
+    }
+
+    fn read_central_directory(&mut self) -> Result<()> {
+        let tail_len = self.len.min(MAX_END_RECORD);
+        let tail = self.read_at(self.len - tail_len, tail_len)?;
+
+        // The comment can contain anything, so search backwards from the end for the record
+        let end = (0..tail.len().saturating_sub(21))
+            .rev()
+            .find(|&i| tail[i..i + 4] == END_OF_CENTRAL_DIRECTORY.to_le_bytes())
+            .ok_or_else(|| self.invalid("no end of central directory record"))?;
+        let end_offset = self.len - tail_len + end as u64;
+
+        let mut reader = Reader::new(&tail[end + 10..]);
+        let (Some(count), Some(size), Some(offset)) = (reader.u16(), reader.u32(), reader.u32())
+        else {
+            unreachable!("the record is at least 22 bytes");
+        };
+
+        let (count, size, offset, directory_end) =
+            if count == u16::MAX || size == u32::MAX || offset == u32::MAX {
+                self.read_zip64_end(end_offset)?
+            } else {
+                (count as u64, size as u64, offset as u64, end_offset)
+            };
+
+        // Offsets are from the start of the zip data, which can have something in front of it,
+        // such as the header of a jmod file. The central directory ends where the record
+        // starts, which gives the size of anything in front.
+        let base = directory_end
+            .checked_sub(size)
+            .and_then(|start| start.checked_sub(offset))
+            .ok_or_else(|| self.invalid("central directory is outside the file"))?;
+
+        let directory = self.read_at(base + offset, size)?;
+        let mut reader = Reader::new(&directory);
+
+        for _ in 0..count {
+            let (name, entry) = directory_entry(&mut reader, base)
+                .ok_or_else(|| self.invalid("malformed central directory"))?;
+            self.entries.insert(name, entry);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the zip64 end of central directory record, for archives with more than 65535
+    /// entries or more than 4 GiB of data.
+    fn read_zip64_end(&self, end_offset: u64) -> Result<(u64, u64, u64, u64)> {
+        let malformed = || self.invalid("malformed zip64 end of central directory record");
+
+        let locator_offset = end_offset.checked_sub(20).ok_or_else(malformed)?;
+        let locator = self.read_at(locator_offset, 20)?;
+        let mut reader = Reader::new(&locator);
+        if reader.u32() != Some(ZIP64_LOCATOR) {
+            return Err(malformed());
+        }
+
+        reader.take(4);
+        let record_offset = reader.u64().ok_or_else(malformed)?;
+
+        let record = self.read_at(record_offset, 56)?;
+        let mut reader = Reader::new(&record);
+        if reader.u32() != Some(ZIP64_END_OF_CENTRAL_DIRECTORY) {
+            return Err(malformed());
+        }
+
+        reader.take(28);
+        let (Some(count), Some(size), Some(offset)) = (reader.u64(), reader.u64(), reader.u64())
+        else {
+            unreachable!("the record was read in full");
+        };
+
+        Ok((count, size, offset, record_offset))
+    }
+
+    fn read_at(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        // Check against the file size first, so corrupt sizes don't cause huge allocations
+        if offset.checked_add(len).is_none_or(|end| end > self.len) {
+            return Err(self.invalid("unexpected end of file"));
+        }
+
+        let mut data = vec![0; len as usize];
+        let mut file = self.file.borrow_mut();
+
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut data))
+            .map_err(|e| match e.kind() {
+                ErrorKind::UnexpectedEof => self.invalid("unexpected end of file"),
+                _ => VmError::Io {
+                    path: self.path.display().to_string(),
+                    reason: e.to_string(),
+                },
+            })?;
+
+        Ok(data)
+    }
+
+    fn invalid(&self, reason: impl Into<String>) -> VmError {
+        VmError::InvalidJar {
+            path: self.path.display().to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Reads one central directory header, or `None` if it's malformed.
+fn directory_entry(reader: &mut Reader, base: u64) -> Option<(String, Entry)> {
+    if reader.u32()? != CENTRAL_DIRECTORY_HEADER {
+        return None;
+    }
+
+    reader.take(4)?; // version made by, version needed
+    let flags = reader.u16()?;
+    let method = reader.u16()?;
+    reader.take(8)?; // time, date, crc-32
+    let mut compressed_size = reader.u32()? as u64;
+    let mut size = reader.u32()? as u64;
+    let name_len = reader.u16()? as usize;
+    let extra_len = reader.u16()? as usize;
+    let comment_len = reader.u16()? as usize;
+    reader.take(8)?; // disk number, internal and external attributes
+    let mut header_offset = reader.u32()? as u64;
+
+    let name = String::from_utf8_lossy(reader.take(name_len)?).into_owned();
+    let mut extra = Reader::new(reader.take(extra_len)?);
+    reader.take(comment_len)?;
+
+    // Only the values that didn't fit are in the zip64 field, in this order
+    while let (Some(id), Some(len)) = (extra.u16(), extra.u16()) {
+        let mut field = Reader::new(extra.take(len as usize)?);
+        if id != ZIP64_EXTRA_FIELD {
+            continue;
+        }
+
+        if size == u32::MAX as u64 {
+            size = field.u64()?;
+        }
+        if compressed_size == u32::MAX as u64 {
+            compressed_size = field.u64()?;
+        }
+        if header_offset == u32::MAX as u64 {
+            header_offset = field.u64()?;
+        }
+    }
+
+    Some((
+        name,
+        Entry {
+            flags,
+            method,
+            compressed_size,
+            size,
+            header_offset: base + header_offset,
+        },
+    ))
+}
+
+/// The main section of a jar's `META-INF/MANIFEST.MF`.
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    attributes: Vec<(String, String)>,
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Self {
+        let mut attributes: Vec<(String, String)> = Vec::new();
+
+        for line in text.lines() {
+            // A blank line ends the main section, and the per-entry sections aren't needed
+            if line.is_empty() {
+                break;
+            }
+
+            // Long values are wrapped onto lines starting with a space
+            if let Some(continuation) = line.strip_prefix(' ') {
+                if let Some((_, value)) = attributes.last_mut() {
+                    value.push_str(continuation);
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                attributes.push((name.to_string(), value.to_string()));
+            }
+        }
+
+        Self { attributes }
+    }
+
+    /// The value of the main attribute `name`. Names are case insensitive.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn main_class(&self) -> Option<&str> {
+        self.get("Main-Class")
+    }
+
+    /// The jars and directories in `Class-Path`, relative to the jar's directory.
+    pub fn class_path(&self) -> impl Iterator<Item = &str> {
+        self.get("Class-Path")
+            .into_iter()
+            .flat_map(str::split_whitespace)
+    }
+
+    pub fn is_multi_release(&self) -> bool {
+        self.get("Multi-Release")
+            .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+    }
+}
+
+/// A jar or zip file of class files laid out by package.
+///
+/// Multi-release jars (JEP 238) are supported. A class in `META-INF/versions/N/` replaces the
+/// base entry on release `N` and later, and the highest such `N` wins.
+pub struct JarSource {
+    archive: Archive,
+    manifest: Option<Manifest>,
+    /// The versioned directories to search before the base entries, highest first.
+    versions: Vec<u16>,
+    /// Inflated class files, so a class is only decompressed once.
+    cache: RefCell<HashMap<String, Vec<u8>>>,
+}
+
+impl JarSource {
+    /// Opens a jar for the newest release this VM supports.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        Self::open_for_release(path, MAX_MAJOR_VERSION - 44)
+    }
+
+    /// Opens a jar, using the multi-release entries for Java `release`, such as 17.
+    pub fn open_for_release(path: impl Into<PathBuf>, release: u16) -> Result<Self> {
+        let archive = Archive::open(path)?;
+        let manifest = archive
+            .read("META-INF/MANIFEST.MF")?
+            .map(|data| Manifest::parse(&String::from_utf8_lossy(&data)));
+
+        let mut versions = Vec::new();
+        if manifest.as_ref().is_some_and(Manifest::is_multi_release) {
+            versions = archive
+                .names()
+                .filter_map(|name| {
+                    let (version, _) = name.strip_prefix("META-INF/versions/")?.split_once('/')?;
+                    version.parse().ok()
+                })
+                .filter(|version| (9..=release).contains(version))
+                .collect();
+
+            versions.sort_unstable_by(|a, b| b.cmp(a));
+            versions.dedup();
+        }
+
+        Ok(Self {
+            archive,
+            manifest,
+            versions,
+            cache: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.archive.path()
+    }
+
+    pub fn manifest(&self) -> Option<&Manifest> {
+        self.manifest.as_ref()
+    }
+}
+
+impl ClassSource for JarSource {
+    fn find(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        if let Some(data) = self.cache.borrow().get(name) {
+            return Ok(Some(data.clone()));
+        }
+
+        let versioned = self
+            .versions
+            .iter()
+            .map(|version| format!("META-INF/versions/{}/{}.class", version, name));
+
+        for entry in versioned.chain(std::iter::once(format!("{}.class", name))) {
+            if let Some(data) = self.archive.read(&entry)? {
+                self.cache
+                    .borrow_mut()
+                    .insert(name.to_string(), data.clone());
+                return Ok(Some(data));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::classpath::ClassSource;
+
+    use super::{JarSource, Manifest};
+
+    /// Builds a zip file with the entries stored, or deflated if `deflate` is set.
+    fn zip(entries: &[(&str, &[u8])], deflate: bool) -> Vec<u8> {
+        let mut data = vec![b'J', b'M', 1, 0]; // like a jmod, to check offsets are relative
+        let mut directory = Vec::new();
+
+        for (name, contents) in entries {
+            let offset = data.len() as u32 - 4;
+            let (method, compressed) = if deflate {
+                (8u16, miniz_oxide::deflate::compress_to_vec(contents, 6))
+            } else {
+                (0u16, contents.to_vec())
+            };
+
+            let mut header = Vec::new();
+            header.extend_from_slice(&[0; 4]); // version needed, flags
+            header.extend_from_slice(&method.to_le_bytes());
+            header.extend_from_slice(&[0; 8]); // time, date, crc-32
+            header.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes()); // extra length
+
+            data.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+            data.extend_from_slice(&header);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&compressed);
+
+            directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            directory.extend_from_slice(&[0; 2]); // version made by
+            directory.extend_from_slice(&header);
+            directory.extend_from_slice(&[0; 10]); // comment, disk, attributes
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let offset = data.len() as u32 - 4;
+        data.extend_from_slice(&directory);
+        data.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        data.extend_from_slice(&[0; 4]); // disk numbers
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        data.extend_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(&[0; 2]); // comment length
+
+        data
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = Manifest::parse(
+            "Manifest-Version: 1.0\r\nmain-class: com.acme.\r\n Main\r\nClass-Path: lib/a.jar  b/\r\n\r\nName: x\r\nMain-Class: no\r\n",
+        );
+
+        assert_eq!(manifest.main_class(), Some("com.acme.Main"));
+        assert_eq!(
+            manifest.class_path().collect::<Vec<_>>(),
+            ["lib/a.jar", "b/"]
+        );
+        assert!(!manifest.is_multi_release());
+    }
+
+    #[test]
+    fn test_multi_release_jar() {
+        let manifest = b"Manifest-Version: 1.0\nMulti-Release: true\n";
+        let entries: &[(&str, &[u8])] = &[
+            ("META-INF/MANIFEST.MF", manifest),
+            ("a/A.class", b"base A"),
+            ("a/B.class", b"base B"),
+            ("META-INF/versions/11/a/A.class", b"11 A"),
+            ("META-INF/versions/17/a/A.class", b"17 A"),
+            ("META-INF/versions/11/a/B.class", b"11 B"),
+        ];
+
+        for deflate in [false, true] {
+            let path = std::env::temp_dir().join(format!(
+                "jvm-rs-jar-{}-{}.jar",
+                std::process::id(),
+                deflate
+            ));
+            std::fs::write(&path, zip(entries, deflate)).unwrap();
+
+            let find = |release, name| {
+                let jar = JarSource::open_for_release(&path, release).unwrap();
+                jar.find(name).unwrap()
+            };
+
+            assert_eq!(find(8, "a/A").as_deref(), Some(&b"base A"[..]));
+            assert_eq!(find(11, "a/A").as_deref(), Some(&b"11 A"[..]));
+            assert_eq!(find(16, "a/A").as_deref(), Some(&b"11 A"[..]));
+            assert_eq!(find(21, "a/A").as_deref(), Some(&b"17 A"[..]));
+            assert_eq!(find(21, "a/B").as_deref(), Some(&b"11 B"[..]));
+            assert_eq!(find(21, "a/C"), None);
+
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! The platform classes of an installed JDK, which the runtime loads `java/*` classes from.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::error::{Result, VmError};
+
+use super::{jar::Archive, jimage::ImageSource, ClassSource};
+
+/// A JDK's `jmods` directory, where each module is a zip of its classes under `classes/`.
+#[derive(Debug)]
+pub struct JmodsSource {
+    modules: Vec<Archive>,
+    /// Which module each package is in, by index into `modules`.
+    packages: HashMap<String, usize>,
+}
+
+impl JmodsSource {
+    pub fn open(directory: impl AsRef<Path>) -> Result<Self> {
+        let directory = directory.as_ref();
+        let io_error = |e: std::io::Error| VmError::Io {
+            path: directory.display().to_string(),
+            reason: e.to_string(),
+        };
+
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(directory).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "jmod")
+            {
+                paths.push(path);
+            }
+        }
+
+        // Packages can only be in one module, but keep the choice stable if they aren't
+        paths.sort();
+
+        let mut source = Self {
+            modules: Vec::new(),
+            packages: HashMap::new(),
+        };
+
+        for path in paths {
+            let module = Archive::open(path)?;
+
+            for name in module.names() {
+                let Some((package, _)) = name
+                    .strip_prefix("classes/")
+                    .and_then(|name| name.strip_suffix(".class"))
+                    .and_then(|name| name.rsplit_once('/'))
+                else {
+                    continue;
+                };
+
+                source
+                    .packages
+                    .entry(package.to_string())
+                    .or_insert(source.modules.len());
+            }
+
+            source.modules.push(module);
+        }
+
+        Ok(source)
+    }
+}
+
+impl ClassSource for JmodsSource {
+    fn find(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let Some((package, _)) = name.rsplit_once('/') else {
+            return Ok(None);
+        };
+
+        match self.packages.get(package) {
+            Some(&module) => self.modules[module].read(&format!("classes/{}.class", name)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The platform classes of the JDK installed at some directory, like `$JAVA_HOME`. These are
+/// read from its `lib/modules` image, or from its `jmods` if it doesn't have one.
+#[derive(Debug)]
+pub enum JdkSource {
+    Image(ImageSource),
+    Jmods(JmodsSource),
+}
+
+impl JdkSource {
+    pub fn open(home: impl AsRef<Path>) -> Result<Self> {
+        let home = home.as_ref();
+        let image = home.join("lib").join("modules");
+        let jmods = home.join("jmods");
+
+        if image.is_file() {
+            ImageSource::open(image).map(Self::Image)
+        } else if jmods.is_dir() {
+            JmodsSource::open(jmods).map(Self::Jmods)
+        } else {
+            Err(VmError::Io {
+                path: home.display().to_string(),
+                reason: "not a JDK, as it has neither lib/modules nor jmods".to_string(),
+            })
+        }
+    }
+}
+
+impl ClassSource for JdkSource {
+    fn find(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        match self {
+            Self::Image(source) => source.find(name),
+            Self::Jmods(source) => source.find(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::{byte_stream::ByteStream, bytecode::ClassFile, classpath::ClassSource};
+
+    use super::{JdkSource, JmodsSource};
+
+    fn jdk_home() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../examples/jdk")
+    }
+
+    fn class_name(data: &[u8]) -> String {
+        ClassFile::read(&mut ByteStream::new(data))
+            .unwrap()
+            .class_name()
+            .to_string()
+    }
+
+    #[test]
+    fn test_jdk_sources() {
+        let image = JdkSource::open(jdk_home()).unwrap();
+        let jmods = JmodsSource::open(jdk_home().join("jmods")).unwrap();
+        assert!(matches!(image, JdkSource::Image(_)));
+
+        for name in ["java/lang/Object", "java/lang/String"] {
+            let data = image.find(name).unwrap().unwrap();
+            assert_eq!(class_name(&data), name);
+            assert_eq!(jmods.find(name).unwrap(), Some(data));
+        }
+
+        for name in [
+            "java/lang/Integer",
+            "java/util/List",
+            "Object",
+            "module-info",
+        ] {
+            assert_eq!(image.find(name).unwrap(), None);
+            assert_eq!(jmods.find(name).unwrap(), None);
+        }
+    }
+}
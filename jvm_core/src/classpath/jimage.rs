@@ -0,0 +1,336 @@
+//! The jimage container a JDK keeps its platform classes in, `lib/modules`.
+//!
+//! The format isn't specified anywhere, so this follows the JDK's own reader in
+//! `jdk.internal.jimage`. The index at the start of the file is read when the image is opened and
+//! resources are read from the file as they're asked for.
+
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+use crate::error::{Result, VmError};
+
+use super::ClassSource;
+
+const MAGIC: u32 = 0xCAFE_DADA;
+const MAJOR_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 7 * 4;
+
+/// The seed and multiplier of the hash used to look up names.
+const HASH_SEED: u32 = 0x0100_0193;
+
+const ATTRIBUTE_END: u8 = 0;
+const ATTRIBUTE_MODULE: u8 = 1;
+const ATTRIBUTE_PARENT: u8 = 2;
+const ATTRIBUTE_BASE: u8 = 3;
+const ATTRIBUTE_EXTENSION: u8 = 4;
+const ATTRIBUTE_OFFSET: u8 = 5;
+const ATTRIBUTE_COMPRESSED: u8 = 6;
+const ATTRIBUTE_UNCOMPRESSED: u8 = 7;
+const ATTRIBUTE_COUNT: usize = 8;
+
+/// Where a resource is and what it's called, split into parts that index the strings table.
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    attributes: [u64; ATTRIBUTE_COUNT],
+}
+
+impl Location {
+    fn get(&self, kind: u8) -> u64 {
+        self.attributes[kind as usize]
+    }
+}
+
+/// A jimage file whose index has been read.
+pub struct Image {
+    path: PathBuf,
+    file: RefCell<File>,
+    len: u64,
+    /// Images are written in the byte order of the platform they were built for.
+    big_endian: bool,
+    redirect: Vec<i32>,
+    offsets: Vec<u32>,
+    locations: Vec<u8>,
+    strings: Vec<u8>,
+    /// Resources are stored after the index, at offsets relative to its end.
+    index_size: u64,
+}
+
+impl std::fmt::Debug for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Image")
+            .field("path", &self.path)
+            .field("resources", &self.offsets.len())
+            .finish()
+    }
+}
+
+impl Image {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let io_error = |e: std::io::Error| VmError::Io {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        };
+
+        let file = File::open(&path).map_err(io_error)?;
+        let len = file.metadata().map_err(io_error)?.len();
+
+        let mut image = Self {
+            path,
+            file: RefCell::new(file),
+            len,
+            big_endian: false,
+            redirect: Vec::new(),
+            offsets: Vec::new(),
+            locations: Vec::new(),
+            strings: Vec::new(),
+            index_size: 0,
+        };
+
+        let header = image.read_at(0, HEADER_SIZE as u64)?;
+
+        image.big_endian = match header[..4].try_into().unwrap() {
+            magic if u32::from_le_bytes(magic) == MAGIC => false,
+            magic if u32::from_be_bytes(magic) == MAGIC => true,
+            _ => return Err(image.invalid("not a jimage file")),
+        };
+
+        let fields: Vec<_> = header
+            .chunks_exact(4)
+            .map(|bytes| image.u32(bytes))
+            .collect();
+        let [_, version, _, _, table_length, locations_size, strings_size] = fields[..] else {
+            unreachable!()
+        };
+
+        if version >> 16 != MAJOR_VERSION {
+            return Err(image.invalid(format!(
+                "unsupported version {}.{}",
+                version >> 16,
+                version & 0xFFFF
+            )));
+        }
+
+        let table_length = table_length as u64;
+        let index_size =
+            HEADER_SIZE as u64 + table_length * 8 + locations_size as u64 + strings_size as u64;
+        let index = image.read_at(HEADER_SIZE as u64, index_size - HEADER_SIZE as u64)?;
+
+        let (redirect, rest) = index.split_at(table_length as usize * 4);
+        let (offsets, rest) = rest.split_at(table_length as usize * 4);
+        let (locations, strings) = rest.split_at(locations_size as usize);
+
+        image.redirect = redirect
+            .chunks_exact(4)
+            .map(|bytes| image.u32(bytes) as i32)
+            .collect();
+        image.offsets = offsets
+            .chunks_exact(4)
+            .map(|bytes| image.u32(bytes))
+            .collect();
+        image.locations = locations.to_vec();
+        image.strings = strings.to_vec();
+        image.index_size = index_size;
+
+        Ok(image)
+    }
+
+    /// Reads the resource called `name`, such as `/java.base/java/lang/Object.class`.
+    pub fn read(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let Some(location) = self.find_location(name)? else {
+            return Ok(None);
+        };
+
+        // Images built with `jlink --compress` need the JDK's decompressors, which aren't
+        // implemented
+        if location.get(ATTRIBUTE_COMPRESSED) != 0 {
+            return Err(self.invalid(format!("resource '{}' is compressed", name)));
+        }
+
+        let offset = self
+            .index_size
+            .checked_add(location.get(ATTRIBUTE_OFFSET))
+            .ok_or_else(|| self.invalid(format!("resource '{}' is out of bounds", name)))?;
+
+        self.read_at(offset, location.get(ATTRIBUTE_UNCOMPRESSED))
+            .map(Some)
+    }
+
+    /// The modules that contain classes in `package`, such as `java.lang`.
+    pub fn package_modules(&self, package: &str) -> Result<Vec<String>> {
+        let Some(data) = self.read(&format!("/packages/{}", package))? else {
+            return Ok(Vec::new());
+        };
+
+        // Each entry is a flag saying whether the module's copy of the package is empty,
+        // followed by the module's name in the strings table
+        data.chunks_exact(8)
+            .filter(|entry| self.u32(&entry[..4]) == 0)
+            .map(|entry| {
+                self.string(self.u32(&entry[4..]) as u64)
+                    .map(str::to_string)
+            })
+            .collect()
+    }
+
+    /// Looks `name` up in the hash table, which is a perfect hash: `redirect` either holds the
+    /// index of the only name in its bucket, or a seed that separates the names that collide.
+    fn find_location(&self, name: &str) -> Result<Option<Location>> {
+        if self.redirect.is_empty() {
+            return Ok(None);
+        }
+
+        let len = self.redirect.len() as u32;
+        let index = match self.redirect[(hash(name, HASH_SEED) % len) as usize] {
+            0 => return Ok(None),
+            redirect if redirect < 0 => (-1 - redirect) as u32,
+            seed => hash(name, seed as u32) % len,
+        };
+
+        let offset = *self
+            .offsets
+            .get(index as usize)
+            .ok_or_else(|| self.invalid("bad hash table"))?;
+        let location = self.location(offset as usize)?;
+
+        // Names that aren't in the image still hash to something
+        Ok((self.location_name(&location)? == name).then_some(location))
+    }
+
+    fn location(&self, offset: usize) -> Result<Location> {
+        let invalid = || self.invalid("bad location attributes");
+
+        let mut attributes = [0; ATTRIBUTE_COUNT];
+        let mut index = offset;
+
+        loop {
+            let byte = *self.locations.get(index).ok_or_else(invalid)?;
+            let kind = byte >> 3;
+            if kind == ATTRIBUTE_END {
+                break;
+            }
+
+            let len = (byte & 7) as usize + 1;
+            let value = self
+                .locations
+                .get(index + 1..index + 1 + len)
+                .ok_or_else(invalid)?
+                .iter()
+                .fold(0, |value, &byte| (value << 8) | byte as u64);
+
+            *attributes.get_mut(kind as usize).ok_or_else(invalid)? = value;
+            index += 1 + len;
+        }
+
+        Ok(Location { attributes })
+    }
+
+    fn location_name(&self, location: &Location) -> Result<String> {
+        let module = self.string(location.get(ATTRIBUTE_MODULE))?;
+        let parent = self.string(location.get(ATTRIBUTE_PARENT))?;
+        let base = self.string(location.get(ATTRIBUTE_BASE))?;
+        let extension = self.string(location.get(ATTRIBUTE_EXTENSION))?;
+
+        let mut name = String::new();
+        if !module.is_empty() {
+            name += &format!("/{}/", module);
+        }
+        if !parent.is_empty() {
+            name += &format!("{}/", parent);
+        }
+        name += base;
+        if !extension.is_empty() {
+            name += &format!(".{}", extension);
+        }
+
+        Ok(name)
+    }
+
+    /// Reads the NUL-terminated string at `offset` in the strings table.
+    fn string(&self, offset: u64) -> Result<&str> {
+        let invalid = || self.invalid("bad string offset");
+
+        let bytes = self.strings.get(offset as usize..).ok_or_else(invalid)?;
+        let len = bytes.iter().position(|&b| b == 0).ok_or_else(invalid)?;
+
+        std::str::from_utf8(&bytes[..len]).map_err(|_| invalid())
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = bytes.try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    fn read_at(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        if offset.checked_add(len).is_none_or(|end| end > self.len) {
+            return Err(self.invalid("unexpected end of file"));
+        }
+
+        let mut data = vec![0; len as usize];
+        let mut file = self.file.borrow_mut();
+
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut data))
+            .map_err(|e| VmError::Io {
+                path: self.path.display().to_string(),
+                reason: e.to_string(),
+            })?;
+
+        Ok(data)
+    }
+
+    fn invalid(&self, reason: impl Into<String>) -> VmError {
+        VmError::InvalidImage {
+            path: self.path.display().to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// The FNV-1a style hash the JDK uses for names in images.
+fn hash(name: &str, seed: u32) -> u32 {
+    name.bytes().fold(seed, |hash, byte| {
+        hash.wrapping_mul(HASH_SEED) ^ byte as u32
+    }) & 0x7FFF_FFFF
+}
+
+/// The classes in a jimage, found through the image's index of which modules hold each package.
+#[derive(Debug)]
+pub struct ImageSource {
+    image: Image,
+}
+
+impl ImageSource {
+    pub fn new(image: Image) -> Self {
+        Self { image }
+    }
+
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        Image::open(path).map(Self::new)
+    }
+}
+
+impl ClassSource for ImageSource {
+    fn find(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        // Modules can't have classes in the unnamed package
+        let Some((package, _)) = name.rsplit_once('/') else {
+            return Ok(None);
+        };
+
+        for module in self.image.package_modules(&package.replace('/', "."))? {
+            if let Some(data) = self.image.read(&format!("/{}/{}.class", module, name))? {
+                return Ok(Some(data));
+            }
+        }
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,83 @@
+//! The bytecode of linked methods, and the pcs threads execute it at.
+//!
+//! Each Java method owns its code and is given its own range of pcs. The ranges are kept sorted,
+//! so any pc can be mapped back to the method and bytecode index it's in, and they're dropped
+//! along with their classes when those are unloaded.
+
+use std::{collections::BTreeMap, rc::Rc};
+
+use crate::class_loader::{ClassId, LoaderId};
+
+/// Where a pc is: a method of a linked class, and the index of the instruction in its code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeLocation {
+    pub class: ClassId,
+    /// Index into the class's methods, which is also its index in the class file.
+    pub method: usize,
+    pub bci: usize,
+}
+
+#[derive(Debug)]
+struct Segment {
+    class: ClassId,
+    method: usize,
+    code: Rc<[u8]>,
+}
+
+#[derive(Debug, Default)]
+pub struct CodeSpace {
+    /// Keyed by the first pc of each segment.
+    segments: BTreeMap<usize, Segment>,
+    /// Where the next segment starts. Pcs are never reused, so a stale pc from an unloaded class
+    /// can't run some other method's code.
+    next: usize,
+}
+
+impl CodeSpace {
+    /// Gives the code of a method its own range of pcs, and returns the first.
+    pub fn insert(&mut self, class: ClassId, method: usize, code: Rc<[u8]>) -> usize {
+        let start = self.next;
+
+        // Starts are kept 4-byte aligned, so the padding of switch instructions, which is
+        // relative to the start of the method, is the same as it would be relative to the pc
+        self.next = (start + code.len()).next_multiple_of(4).max(start + 4);
+        self.segments.insert(
+            start,
+            Segment {
+                class,
+                method,
+                code,
+            },
+        );
+
+        start
+    }
+
+    fn segment(&self, pc: usize) -> Option<(usize, &Segment)> {
+        self.segments
+            .range(..=pc)
+            .next_back()
+            .filter(|(&start, segment)| pc - start < segment.code.len())
+            .map(|(&start, segment)| (start, segment))
+    }
+
+    /// The code from `pc` to the end of its method.
+    pub fn code_at(&self, pc: usize) -> Option<&[u8]> {
+        self.segment(pc)
+            .map(|(start, segment)| &segment.code[pc - start..])
+    }
+
+    pub fn locate(&self, pc: usize) -> Option<CodeLocation> {
+        self.segment(pc).map(|(start, segment)| CodeLocation {
+            class: segment.class.clone(),
+            method: segment.method,
+            bci: pc - start,
+        })
+    }
+
+    /// Drops the code of every class defined by `loader`.
+    pub fn remove_loader(&mut self, loader: LoaderId) {
+        self.segments
+            .retain(|_, segment| segment.class.loader != loader);
+    }
+}
@@ -0,0 +1,538 @@
+//! Prints parsed class files for tooling.
+//!
+//! Without flags, `dump` prints the parsed structures with `Debug`. With `--json` it prints
+//! a stable schema instead of serializing `ClassFile` directly, so consumers don't depend
+//! on how the parser happens to store things. Every symbolic reference in the schema keeps
+//! its raw constant pool index next to the value it resolves to.
+
+use std::path::Path;
+
+use crate::{byte_stream::ByteStream, bytecode::ClassFile, classpath, error::Result};
+
+const USAGE: &str = "Usage: jvm-rs dump [--json] <class file|jar!/entry>...";
+
+/// Bumped whenever a field is removed or changes meaning. Adding fields doesn't change it.
+#[cfg(feature = "serde")]
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Runs the `dump` subcommand with the arguments following it, returning the exit code.
+pub fn main(args: &[String]) -> i32 {
+    let mut json = false;
+    let mut paths = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            flag if flag.starts_with('-') => {
+                eprintln!("Error: invalid flag: {}", flag);
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+            path => paths.push(Path::new(path)),
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    if json {
+        return json_main(&paths);
+    }
+
+    let mut status = 0;
+    for path in paths {
+        match read(path) {
+            Ok(class) => println!("{:#?}", class),
+            Err(e) => {
+                eprintln!("Error: {}: {}", path.display(), e);
+                status = 1;
+            }
+        }
+    }
+
+    status
+}
+
+/// Prints a JSON array with one entry per class file.
+#[cfg(feature = "serde")]
+fn json_main(paths: &[&Path]) -> i32 {
+    let mut classes = Vec::new();
+
+    for path in paths {
+        match read(path).and_then(|class| json::class(path, &class)) {
+            Ok(class) => classes.push(class),
+            Err(e) => {
+                eprintln!("Error: {}: {}", path.display(), e);
+                return 1;
+            }
+        }
+    }
+
+    match serde_json::to_string_pretty(&classes) {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn json_main(_paths: &[&Path]) -> i32 {
+    eprintln!("Error: --json requires jvm-rs to be built with the `serde` feature");
+    2
+}
+
+fn read(path: &Path) -> Result<ClassFile> {
+    let data = classpath::read_class_file(&path.to_string_lossy())?;
+
+    let mut stream = ByteStream::new(&data);
+    ClassFile::read(&mut stream)
+}
+
+/// The JSON schema. Field names here are the public interface, so rename with care.
+#[cfg(feature = "serde")]
+pub mod json {
+    use std::path::Path;
+
+    use bitflags::Flags;
+    use serde::Serialize;
+
+    use crate::{
+        bytecode::{
+            attribute_info::{self, ElementValue},
+            Attribute, AttributeInfo, ClassFile, ConstantPool, FieldInfo, MethodInfo,
+        },
+        error::Result,
+    };
+
+    use super::SCHEMA_VERSION;
+
+    #[derive(Debug, Serialize)]
+    pub struct Class {
+        pub schema_version: u32,
+        pub path: String,
+        pub minor_version: u16,
+        pub major_version: u16,
+        pub access_flags: AccessFlags,
+        pub this_class: Symbol,
+        /// `None` only for `java/lang/Object` and module descriptors.
+        pub super_class: Option<Symbol>,
+        pub interfaces: Vec<Symbol>,
+        pub signature: Option<String>,
+        pub source_file: Option<Symbol>,
+        pub constant_pool: Vec<Constant>,
+        pub fields: Vec<Field>,
+        pub methods: Vec<Method>,
+        pub annotations: Vec<Annotation>,
+        pub attributes: Vec<String>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct AccessFlags {
+        pub raw: u16,
+        /// The names of the flags that are set, such as `ACC_PUBLIC`, in bit order.
+        pub names: Vec<String>,
+    }
+
+    /// A constant pool index and the string it resolves to.
+    #[derive(Debug, Serialize)]
+    pub struct Symbol {
+        pub index: u16,
+        pub value: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Constant {
+        pub index: u16,
+        /// The JVMS name of the constant's tag, such as `Methodref`.
+        pub tag: &'static str,
+        /// The constant as parsed, including the indices it refers to.
+        pub raw: ConstantPool,
+        /// The resolved value. Numbers are kept as text in javap's notation, such as `1.5f`,
+        /// since JSON can't represent every float or the full range of a long.
+        pub value: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Field {
+        pub access_flags: AccessFlags,
+        pub name: Symbol,
+        pub descriptor: Symbol,
+        pub signature: Option<String>,
+        pub constant_value: Option<Symbol>,
+        pub annotations: Vec<Annotation>,
+        pub attributes: Vec<String>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Method {
+        pub access_flags: AccessFlags,
+        pub name: Symbol,
+        pub descriptor: Symbol,
+        pub signature: Option<String>,
+        /// `None` for abstract and native methods.
+        pub code: Option<Code>,
+        pub exceptions: Vec<Symbol>,
+        pub annotations: Vec<Annotation>,
+        pub attributes: Vec<String>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Code {
+        pub max_stack: u16,
+        pub max_locals: u16,
+        /// The size of the bytecode in bytes.
+        pub code_length: usize,
+        pub exception_handlers: usize,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Annotation {
+        /// The field descriptor of the annotation interface, such as `Ljava/lang/Deprecated;`.
+        pub type_descriptor: Symbol,
+        /// Whether the annotation is retained at run time.
+        pub visible: bool,
+        pub elements: Vec<Element>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Element {
+        pub name: Symbol,
+        pub value: Value,
+    }
+
+    #[derive(Debug, Serialize)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    pub enum Value {
+        /// A primitive or string constant. `tag` is the element value tag, such as `I` or `s`.
+        Const {
+            tag: char,
+            value: Symbol,
+        },
+        Enum {
+            type_descriptor: Symbol,
+            name: Symbol,
+        },
+        Class {
+            descriptor: Symbol,
+        },
+        Annotation {
+            annotation: Box<Annotation>,
+        },
+        Array {
+            values: Vec<Value>,
+        },
+    }
+
+    /// Builds the schema for `class`, which was read from `path`.
+    pub fn class(path: &Path, class: &ClassFile) -> Result<Class> {
+        let super_class = match class.super_class() {
+            0 => None,
+            index => Some(class_symbol(class, index)?),
+        };
+
+        let mut constant_pool = Vec::new();
+        for (index, constant) in class.constant_pool().iter().enumerate() {
+            if matches!(constant, ConstantPool::Empty) {
+                continue;
+            }
+
+            let index = index as u16;
+            constant_pool.push(Constant {
+                index,
+                tag: tag(constant),
+                raw: constant.clone(),
+                value: constant_value(class, index)?,
+            });
+        }
+
+        Ok(Class {
+            schema_version: SCHEMA_VERSION,
+            path: path.display().to_string(),
+            minor_version: class.minor_version(),
+            major_version: class.major_version(),
+            access_flags: access_flags(class.access_flags()),
+            this_class: class_symbol(class, class.this_class())?,
+            super_class,
+            interfaces: class
+                .interfaces()
+                .iter()
+                .map(|&index| class_symbol(class, index))
+                .collect::<Result<_>>()?,
+            signature: class.signature()?.map(|signature| signature.to_string()),
+            source_file: class
+                .attributes()
+                .iter()
+                .find_map(|attr| match attr.attribute() {
+                    Attribute::SourceFile(source_file) => Some(source_file.source_file_index),
+                    _ => None,
+                })
+                .map(|index| utf8_symbol(class, index))
+                .transpose()?,
+            constant_pool,
+            fields: class
+                .fields()
+                .iter()
+                .map(|field| self::field(class, field))
+                .collect::<Result<_>>()?,
+            methods: class
+                .methods()
+                .iter()
+                .map(|method| self::method(class, method))
+                .collect::<Result<_>>()?,
+            annotations: annotations(class, class.attributes())?,
+            attributes: attribute_names(class, class.attributes())?,
+        })
+    }
+
+    fn field(class: &ClassFile, field: &FieldInfo) -> Result<Field> {
+        let constant_value = field
+            .attributes()
+            .iter()
+            .find_map(|attr| match attr.attribute() {
+                Attribute::ConstantValue(value) => Some(value.constantvalue_index),
+                _ => None,
+            })
+            .map(|index| {
+                Ok(Symbol {
+                    index,
+                    value: constant_value(class, index)?,
+                })
+            })
+            .transpose()?;
+
+        Ok(Field {
+            access_flags: access_flags(field.access_flags()),
+            name: utf8_symbol(class, field.name_index)?,
+            descriptor: utf8_symbol(class, field.descriptor_index)?,
+            signature: field.signature(class)?.map(|ty| ty.to_string()),
+            constant_value,
+            annotations: annotations(class, field.attributes())?,
+            attributes: attribute_names(class, field.attributes())?,
+        })
+    }
+
+    fn method(class: &ClassFile, method: &MethodInfo) -> Result<Method> {
+        let mut code = None;
+        let mut exceptions = Vec::new();
+
+        for attr in method.attributes() {
+            match attr.attribute() {
+                Attribute::Code(attribute_info::Code {
+                    max_stack,
+                    max_locals,
+                    instructions,
+                    exception_table,
+                    ..
+                }) => {
+                    code = Some(Code {
+                        max_stack: *max_stack,
+                        max_locals: *max_locals,
+                        code_length: instructions.len(),
+                        exception_handlers: exception_table.len(),
+                    })
+                }
+                Attribute::Exceptions(attribute_info::Exceptions {
+                    exception_index_table,
+                }) => {
+                    for &index in exception_index_table {
+                        exceptions.push(class_symbol(class, index)?);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(Method {
+            access_flags: access_flags(method.access_flags()),
+            name: utf8_symbol(class, method.name_index)?,
+            descriptor: utf8_symbol(class, method.descriptor_index)?,
+            signature: method
+                .signature(class)?
+                .map(|signature| signature.to_string()),
+            code,
+            exceptions,
+            annotations: annotations(class, method.attributes())?,
+            attributes: attribute_names(class, method.attributes())?,
+        })
+    }
+
+    fn annotations(class: &ClassFile, attributes: &[AttributeInfo]) -> Result<Vec<Annotation>> {
+        let mut result = Vec::new();
+
+        for attr in attributes {
+            let (visible, annotations) = match attr.attribute() {
+                Attribute::RuntimeVisibleAnnotations(a) => (true, &a.annotations),
+                Attribute::RuntimeInvisibleAnnotations(a) => (false, &a.annotations),
+                _ => continue,
+            };
+
+            for annotation in annotations {
+                result.push(self::annotation(class, annotation, visible)?);
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn annotation(
+        class: &ClassFile,
+        annotation: &attribute_info::Annotation,
+        visible: bool,
+    ) -> Result<Annotation> {
+        Ok(Annotation {
+            type_descriptor: Symbol {
+                index: annotation.type_index,
+                value: annotation.type_descriptor.clone(),
+            },
+            visible,
+            elements: annotation
+                .elements
+                .iter()
+                .map(|element| {
+                    Ok(Element {
+                        name: Symbol {
+                            index: element.name_index,
+                            value: element.name.clone(),
+                        },
+                        value: self::value(class, &element.value, visible)?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    fn value(class: &ClassFile, value: &ElementValue, visible: bool) -> Result<Value> {
+        Ok(match value {
+            ElementValue::Const {
+                tag,
+                const_value_index,
+            } => Value::Const {
+                tag: *tag as char,
+                value: Symbol {
+                    index: *const_value_index,
+                    value: constant_value(class, *const_value_index)?,
+                },
+            },
+            ElementValue::Enum {
+                type_name_index,
+                const_name_index,
+            } => Value::Enum {
+                type_descriptor: utf8_symbol(class, *type_name_index)?,
+                name: utf8_symbol(class, *const_name_index)?,
+            },
+            ElementValue::Class { class_info_index } => Value::Class {
+                descriptor: utf8_symbol(class, *class_info_index)?,
+            },
+            ElementValue::Annotation(annotation) => Value::Annotation {
+                annotation: Box::new(self::annotation(class, annotation, visible)?),
+            },
+            ElementValue::Array(values) => Value::Array {
+                values: values
+                    .iter()
+                    .map(|value| self::value(class, value, visible))
+                    .collect::<Result<_>>()?,
+            },
+        })
+    }
+
+    fn attribute_names(class: &ClassFile, attributes: &[AttributeInfo]) -> Result<Vec<String>> {
+        attributes
+            .iter()
+            .map(|attr| Ok(class.utf8(attr.name_index())?.to_string()))
+            .collect()
+    }
+
+    fn access_flags<F: Flags<Bits = u16>>(flags: F) -> AccessFlags {
+        AccessFlags {
+            raw: flags.bits(),
+            names: flags
+                .iter_names()
+                .map(|(name, _)| format!("ACC_{}", name))
+                .collect(),
+        }
+    }
+
+    fn utf8_symbol(class: &ClassFile, index: u16) -> Result<Symbol> {
+        Ok(Symbol {
+            index,
+            value: class.utf8(index)?.to_string(),
+        })
+    }
+
+    fn class_symbol(class: &ClassFile, index: u16) -> Result<Symbol> {
+        Ok(Symbol {
+            index,
+            value: class.class_ref(index)?.to_string(),
+        })
+    }
+
+    /// Strings are given as they are, everything else the way javap describes it.
+    fn constant_value(class: &ClassFile, index: u16) -> Result<String> {
+        match class.pool(index as usize) {
+            ConstantPool::Utf8(_) => Ok(class.utf8(index)?.to_string()),
+            ConstantPool::String(_) => Ok(class.string_constant(index)?.to_string()),
+            _ => class.describe_constant(index, false),
+        }
+    }
+
+    fn tag(constant: &ConstantPool) -> &'static str {
+        match constant {
+            ConstantPool::Empty => "Empty",
+            ConstantPool::Class(_) => "Class",
+            ConstantPool::FieldRef(_) => "Fieldref",
+            ConstantPool::MethodRef(_) => "Methodref",
+            ConstantPool::InterfaceMethodRef(_) => "InterfaceMethodref",
+            ConstantPool::String(_) => "String",
+            ConstantPool::Integer(_) => "Integer",
+            ConstantPool::Float(_) => "Float",
+            ConstantPool::Long(_) => "Long",
+            ConstantPool::Double(_) => "Double",
+            ConstantPool::NameAndType(_) => "NameAndType",
+            ConstantPool::Utf8(_) => "Utf8",
+            ConstantPool::MethodHandle(_) => "MethodHandle",
+            ConstantPool::MethodType(_) => "MethodType",
+            ConstantPool::Dynamic(_) => "Dynamic",
+            ConstantPool::InvokeDynamic(_) => "InvokeDynamic",
+            ConstantPool::Module(_) => "Module",
+            ConstantPool::Package(_) => "Package",
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use std::path::Path;
+
+    use crate::{byte_stream::ByteStream, bytecode::ClassFile};
+
+    use super::{json, SCHEMA_VERSION};
+
+    #[test]
+    fn test_json_schema() {
+        let bytes = include_bytes!("../../examples/Main.class");
+        let file = ClassFile::read(&mut ByteStream::new(bytes)).unwrap();
+        let class = json::class(Path::new("examples/Main.class"), &file).unwrap();
+        let output = serde_json::to_value(&class).unwrap();
+
+        // Changing this file means changing the schema, and maybe SCHEMA_VERSION
+        let expected: serde_json::Value =
+            serde_json::from_str(include_str!("../../examples/dump/Main.json")).unwrap();
+        assert_eq!(output, expected);
+
+        assert_eq!(output["schema_version"], SCHEMA_VERSION);
+        assert_eq!(
+            output["this_class"],
+            serde_json::json!({ "index": 8, "value": "Test/Main" })
+        );
+        assert_eq!(output["methods"][1]["name"]["value"], "out");
+        assert_eq!(output["methods"][1]["descriptor"]["value"], "(I)V");
+        assert_eq!(output["methods"][1]["code"], serde_json::Value::Null);
+    }
+}
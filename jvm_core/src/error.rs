@@ -1,14 +1,216 @@
 use std::fmt::Display;
 
+use crate::bytecode::{java_version, MAX_MAJOR_VERSION, PREVIEW_MINOR_VERSION};
+
 #[derive(Debug, Clone)]
 pub enum VmError {
     ClassNotFound(String),
+    /// A class file could not be parsed.
+    ///
+    /// `offset` is the byte offset into the class file where parsing failed and `structure`
+    /// is the path to the item being read, such as `methods[3].attributes[1].Code`.
+    ClassFormat {
+        offset: usize,
+        structure: String,
+        reason: String,
+    },
+    /// A parsed class file is inconsistent, such as a constant pool index that refers to the
+    /// wrong kind of entry (JVMS 4.8).
+    ///
+    /// `structure` is the path to the offending item, such as `constant_pool[4].class_index`.
+    FormatCheck { structure: String, reason: String },
+    /// A field or method descriptor doesn't follow the grammar in JVMS 4.3.
+    InvalidDescriptor {
+        descriptor: String,
+        position: usize,
+        reason: String,
+    },
+    /// A generic signature doesn't follow the grammar in JVMS 4.7.9.1.
+    InvalidSignature {
+        signature: String,
+        position: usize,
+        reason: String,
+    },
+    /// A class file's version is outside the range this VM supports.
+    UnsupportedClassVersion { major: u16, minor: u16 },
+    /// A symbolic reference to a field didn't match any field of its class.
+    NoSuchField { class_name: String, name: String },
+    /// A symbolic reference to a method didn't match any method of its class.
+    NoSuchMethod { class_name: String, name: String },
+    /// A class file was parsed but its contents could not be linked.
+    Linkage { class_name: String, reason: String },
+    /// A class is its own superclass or superinterface, directly or not (JVMS 5.3.5).
+    ClassCircularity(String),
+    /// A class's supertypes can't be extended or implemented by it, such as a final superclass
+    /// or a superclass that's actually an interface.
+    IncompatibleClassChange { class_name: String, reason: String },
+    /// A class's `<clinit>` failed, leaving the class unusable (JVMS 5.5).
+    ExceptionInInitializer { class_name: String, reason: String },
+    /// A class was used after its initialization had failed.
+    NoClassDefFound { class_name: String, reason: String },
+    /// `new` named an abstract class or an interface.
+    Instantiation(String),
+    /// A thread jumped to a pc outside the code of any linked method, such as one of a class
+    /// that has since been unloaded.
+    InvalidPc(usize),
+    /// An abstract method was invoked, named like `java/lang/Runnable.run()V`.
+    AbstractMethod(String),
+    /// A native method was invoked that the runtime has no native function for.
+    UnsatisfiedLink(String),
+    /// Reading from a class path entry failed for a reason other than the class not existing.
+    Io { path: String, reason: String },
+    /// A jar or zip file on the class path is malformed or uses unsupported features.
+    InvalidJar { path: String, reason: String },
+    /// A JDK's `lib/modules` image is malformed or uses unsupported features.
+    InvalidImage { path: String, reason: String },
+}
+
+impl VmError {
+    pub fn class_format(offset: usize, reason: impl Into<String>) -> VmError {
+        VmError::ClassFormat {
+            offset,
+            structure: String::new(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Prefixes the structure path of a `ClassFormat` error with `segment`.
+    ///
+    /// Segments starting with `[` are treated as indices and are joined without a `.`.
+    pub fn within(self, segment: impl Into<String>) -> VmError {
+        let join = |structure: String| {
+            let mut segment = segment.into();
+
+            if !structure.is_empty() {
+                if !structure.starts_with('[') {
+                    segment.push('.');
+                }
+                segment.push_str(&structure);
+            }
+
+            segment
+        };
+
+        match self {
+            VmError::ClassFormat {
+                offset,
+                structure,
+                reason,
+            } => VmError::ClassFormat {
+                offset,
+                structure: join(structure),
+                reason,
+            },
+            VmError::FormatCheck { structure, reason } => VmError::FormatCheck {
+                structure: join(structure),
+                reason,
+            },
+            err => err,
+        }
+    }
 }
 
 impl Display for VmError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::ClassNotFound(class_name) => write!(f, "Class '{}' not found!", class_name),
+            Self::NoSuchField { class_name, name } => {
+                write!(f, "Class '{}' has no field '{}'", class_name, name)
+            }
+            Self::NoSuchMethod { class_name, name } => {
+                write!(f, "Class '{}' has no method '{}'", class_name, name)
+            }
+            Self::Linkage { class_name, reason } => {
+                write!(f, "Unable to link class '{}': {}", class_name, reason)
+            }
+            Self::ClassCircularity(class_name) => {
+                write!(f, "Class '{}' is its own supertype", class_name)
+            }
+            Self::IncompatibleClassChange { class_name, reason } => {
+                write!(f, "Incompatible class change in '{}': {}", class_name, reason)
+            }
+            Self::ExceptionInInitializer { class_name, reason } => {
+                write!(f, "Initializer of class '{}' failed: {}", class_name, reason)
+            }
+            Self::NoClassDefFound { class_name, reason } => {
+                write!(f, "Could not initialize class '{}': {}", class_name, reason)
+            }
+            Self::Instantiation(class_name) => {
+                write!(f, "Cannot instantiate abstract class or interface '{}'", class_name)
+            }
+            Self::InvalidPc(pc) => write!(f, "No code at pc {:#x}", pc),
+            Self::AbstractMethod(method_name) => {
+                write!(f, "Cannot invoke abstract method '{}'", method_name)
+            }
+            Self::UnsatisfiedLink(method_name) => {
+                write!(f, "No native function for method '{}'", method_name)
+            }
+            Self::Io { path, reason } => write!(f, "Unable to read '{}': {}", path, reason),
+            Self::InvalidJar { path, reason } => {
+                write!(f, "Invalid jar file '{}': {}", path, reason)
+            }
+            Self::InvalidImage { path, reason } => {
+                write!(f, "Invalid jimage file '{}': {}", path, reason)
+            }
+            Self::ClassFormat {
+                offset,
+                structure,
+                reason,
+            } => write!(
+                f,
+                "Malformed class file at offset 0x{:x} in '{}': {}",
+                offset, structure, reason
+            ),
+            Self::FormatCheck { structure, reason } => {
+                write!(f, "Invalid class file in '{}': {}", structure, reason)
+            }
+            Self::InvalidDescriptor {
+                descriptor,
+                position,
+                reason,
+            } => write!(
+                f,
+                "Invalid descriptor '{}' at position {}: {}",
+                descriptor, position, reason
+            ),
+            Self::InvalidSignature {
+                signature,
+                position,
+                reason,
+            } => write!(
+                f,
+                "Invalid signature '{}' at position {}: {}",
+                signature, position, reason
+            ),
+            Self::UnsupportedClassVersion { major, minor } if *minor == PREVIEW_MINOR_VERSION => {
+                if *major == MAX_MAJOR_VERSION {
+                    write!(
+                        f,
+                        "Class file uses preview features of Java {} ({}.{}), but preview features are not enabled",
+                        java_version(*major),
+                        major,
+                        minor
+                    )
+                } else {
+                    write!(
+                        f,
+                        "Class file uses preview features of Java {} ({}.{}), only preview features of Java {} are supported",
+                        java_version(*major),
+                        major,
+                        minor,
+                        java_version(MAX_MAJOR_VERSION)
+                    )
+                }
+            }
+            Self::UnsupportedClassVersion { major, minor } => write!(
+                f,
+                "Class file compiled for Java {} ({}.{}), supported up to Java {} ({}.0)",
+                java_version(*major),
+                major,
+                minor,
+                java_version(MAX_MAJOR_VERSION),
+                MAX_MAJOR_VERSION
+            ),
         }
     }
 }
@@ -1,8 +1,6 @@
-use std::rc::Rc;
-
 use bitflags::bitflags;
 
-use crate::value::Value;
+use crate::value::{runtime_pool::ClassRef, Value};
 
 bitflags! {
     #[derive(Debug)]
@@ -17,41 +15,52 @@ pub struct Frame {
     pub base_pointer: usize,
     pub stack_pointer: usize,
     pub return_pc: usize,
-    pub class_name: Rc<str>,
+    pub class: ClassRef,
     pub flags: FrameFlags,
+    /// For `<clinit>` frames, the classes whose `<clinit>` runs after this one returns, last
+    /// first.
+    pub pending: Vec<ClassRef>,
 }
 
 impl Frame {
-    pub fn new_main(class_name: Rc<str>) -> Frame {
+    pub fn new_main(class: ClassRef) -> Frame {
         Frame {
             locals: Vec::new(),
             base_pointer: 0,
             stack_pointer: 0,
             return_pc: 0,
-            class_name,
+            class,
             flags: FrameFlags::empty(),
+            pending: Vec::new(),
         }
     }
 
-    pub fn new(stack: usize, return_pc: usize, class_name: Rc<str>) -> Frame {
+    pub fn new(stack: usize, return_pc: usize, class: ClassRef) -> Frame {
         Frame {
             locals: Vec::new(),
             base_pointer: stack,
             stack_pointer: stack,
             return_pc,
-            class_name,
+            class,
             flags: FrameFlags::empty(),
+            pending: Vec::new(),
         }
     }
 
-    pub fn new_clinit(stack: usize, return_pc: usize, class_name: Rc<str>) -> Frame {
+    pub fn new_clinit(stack: usize, return_pc: usize, class: ClassRef) -> Frame {
         Frame {
             locals: Vec::new(),
             base_pointer: stack,
             stack_pointer: stack,
             return_pc,
-            class_name,
+            class,
             flags: FrameFlags::CLINIT,
+            pending: Vec::new(),
         }
     }
+
+    pub fn with_pending(mut self, pending: Vec<ClassRef>) -> Frame {
+        self.pending = pending;
+        self
+    }
 }
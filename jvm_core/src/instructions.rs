@@ -14,7 +14,7 @@
 
 use std::fmt::Display;
 
-use crate::byte_stream::{ByteStream, ReaderContext};
+use crate::bytecode::{ClassFile, ConstantPool};
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -231,6 +231,7 @@ pub enum Instruction {
     PutStatic = 0xb3,
     InvokeStatic = 0xb8,
     InvokeSpecial = 0xb7,
+    New = 0xbb,
 }
 
 impl Instruction {
@@ -278,6 +279,7 @@ impl Instruction {
             Instruction::InvokeSpecial => 2,
             Instruction::PutStatic => 2,
             Instruction::GetStatic => 2,
+            Instruction::New => 2,
             _ => 0,
         }
     }
@@ -290,51 +292,280 @@ impl From<u8> for Instruction {
     }
 }
 
-pub struct Format<'a>(&'a [u8]);
+/// Returns the length in bytes of the instruction at `pc`, including its operands.
+///
+/// Unlike `Instruction::operands_size` this covers every opcode, including the variable
+/// length `tableswitch`, `lookupswitch` and `wide` forms. Returns `None` for undefined
+/// opcodes or if the instruction runs past the end of `code`.
+pub fn instruction_length(code: &[u8], pc: usize) -> Option<usize> {
+    let opcode = *code.get(pc)?;
+
+    let len = match opcode {
+        0x10 | 0x12 | 0x15..=0x19 | 0x36..=0x3a | 0xa9 | 0xbc => 2,
+        0x11 | 0x13 | 0x14 | 0x84 | 0x99..=0xa8 | 0xb2..=0xb8 | 0xbb | 0xbd | 0xc0 | 0xc1
+        | 0xc6 | 0xc7 => 3,
+        0xc5 => 4,
+        0xb9 | 0xba | 0xc8 | 0xc9 => 5,
+        // tableswitch and lookupswitch are padded so their operands are 4 byte aligned
+        0xaa | 0xab => {
+            let operands = (pc + 4) & !3;
+            let read_i32 = |index: usize| -> Option<i32> {
+                Some(i32::from_be_bytes(code.get(index..index + 4)?.try_into().ok()?))
+            };
+
+            let table_len = if opcode == 0xaa {
+                let low = read_i32(operands + 4)? as i64;
+                let high = read_i32(operands + 8)? as i64;
+
+                12 + (high - low + 1).max(0) as usize * 4
+            } else {
+                let pairs = read_i32(operands + 4)?.max(0) as usize;
+
+                8 + pairs * 8
+            };
+
+            operands + table_len - pc
+        }
+        // wide
+        0xc4 => match *code.get(pc + 1)? {
+            0x84 => 6,
+            0x15..=0x19 | 0x36..=0x3a | 0xa9 => 4,
+            _ => return None,
+        },
+        0x00..=0xc3 => 1,
+        _ => return None,
+    };
+
+    (pc + len <= code.len()).then_some(len)
+}
+
+/// Whether the opcode can transfer control somewhere other than the next instruction,
+/// which means it has branch targets that need a stack map frame.
+pub fn is_branch(opcode: u8) -> bool {
+    matches!(opcode, 0x99..=0xab | 0xc6..=0xc9)
+}
+
+/// javap's name for each opcode, indexed by opcode.
+#[rustfmt::skip]
+const MNEMONICS: [&str; 0xca] = [
+    "nop", "aconst_null", "iconst_m1", "iconst_0", "iconst_1", "iconst_2", "iconst_3",
+    "iconst_4", "iconst_5", "lconst_0", "lconst_1", "fconst_0", "fconst_1", "fconst_2",
+    "dconst_0", "dconst_1", "bipush", "sipush", "ldc", "ldc_w", "ldc2_w", "iload", "lload",
+    "fload", "dload", "aload", "iload_0", "iload_1", "iload_2", "iload_3", "lload_0", "lload_1",
+    "lload_2", "lload_3", "fload_0", "fload_1", "fload_2", "fload_3", "dload_0", "dload_1",
+    "dload_2", "dload_3", "aload_0", "aload_1", "aload_2", "aload_3", "iaload", "laload",
+    "faload", "daload", "aaload", "baload", "caload", "saload", "istore", "lstore", "fstore",
+    "dstore", "astore", "istore_0", "istore_1", "istore_2", "istore_3", "lstore_0", "lstore_1",
+    "lstore_2", "lstore_3", "fstore_0", "fstore_1", "fstore_2", "fstore_3", "dstore_0",
+    "dstore_1", "dstore_2", "dstore_3", "astore_0", "astore_1", "astore_2", "astore_3",
+    "iastore", "lastore", "fastore", "dastore", "aastore", "bastore", "castore", "sastore", "pop",
+    "pop2", "dup", "dup_x1", "dup_x2", "dup2", "dup2_x1", "dup2_x2", "swap", "iadd", "ladd",
+    "fadd", "dadd", "isub", "lsub", "fsub", "dsub", "imul", "lmul", "fmul", "dmul", "idiv",
+    "ldiv", "fdiv", "ddiv", "irem", "lrem", "frem", "drem", "ineg", "lneg", "fneg", "dneg",
+    "ishl", "lshl", "ishr", "lshr", "iushr", "lushr", "iand", "land", "ior", "lor", "ixor",
+    "lxor", "iinc", "i2l", "i2f", "i2d", "l2i", "l2f", "l2d", "f2i", "f2l", "f2d", "d2i", "d2l",
+    "d2f", "i2b", "i2c", "i2s", "lcmp", "fcmpl", "fcmpg", "dcmpl", "dcmpg", "ifeq", "ifne",
+    "iflt", "ifge", "ifgt", "ifle", "if_icmpeq", "if_icmpne", "if_icmplt", "if_icmpge",
+    "if_icmpgt", "if_icmple", "if_acmpeq", "if_acmpne", "goto", "jsr", "ret", "tableswitch",
+    "lookupswitch", "ireturn", "lreturn", "freturn", "dreturn", "areturn", "return",
+    "getstatic", "putstatic", "getfield", "putfield", "invokevirtual", "invokespecial",
+    "invokestatic", "invokeinterface", "invokedynamic", "new", "newarray", "anewarray",
+    "arraylength", "athrow", "checkcast", "instanceof", "monitorenter", "monitorexit", "wide",
+    "multianewarray", "ifnull", "ifnonnull", "goto_w", "jsr_w",
+];
+
+/// The column that comments on operands are aligned to, as in javap.
+pub const COMMENT_COLUMN: usize = 40;
+
+/// Disassembles a method's code the way javap does, one instruction per line.
+///
+/// Lines aren't indented so they can be nested in other output. When the class file the
+/// code came from is known, constant pool operands are followed by a comment describing the
+/// constant.
+pub struct Format<'a> {
+    code: &'a [u8],
+    class: Option<&'a ClassFile>,
+}
+
+impl<'a> Format<'a> {
+    pub fn with_class(code: &'a [u8], class: &'a ClassFile) -> Self {
+        Format {
+            code,
+            class: Some(class),
+        }
+    }
+}
 
 impl<'a> From<&'a [u8]> for Format<'a> {
     fn from(value: &'a [u8]) -> Self {
-        Format(value)
+        Format {
+            code: value,
+            class: None,
+        }
     }
 }
 
 impl Display for Format<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        format_instructions(self.0, f)
+        let mut pc = 0;
+
+        while pc < self.code.len() {
+            let Some(len) = instruction_length(self.code, pc) else {
+                writeln!(f, "{:>4}: <invalid opcode 0x{:02x}>", pc, self.code[pc])?;
+                break;
+            };
+
+            self.format_instruction(f, pc)?;
+            pc += len;
+        }
+
+        Ok(())
     }
 }
 
-fn format_instructions(data: &[u8], f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    let ctx = ReaderContext {
-        constant_pool: Vec::new(),
-    };
-    let mut stream = ByteStream::new(data);
+impl Format<'_> {
+    fn u8(&self, index: usize) -> u8 {
+        self.code[index]
+    }
 
-    write!(f, "Instructions:\n")?;
+    fn u16(&self, index: usize) -> u16 {
+        u16::from_be_bytes([self.code[index], self.code[index + 1]])
+    }
 
-    while stream.has_next() {
-        let op: u8 = stream.read(&ctx);
-        let instr = Instruction::from(op);
+    fn i32(&self, index: usize) -> i32 {
+        i32::from_be_bytes(self.code[index..index + 4].try_into().unwrap())
+    }
 
-        write!(f, "    {op:02x} {instr:?}")?;
-        let operands = instr.operands_size();
+    /// Writes a line, followed by a comment describing the constant at `index` if possible.
+    fn with_constant(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        line: String,
+        index: u16,
+    ) -> std::fmt::Result {
+        let Some(class) = self.class else {
+            return writeln!(f, "{}", line);
+        };
+
+        let comment = match class.pool(index as usize) {
+            ConstantPool::Class(_) => "class",
+            ConstantPool::String(_) => "String",
+            ConstantPool::Integer(_) => "int",
+            ConstantPool::Float(_) => "float",
+            ConstantPool::Long(_) => "long",
+            ConstantPool::Double(_) => "double",
+            ConstantPool::FieldRef(_) => "Field",
+            ConstantPool::MethodRef(_) => "Method",
+            ConstantPool::InterfaceMethodRef(_) => "InterfaceMethod",
+            ConstantPool::MethodHandle(_) => "MethodHandle",
+            ConstantPool::MethodType(_) => "MethodType",
+            ConstantPool::Dynamic(_) => "Dynamic",
+            ConstantPool::InvokeDynamic(_) => "InvokeDynamic",
+            _ => return writeln!(f, "{}", line),
+        };
+
+        match class.describe_constant(index, true) {
+            Ok(constant) => {
+                let padding = COMMENT_COLUMN.saturating_sub(line.len()).max(1);
+                writeln!(f, "{}{:padding$}// {} {}", line, "", comment, constant)
+            }
+            Err(_) => writeln!(f, "{}", line),
+        }
+    }
 
-        if operands > 0 {
-            write!(f, "(")?;
-            for i in 0..operands {
-                let operand = stream.read::<u8>(&ctx);
-                if i == operands - 1 {
-                    write!(f, "0x{:x}", operand)?;
+    fn format_instruction(&self, f: &mut std::fmt::Formatter<'_>, pc: usize) -> std::fmt::Result {
+        let opcode = self.u8(pc);
+        let line = format!("{:>4}: {:<13}", pc, MNEMONICS[opcode as usize]);
+        let branch = |offset: i32| pc as i64 + offset as i64;
+
+        match opcode {
+            0x10 => writeln!(f, "{} {}", line, self.u8(pc + 1) as i8),
+            0x11 => writeln!(f, "{} {}", line, self.u16(pc + 1) as i16),
+            0x12 => {
+                let index = self.u8(pc + 1) as u16;
+                self.with_constant(f, format!("{} #{}", line, index), index)
+            }
+            0x13 | 0x14 | 0xb2..=0xb8 | 0xbb | 0xbd | 0xc0 | 0xc1 => {
+                let index = self.u16(pc + 1);
+                self.with_constant(f, format!("{} #{}", line, index), index)
+            }
+            0x15..=0x19 | 0x36..=0x3a | 0xa9 => writeln!(f, "{} {}", line, self.u8(pc + 1)),
+            0x84 => writeln!(f, "{} {}, {}", line, self.u8(pc + 1), self.u8(pc + 2) as i8),
+            0x99..=0xa8 | 0xc6 | 0xc7 => {
+                writeln!(f, "{} {}", line, branch(self.u16(pc + 1) as i16 as i32))
+            }
+            0xc8 | 0xc9 => writeln!(f, "{} {}", line, branch(self.i32(pc + 1))),
+            0xb9 | 0xba | 0xc5 => {
+                let index = self.u16(pc + 1);
+                // invokedynamic has two zero bytes where the others have a count
+                let count = match opcode {
+                    0xba => 0,
+                    _ => self.u8(pc + 3),
+                };
+
+                self.with_constant(f, format!("{} #{},  {}", line, index, count), index)
+            }
+            0xbc => {
+                let ty = match self.u8(pc + 1) {
+                    4 => "boolean",
+                    5 => "char",
+                    6 => "float",
+                    7 => "double",
+                    8 => "byte",
+                    9 => "short",
+                    10 => "int",
+                    11 => "long",
+                    _ => "<invalid>",
+                };
+
+                writeln!(f, "{}  {}", line, ty)
+            }
+            0xaa | 0xab => {
+                let operands = (pc + 4) & !3;
+                let default = branch(self.i32(operands));
+
+                if opcode == 0xaa {
+                    let low = self.i32(operands + 4);
+                    let high = self.i32(operands + 8);
+                    writeln!(f, "{} {{ // {} to {}", line, low, high)?;
+
+                    for (i, key) in (low..=high).enumerate() {
+                        let target = branch(self.i32(operands + 12 + i * 4));
+                        writeln!(f, "{:>18}: {}", key, target)?;
+                    }
                 } else {
-                    write!(f, "0x{:x}, ", operand)?;
+                    let pairs = self.i32(operands + 4);
+                    writeln!(f, "{} {{ // {}", line, pairs)?;
+
+                    for i in 0..pairs.max(0) as usize {
+                        let pair = operands + 8 + i * 8;
+                        writeln!(f, "{:>18}: {}", self.i32(pair), branch(self.i32(pair + 4)))?;
+                    }
+                }
+
+                writeln!(f, "{:>18}: {}", "default", default)?;
+                writeln!(f, "      }}")
+            }
+            // wide
+            0xc4 => {
+                let modified = self.u8(pc + 1);
+                let mnemonic = format!("{}_w", MNEMONICS[modified as usize]);
+                let line = format!("{:>4}: {:<13}", pc, mnemonic);
+
+                match modified {
+                    0x84 => writeln!(
+                        f,
+                        "{} {}, {}",
+                        line,
+                        self.u16(pc + 2),
+                        self.u16(pc + 4) as i16
+                    ),
+                    _ => writeln!(f, "{} {}", line, self.u16(pc + 2)),
                 }
             }
-            write!(f, ")")?;
+            _ => writeln!(f, "{}", line.trim_end()),
         }
-        writeln!(f)?;
     }
-
-    Ok(())
 }
 
 // pub struct Add {}
@@ -344,3 +575,34 @@ fn format_instructions(data: &[u8], f: &mut std::fmt::Formatter<'_>) -> std::fmt
 // pub struct BiPush {}
 
 // impl_op!(BiPush, 0x10, 1);
+
+#[cfg(test)]
+mod test {
+    use super::Format;
+
+    #[test]
+    fn test_format_without_class() {
+        let code: &[u8] = &[
+            0x2a, // aload_0
+            0xb7, 0x00, 0x01, // invokespecial #1
+            0x1b, // iload_1
+            0xaa, 0x00, 0x00, // tableswitch, padded to 8
+            0x00, 0x00, 0x00, 0x14, // default -> 25
+            0x00, 0x00, 0x00, 0x00, // low
+            0x00, 0x00, 0x00, 0x00, // high
+            0x00, 0x00, 0x00, 0x14, // 0 -> 25
+            0xb1, // return
+        ];
+
+        let expected = "   0: aload_0\n\
+                        \x20  1: invokespecial #1\n\
+                        \x20  4: iload_1\n\
+                        \x20  5: tableswitch   { // 0 to 0\n\
+                        \x20                0: 25\n\
+                        \x20          default: 25\n\
+                        \x20     }\n\
+                        \x20 24: return\n";
+
+        assert_eq!(Format::from(code).to_string(), expected);
+    }
+}
@@ -0,0 +1,1324 @@
+//! A class file printer modelled on the JDK's `javap` tool.
+//!
+//! The output follows javap closely enough that the two can be diffed, which is a quick way to
+//! check how a class file was parsed.
+
+use std::{fs, path::Path, time::SystemTime};
+
+use bitflags::Flags;
+
+use crate::{
+    byte_stream::{ByteStream, StreamWrite},
+    bytecode::{
+        attribute_info::{self, Annotation, ElementValue, StackMapFrame, VerificationType},
+        Attribute, AttributeInfo, ClassAccess, ClassFile, ConstantPool, FieldAccess, FieldInfo,
+        MethodAccess, MethodInfo,
+    },
+    classpath,
+    error::Result,
+    instructions::{Format, COMMENT_COLUMN},
+    signature::{ClassType, GenericType, TypeArgument, TypeParameter},
+    value::{MethodDescriptor, Type, TypeKind},
+};
+
+const USAGE: &str = "Usage: jvm-rs javap [-c] [-v] [-p] <class file|jar!/entry>...";
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+    /// Disassemble method bodies (`-c`).
+    pub code: bool,
+    /// Print the constant pool, flags and every attribute (`-v`).
+    pub verbose: bool,
+    /// Include private members (`-p`).
+    pub private: bool,
+}
+
+/// Runs the `javap` subcommand with the arguments following it, returning the exit code.
+pub fn main(args: &[String]) -> i32 {
+    let mut options = Options::default();
+    let mut paths = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "-c" => options.code = true,
+            "-v" | "-verbose" => options.verbose = true,
+            "-p" | "-private" => options.private = true,
+            flag if flag.starts_with('-') => {
+                eprintln!("Error: invalid flag: {}", flag);
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+            path => paths.push(path),
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let mut status = 0;
+    for path in paths {
+        match disassemble(Path::new(path), options) {
+            Ok(output) => print!("{}", output),
+            Err(e) => {
+                eprintln!("Error: {}: {}", path, e);
+                status = 1;
+            }
+        }
+    }
+
+    status
+}
+
+/// Reads the class file at `path` and prints it the way javap would.
+pub fn disassemble(path: &Path, options: Options) -> Result<String> {
+    let data = classpath::read_class_file(&path.to_string_lossy())?;
+
+    let mut stream = ByteStream::new(&data);
+    let class = ClassFile::read(&mut stream)?;
+
+    let mut printer = Printer {
+        class: &class,
+        options,
+        out: String::new(),
+        indent: 0,
+    };
+
+    if options.verbose {
+        printer.file_header(path, data.len());
+    }
+    printer.class()?;
+
+    Ok(printer.out)
+}
+
+struct Printer<'a> {
+    class: &'a ClassFile,
+    options: Options,
+    out: String,
+    indent: usize,
+}
+
+impl Printer<'_> {
+    fn line(&mut self, text: impl AsRef<str>) {
+        // javap never leaves trailing whitespace, even after empty strings
+        let text = text.as_ref().trim_end();
+        if !text.is_empty() {
+            self.out.extend(std::iter::repeat_n(' ', self.indent));
+            self.out.push_str(text);
+        }
+        self.out.push('\n');
+    }
+
+    /// Writes a line followed by a comment, aligned to the same column javap uses.
+    fn commented(&mut self, text: impl AsRef<str>, comment: impl AsRef<str>) {
+        let text = text.as_ref();
+        let padding = COMMENT_COLUMN.saturating_sub(text.chars().count()).max(1);
+
+        self.line(format!("{}{:padding$}// {}", text, "", comment.as_ref()));
+    }
+
+    fn indented(&mut self, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        self.indent += 2;
+        let result = f(self);
+        self.indent -= 2;
+
+        result
+    }
+
+    fn constant(&self, index: u16) -> Result<String> {
+        self.class.describe_constant(index, false)
+    }
+
+    fn file_header(&mut self, path: &Path, size: usize) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.line(format!("Classfile {}", path.display()));
+
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified());
+        self.indent += 2;
+        match modified {
+            Ok(modified) => self.line(format!(
+                "Last modified {}; size {} bytes",
+                format_date(modified),
+                size
+            )),
+            Err(_) => self.line(format!("size {} bytes", size)),
+        }
+        self.indent -= 2;
+    }
+
+    fn class(&mut self) -> Result<()> {
+        let class = self.class;
+        let verbose = self.options.verbose;
+
+        if let Some(source_file) =
+            class
+                .attributes()
+                .iter()
+                .find_map(|attr| match attr.attribute() {
+                    Attribute::SourceFile(source_file) => Some(source_file.source_file_index),
+                    _ => None,
+                })
+        {
+            let source_file = class.utf8(source_file)?;
+            let indent = if verbose { 2 } else { 0 };
+
+            self.indent += indent;
+            self.line(format!("Compiled from \"{}\"", source_file));
+            self.indent -= indent;
+        }
+
+        let header = self.class_header()?;
+
+        if verbose {
+            self.line(header);
+            self.indented(|p| {
+                p.line(format!("minor version: {}", class.minor_version()));
+                p.line(format!("major version: {}", class.major_version()));
+                p.line(flags_line(class.access_flags()));
+                p.commented(
+                    format!("this_class: #{}", class.this_class()),
+                    p.constant(class.this_class())?,
+                );
+
+                match class.super_class() {
+                    0 => p.line("super_class: #0"),
+                    index => p.commented(format!("super_class: #{}", index), p.constant(index)?),
+                }
+
+                p.line(format!(
+                    "interfaces: {}, fields: {}, methods: {}, attributes: {}",
+                    class.interfaces().len(),
+                    class.fields().len(),
+                    class.methods().len(),
+                    class.attributes().len()
+                ));
+
+                Ok(())
+            })?;
+
+            self.line("Constant pool:");
+            self.indented(Self::constant_pool)?;
+            self.line("{");
+        } else {
+            self.line(format!("{} {{", header));
+        }
+
+        let separate = verbose || self.options.code;
+        let mut first = true;
+
+        self.indented(|p| {
+            for field in class.fields() {
+                if !p.options.private && field.access_flags().contains(FieldAccess::PRIVATE) {
+                    continue;
+                }
+
+                if separate && !first {
+                    p.out.push('\n');
+                }
+                first = false;
+
+                p.field(field)?;
+            }
+
+            for method in class.methods() {
+                if !p.options.private && method.access_flags().contains(MethodAccess::PRIVATE) {
+                    continue;
+                }
+
+                if separate && !first {
+                    p.out.push('\n');
+                }
+                first = false;
+
+                p.method(method)?;
+            }
+
+            Ok(())
+        })?;
+
+        self.line("}");
+
+        if verbose {
+            for attribute in class.attributes() {
+                self.attribute(attribute)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The class declaration, such as `public class java.util.ArrayList<E> extends ...`.
+    fn class_header(&self) -> Result<String> {
+        let class = self.class;
+        let flags = class.access_flags();
+        let is_interface = flags.contains(ClassAccess::INTERFACE);
+
+        let mut header = modifiers(&[
+            (flags.contains(ClassAccess::PUBLIC), "public"),
+            (
+                !is_interface && flags.contains(ClassAccess::ABSTRACT),
+                "abstract",
+            ),
+            (flags.contains(ClassAccess::FINAL), "final"),
+        ]);
+
+        header.push_str(match is_interface {
+            true => "interface ",
+            false => "class ",
+        });
+        header.push_str(&java_name(class.class_ref(class.this_class())?));
+
+        match class.signature()? {
+            Some(signature) => {
+                header.push_str(&type_parameters(&signature.type_parameters, self.options));
+
+                let interfaces: Vec<_> = signature.interfaces.iter().map(class_type).collect();
+
+                if is_interface {
+                    if !interfaces.is_empty() {
+                        header.push_str(&format!(" extends {}", interfaces.join(", ")));
+                    }
+                } else {
+                    if self.options.verbose || !is_object(&signature.superclass) {
+                        header.push_str(&format!(" extends {}", class_type(&signature.superclass)));
+                    }
+
+                    if !interfaces.is_empty() {
+                        header.push_str(&format!(" implements {}", interfaces.join(", ")));
+                    }
+                }
+            }
+            None => {
+                if !is_interface && class.super_class() != 0 {
+                    let superclass = class.class_ref(class.super_class())?;
+
+                    if superclass != "java/lang/Object" {
+                        header.push_str(&format!(" extends {}", java_name(superclass)));
+                    }
+                }
+
+                let interfaces = class
+                    .interfaces()
+                    .iter()
+                    .map(|index| class.class_ref(*index).map(java_name))
+                    .collect::<Result<Vec<_>>>()?;
+
+                if !interfaces.is_empty() {
+                    let keyword = if is_interface {
+                        "extends"
+                    } else {
+                        "implements"
+                    };
+                    header.push_str(&format!(" {} {}", keyword, interfaces.join(",")));
+                }
+            }
+        }
+
+        Ok(header)
+    }
+
+    fn constant_pool(&mut self) -> Result<()> {
+        let class = self.class;
+        let width = format!("#{}", class.constant_pool().len()).len();
+
+        for (index, constant) in class.constant_pool().iter().enumerate() {
+            let index = index as u16;
+
+            let (kind, value, comment) = match constant {
+                ConstantPool::Empty => continue,
+                ConstantPool::Utf8(_) => ("Utf8", self.constant(index)?, None),
+                ConstantPool::Integer(_) => ("Integer", self.constant(index)?, None),
+                ConstantPool::Float(_) => ("Float", self.constant(index)?, None),
+                ConstantPool::Long(_) => ("Long", self.constant(index)?, None),
+                ConstantPool::Double(_) => ("Double", self.constant(index)?, None),
+                ConstantPool::Class(c) => ("Class", format!("#{}", c.name_index), Some(index)),
+                ConstantPool::String(s) => ("String", format!("#{}", s.string_index), Some(index)),
+                ConstantPool::FieldRef(r) => ("Fieldref", member(r), Some(index)),
+                ConstantPool::MethodRef(r) => ("Methodref", member(r), Some(index)),
+                ConstantPool::InterfaceMethodRef(r) => {
+                    ("InterfaceMethodref", member(r), Some(index))
+                }
+                ConstantPool::NameAndType(nat) => (
+                    "NameAndType",
+                    format!("#{}:#{}", nat.name_index, nat.descriptor_index),
+                    Some(index),
+                ),
+                ConstantPool::MethodHandle(handle) => (
+                    "MethodHandle",
+                    format!("{}:#{}", handle.reference_kind, handle.reference_index),
+                    Some(index),
+                ),
+                ConstantPool::MethodType(method_type) => (
+                    "MethodType",
+                    format!("#{}", method_type.descriptor_index),
+                    Some(index),
+                ),
+                ConstantPool::Dynamic(dynamic) | ConstantPool::InvokeDynamic(dynamic) => (
+                    match constant {
+                        ConstantPool::Dynamic(_) => "Dynamic",
+                        _ => "InvokeDynamic",
+                    },
+                    format!(
+                        "#{}:#{}",
+                        dynamic.bootstrap_method_attr_index, dynamic.name_and_type_index
+                    ),
+                    Some(index),
+                ),
+                ConstantPool::Module(module) => {
+                    ("Module", format!("#{}", module.name_index), Some(index))
+                }
+                ConstantPool::Package(package) => {
+                    ("Package", format!("#{}", package.name_index), Some(index))
+                }
+            };
+
+            let text = format!("{:>width$} = {:<18} {}", format!("#{}", index), kind, value);
+
+            match comment {
+                // javap leaves an extra space before method types
+                Some(index) if matches!(constant, ConstantPool::MethodType(_)) => {
+                    self.commented(text, format!(" {}", self.constant(index)?))
+                }
+                Some(index) => self.commented(text, self.constant(index)?),
+                None => self.line(text),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn field(&mut self, field: &FieldInfo) -> Result<()> {
+        let class = self.class;
+        let flags = field.access_flags();
+        let descriptor = class.utf8(field.descriptor_index)?;
+
+        let ty = match field.signature(class)? {
+            Some(signature) => generic_type(&signature),
+            None => java_type(&Type::parse(descriptor)?),
+        };
+
+        let modifiers = modifiers(&[
+            (flags.contains(FieldAccess::PUBLIC), "public"),
+            (flags.contains(FieldAccess::PRIVATE), "private"),
+            (flags.contains(FieldAccess::PROTECTED), "protected"),
+            (flags.contains(FieldAccess::STATIC), "static"),
+            (flags.contains(FieldAccess::FINAL), "final"),
+            (flags.contains(FieldAccess::VOLATILE), "volatile"),
+            (flags.contains(FieldAccess::TRANSIENT), "transient"),
+        ]);
+
+        self.line(format!(
+            "{}{} {};",
+            modifiers,
+            ty,
+            class.utf8(field.name_index)?
+        ));
+
+        if self.options.verbose {
+            self.indented(|p| {
+                p.line(format!("descriptor: {}", descriptor));
+                p.line(flags_line(flags));
+
+                for attribute in field.attributes() {
+                    p.attribute(attribute)?;
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn method(&mut self, method: &MethodInfo) -> Result<()> {
+        let class = self.class;
+        let flags = method.access_flags();
+        let name = class.utf8(method.name_index)?;
+        let descriptor = class.utf8(method.descriptor_index)?;
+        let parsed = MethodDescriptor::parse(descriptor)?;
+        let signature = method.signature(class)?;
+
+        let is_default = class.is_interface()
+            && !flags.intersects(MethodAccess::ABSTRACT | MethodAccess::STATIC)
+            && !flags.contains(MethodAccess::PRIVATE)
+            && name != "<clinit>";
+
+        let mut declaration = modifiers(&[
+            (flags.contains(MethodAccess::PUBLIC), "public"),
+            (flags.contains(MethodAccess::PRIVATE), "private"),
+            (flags.contains(MethodAccess::PROTECTED), "protected"),
+            (flags.contains(MethodAccess::STATIC), "static"),
+            (flags.contains(MethodAccess::FINAL), "final"),
+            (flags.contains(MethodAccess::SYNCHRONIZED), "synchronized"),
+            (flags.contains(MethodAccess::NATIVE), "native"),
+            (flags.contains(MethodAccess::ABSTRACT), "abstract"),
+            (flags.contains(MethodAccess::STRICT), "strictfp"),
+            (is_default, "default"),
+        ]);
+
+        let (mut params, return_ty, mut throws) = match &signature {
+            Some(signature) => {
+                let type_parameters = type_parameters(&signature.type_parameters, self.options);
+                if !type_parameters.is_empty() {
+                    declaration.push_str(&type_parameters);
+                    declaration.push(' ');
+                }
+
+                (
+                    signature.params.iter().map(generic_type).collect(),
+                    signature.return_ty.as_ref().map(generic_type),
+                    signature.throws.iter().map(generic_type).collect(),
+                )
+            }
+            None => (
+                parsed.params.iter().map(java_type).collect::<Vec<_>>(),
+                parsed.return_ty.as_ref().map(java_type),
+                Vec::new(),
+            ),
+        };
+
+        if throws.is_empty() {
+            throws = exceptions(class, method.attributes())?;
+        }
+
+        if flags.contains(MethodAccess::VARARGS) {
+            if let Some(last) = params.last_mut().and_then(|last| last.strip_suffix("[]")) {
+                let last = format!("{}...", last);
+                *params.last_mut().unwrap() = last;
+            }
+        }
+
+        match name {
+            // javap shows static initializers as they're written, without their flags
+            "<clinit>" => declaration = "static {}".to_string(),
+            "<init>" => {
+                declaration.push_str(&java_name(class.class_ref(class.this_class())?));
+                declaration.push_str(&format!("({})", params.join(", ")));
+            }
+            _ => {
+                let return_ty = return_ty.unwrap_or_else(|| "void".to_string());
+                declaration.push_str(&format!("{} {}({})", return_ty, name, params.join(", ")));
+            }
+        }
+
+        if !throws.is_empty() {
+            declaration.push_str(&format!(" throws {}", throws.join(", ")));
+        }
+
+        self.line(format!("{};", declaration));
+
+        self.indented(|p| {
+            if p.options.verbose {
+                p.line(format!("descriptor: {}", descriptor));
+                p.line(flags_line(flags));
+
+                for attribute in method.attributes() {
+                    match attribute.attribute() {
+                        Attribute::Code(code) => p.code(code, &parsed, flags)?,
+                        _ => p.attribute(attribute)?,
+                    }
+                }
+            } else if p.options.code {
+                if let Some(code) = method.code() {
+                    p.code(&code, &parsed, flags)?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    fn code(
+        &mut self,
+        code: &attribute_info::Code,
+        descriptor: &MethodDescriptor,
+        flags: MethodAccess,
+    ) -> Result<()> {
+        let class = self.class;
+        let verbose = self.options.verbose;
+
+        self.line("Code:");
+
+        // Everything but the header is only indented in verbose mode
+        let indent = if verbose { 2 } else { 0 };
+        self.indent += indent;
+
+        if verbose {
+            // Unlike max_locals, javap counts long and double parameters once
+            let args_size =
+                descriptor.params.len() + !flags.contains(MethodAccess::STATIC) as usize;
+
+            self.line(format!(
+                "stack={}, locals={}, args_size={}",
+                code.max_stack, code.max_locals, args_size
+            ));
+        }
+
+        for line in Format::with_class(&code.instructions, class)
+            .to_string()
+            .lines()
+        {
+            self.line(line);
+        }
+
+        if !code.exception_table.is_empty() {
+            self.line("Exception table:");
+            self.line("   from    to  target type");
+
+            for entry in &code.exception_table {
+                let ty = match entry.catch_type {
+                    0 => "any".to_string(),
+                    index => format!("Class {}", self.constant(index)?),
+                };
+
+                self.line(format!(
+                    "   {:>5} {:>5} {:>5}   {}",
+                    entry.start_pc, entry.end_pc, entry.handler_pc, ty
+                ));
+            }
+        }
+
+        let result = match verbose {
+            true => code
+                .attributes
+                .iter()
+                .try_for_each(|attribute| self.attribute(attribute)),
+            false => Ok(()),
+        };
+
+        self.indent -= indent;
+        result
+    }
+
+    /// Prints a class, field, method or code attribute in verbose mode.
+    fn attribute(&mut self, info: &AttributeInfo) -> Result<()> {
+        let class = self.class;
+
+        match info.attribute() {
+            Attribute::SourceFile(source_file) => self.line(format!(
+                "SourceFile: \"{}\"",
+                class.utf8(source_file.source_file_index)?
+            )),
+            Attribute::Signature(signature) => self.commented(
+                format!("Signature: #{}", signature.signature_index),
+                self.constant(signature.signature_index)?,
+            ),
+            Attribute::ConstantValue(value) => {
+                let index = value.constantvalue_index;
+                let kind = match class.pool(index as usize) {
+                    ConstantPool::Integer(_) => "int",
+                    ConstantPool::Float(_) => "float",
+                    ConstantPool::Long(_) => "long",
+                    ConstantPool::Double(_) => "double",
+                    _ => "String",
+                };
+
+                self.line(format!("ConstantValue: {} {}", kind, self.constant(index)?));
+            }
+            Attribute::Deprecated(_) => self.line("Deprecated: true"),
+            Attribute::Synthetic(_) => self.line("Synthetic: true"),
+            Attribute::Exceptions(_) => {
+                let throws = exceptions(class, std::slice::from_ref(info))?;
+
+                self.line("Exceptions:");
+                self.indented(|p| {
+                    p.line(format!("throws {}", throws.join(", ")));
+                    Ok(())
+                })?;
+            }
+            Attribute::LineNumberTable(table) => {
+                self.line("LineNumberTable:");
+                self.indented(|p| {
+                    for entry in &table.entries {
+                        p.line(format!("line {}: {}", entry.line_number, entry.start_pc));
+                    }
+
+                    Ok(())
+                })?;
+            }
+            Attribute::LocalVariableTable(table) => {
+                let entries = table.entries.iter().map(|entry| {
+                    let variable = (entry.start_pc, entry.length, entry.index, entry.name_index);
+                    (variable, entry.descriptor_index)
+                });
+
+                self.local_variables("LocalVariableTable", entries.collect())?;
+            }
+            Attribute::LocalVariableTypeTable(table) => {
+                let entries = table.entries.iter().map(|entry| {
+                    let variable = (entry.start_pc, entry.length, entry.index, entry.name_index);
+                    (variable, entry.signature_index)
+                });
+
+                self.local_variables("LocalVariableTypeTable", entries.collect())?;
+            }
+            Attribute::StackMapTable(table) => {
+                self.line(format!(
+                    "StackMapTable: number_of_entries = {}",
+                    table.entries.len()
+                ));
+                self.indented(|p| {
+                    table
+                        .entries
+                        .iter()
+                        .try_for_each(|frame| p.stack_map_frame(frame))
+                })?;
+            }
+            Attribute::InnerClasses(inner_classes) => {
+                self.line("InnerClasses:");
+                self.indented(|p| {
+                    for inner in &inner_classes.classes {
+                        p.inner_class(inner)?;
+                    }
+
+                    Ok(())
+                })?;
+            }
+            Attribute::EnclosingMethod(enclosing) => {
+                let mut comment = java_name(class.class_ref(enclosing.class_index)?);
+                if enclosing.method_index != 0 {
+                    let (name, _) = class.name_and_type(enclosing.method_index)?;
+                    comment.push('.');
+                    comment.push_str(name);
+                }
+
+                self.commented(
+                    format!(
+                        "EnclosingMethod: #{}.#{}",
+                        enclosing.class_index, enclosing.method_index
+                    ),
+                    comment,
+                );
+            }
+            Attribute::NestHost(host) => self.line(format!(
+                "NestHost: class {}",
+                self.constant(host.host_class_index)?
+            )),
+            Attribute::NestMembers(members) => self.class_list("NestMembers", &members.classes)?,
+            Attribute::PermittedSubclasses(subclasses) => {
+                self.class_list("PermittedSubclasses", &subclasses.classes)?
+            }
+            Attribute::BootstrapMethods(methods) => {
+                self.line("BootstrapMethods:");
+                self.indented(|p| {
+                    for (i, method) in methods.bootstrap_methods.iter().enumerate() {
+                        p.line(format!(
+                            "{}: #{} {}",
+                            i,
+                            method.bootstrap_method_ref,
+                            p.constant(method.bootstrap_method_ref)?
+                        ));
+
+                        p.indented(|p| {
+                            p.line("Method arguments:");
+                            p.indented(|p| {
+                                for argument in &method.bootstrap_arguments {
+                                    p.line(format!("#{} {}", argument, p.constant(*argument)?));
+                                }
+
+                                Ok(())
+                            })
+                        })?;
+                    }
+
+                    Ok(())
+                })?;
+            }
+            Attribute::MethodParameters(parameters) => {
+                self.line("MethodParameters:");
+                self.indented(|p| {
+                    p.line(format!("{:<30} {}", "Name", "Flags"));
+
+                    for parameter in &parameters.parameters {
+                        let name = match parameter.name_index {
+                            0 => "<no name>".to_string(),
+                            index => p.constant(index)?,
+                        };
+
+                        let flags = modifiers(&[
+                            (parameter.access_flags & 0x0010 != 0, "final"),
+                            (parameter.access_flags & 0x1000 != 0, "synthetic"),
+                            (parameter.access_flags & 0x8000 != 0, "mandated"),
+                        ]);
+
+                        p.line(format!("{:<30} {}", name, flags).trim_end());
+                    }
+
+                    Ok(())
+                })?;
+            }
+            Attribute::Record(record) => {
+                self.line("Record:");
+                self.indented(|p| {
+                    for component in &record.components {
+                        p.record_component(component)?;
+                    }
+
+                    Ok(())
+                })?;
+            }
+            Attribute::RuntimeVisibleAnnotations(annotations) => {
+                self.annotations("RuntimeVisibleAnnotations", &annotations.annotations)?
+            }
+            Attribute::RuntimeInvisibleAnnotations(annotations) => {
+                self.annotations("RuntimeInvisibleAnnotations", &annotations.annotations)?
+            }
+            Attribute::AnnotationDefault(default) => {
+                self.line("AnnotationDefault:");
+                self.indented(|p| {
+                    p.line(format!(
+                        "default_value: {}",
+                        raw_element_value(&default.default_value)
+                    ));
+                    p.indented(|p| {
+                        let value = p.element_value(&default.default_value)?;
+                        p.line(value);
+                        Ok(())
+                    })
+                })?;
+            }
+            attribute => {
+                let mut body = Vec::new();
+                attribute.write(&mut body);
+
+                let name = class.utf8(info.name_index())?;
+                self.line(format!("{}: length = 0x{:X}", name, body.len()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn local_variables(
+        &mut self,
+        name: &str,
+        entries: Vec<((u16, u16, u16, u16), u16)>,
+    ) -> Result<()> {
+        self.line(format!("{}:", name));
+        self.indented(|p| {
+            p.line("Start  Length  Slot  Name   Signature");
+
+            for ((start_pc, length, slot, name_index), type_index) in entries {
+                p.line(format!(
+                    "{:>5} {:>7} {:>5} {:>5}   {}",
+                    start_pc,
+                    length,
+                    slot,
+                    p.constant(name_index)?,
+                    p.constant(type_index)?
+                ));
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Prints a record component like a field, followed by a blank line as javap does.
+    fn record_component(&mut self, component: &attribute_info::RecordComponent) -> Result<()> {
+        let class = self.class;
+        let descriptor = class.utf8(component.descriptor_index)?;
+
+        let signature = component
+            .attributes
+            .iter()
+            .find_map(|attr| match attr.attribute() {
+                Attribute::Signature(signature) => Some(signature.signature_index),
+                _ => None,
+            });
+        let ty = match signature {
+            Some(index) => generic_type(&GenericType::parse_field(class.utf8(index)?)?),
+            None => java_type(&Type::parse(descriptor)?),
+        };
+
+        self.line(format!("{} {};", ty, class.utf8(component.name_index)?));
+        self.indented(|p| {
+            p.line(format!("descriptor: {}", descriptor));
+
+            for attribute in &component.attributes {
+                p.attribute(attribute)?;
+            }
+
+            Ok(())
+        })?;
+        self.line("");
+
+        Ok(())
+    }
+
+    fn stack_map_frame(&mut self, frame: &StackMapFrame) -> Result<()> {
+        let kind = match frame {
+            StackMapFrame::Same { .. } => "same",
+            StackMapFrame::SameLocals1StackItem { .. } => "same_locals_1_stack_item",
+            StackMapFrame::SameLocals1StackItemExtended { .. } => {
+                "same_locals_1_stack_item_frame_extended"
+            }
+            StackMapFrame::Chop { .. } => "chop",
+            StackMapFrame::SameExtended { .. } => "same_frame_extended",
+            StackMapFrame::Append { .. } => "append",
+            StackMapFrame::Full { .. } => "full_frame",
+        };
+        self.line(format!(
+            "frame_type = {} /* {} */",
+            frame.frame_type(),
+            kind
+        ));
+
+        self.indented(|p| {
+            if !matches!(
+                frame,
+                StackMapFrame::Same { .. } | StackMapFrame::SameLocals1StackItem { .. }
+            ) {
+                p.line(format!("offset_delta = {}", frame.offset_delta()));
+            }
+
+            match frame {
+                StackMapFrame::SameLocals1StackItem { stack, .. }
+                | StackMapFrame::SameLocals1StackItemExtended { stack, .. } => {
+                    p.line(format!("stack = {}", p.verification_types(&[*stack])?));
+                }
+                StackMapFrame::Append { locals, .. } => {
+                    p.line(format!("locals = {}", p.verification_types(locals)?));
+                }
+                StackMapFrame::Full { locals, stack, .. } => {
+                    p.line(format!("locals = {}", p.verification_types(locals)?));
+                    p.line(format!("stack = {}", p.verification_types(stack)?));
+                }
+                _ => (),
+            }
+
+            Ok(())
+        })
+    }
+
+    fn verification_types(&self, types: &[VerificationType]) -> Result<String> {
+        if types.is_empty() {
+            return Ok("[]".to_string());
+        }
+
+        let types = types
+            .iter()
+            .map(|ty| {
+                Ok(match ty {
+                    VerificationType::Top => "top".to_string(),
+                    VerificationType::Integer => "int".to_string(),
+                    VerificationType::Float => "float".to_string(),
+                    VerificationType::Double => "double".to_string(),
+                    VerificationType::Long => "long".to_string(),
+                    VerificationType::Null => "null".to_string(),
+                    VerificationType::UninitializedThis => "this".to_string(),
+                    VerificationType::Object { cpool_index } => {
+                        format!("class {}", self.constant(*cpool_index)?)
+                    }
+                    VerificationType::Uninitialized { offset } => {
+                        format!("uninitialized {}", offset)
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(format!("[ {} ]", types.join(", ")))
+    }
+
+    fn inner_class(&mut self, inner: &attribute_info::InnerClass) -> Result<()> {
+        let flags = inner.inner_class_access_flags;
+        let is_interface = flags & 0x0200 != 0;
+
+        let mut text = modifiers(&[
+            (flags & 0x0001 != 0, "public"),
+            (flags & 0x0002 != 0, "private"),
+            (flags & 0x0004 != 0, "protected"),
+            (flags & 0x0008 != 0, "static"),
+            (flags & 0x0010 != 0, "final"),
+            (!is_interface && flags & 0x0400 != 0, "abstract"),
+        ]);
+        let mut comment = String::new();
+
+        if inner.inner_name_index != 0 {
+            text.push_str(&format!("#{}= ", inner.inner_name_index));
+            comment.push_str(&format!("{}=", self.constant(inner.inner_name_index)?));
+        }
+
+        text.push_str(&format!("#{}", inner.inner_class_info_index));
+        comment.push_str(&format!(
+            "class {}",
+            self.constant(inner.inner_class_info_index)?
+        ));
+
+        if inner.outer_class_info_index != 0 {
+            text.push_str(&format!(" of #{}", inner.outer_class_info_index));
+            comment.push_str(&format!(
+                " of class {}",
+                self.constant(inner.outer_class_info_index)?
+            ));
+        }
+
+        text.push(';');
+        self.commented(text, comment);
+
+        Ok(())
+    }
+
+    fn class_list(&mut self, name: &str, classes: &[u16]) -> Result<()> {
+        self.line(format!("{}:", name));
+        self.indented(|p| {
+            for class in classes {
+                let name = p.constant(*class)?;
+                p.line(name);
+            }
+
+            Ok(())
+        })
+    }
+
+    fn annotations(&mut self, name: &str, annotations: &[Annotation]) -> Result<()> {
+        self.line(format!("{}:", name));
+        self.indented(|p| {
+            for (i, annotation) in annotations.iter().enumerate() {
+                p.line(format!("{}: {}", i, raw_annotation(annotation)));
+                p.indented(|p| p.annotation(annotation))?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Prints an annotation in Java syntax, with one element per line.
+    fn annotation(&mut self, annotation: &Annotation) -> Result<()> {
+        let name = java_type(&Type::parse(&annotation.type_descriptor)?);
+
+        if annotation.elements.is_empty() {
+            self.line(name);
+            return Ok(());
+        }
+
+        self.line(format!("{}(", name));
+        self.indented(|p| {
+            for element in &annotation.elements {
+                let value = p.element_value(&element.value)?;
+                p.line(format!("{}={}", element.name, value));
+            }
+
+            Ok(())
+        })?;
+        self.line(")");
+
+        Ok(())
+    }
+
+    fn element_value(&self, value: &ElementValue) -> Result<String> {
+        let class = self.class;
+
+        Ok(match value {
+            ElementValue::Const {
+                tag,
+                const_value_index,
+            } => {
+                let constant = self.constant(*const_value_index)?;
+
+                match tag {
+                    b's' => format!("\"{}\"", constant),
+                    b'Z' => (constant != "0").to_string(),
+                    b'C' => {
+                        let c = constant
+                            .parse::<u32>()
+                            .ok()
+                            .and_then(char::from_u32)
+                            .unwrap_or(char::REPLACEMENT_CHARACTER);
+                        format!("'{}'", c)
+                    }
+                    _ => constant,
+                }
+            }
+            ElementValue::Enum {
+                type_name_index,
+                const_name_index,
+            } => format!(
+                "{}.{}",
+                class.utf8(*type_name_index)?,
+                class.utf8(*const_name_index)?
+            ),
+            ElementValue::Class { class_info_index } => {
+                format!("class {}", class.utf8(*class_info_index)?)
+            }
+            ElementValue::Annotation(annotation) => {
+                let name = java_type(&Type::parse(&annotation.type_descriptor)?);
+                let elements = annotation
+                    .elements
+                    .iter()
+                    .map(|element| {
+                        Ok(format!(
+                            "{}={}",
+                            element.name,
+                            self.element_value(&element.value)?
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                format!("@{}({})", name, elements.join(","))
+            }
+            ElementValue::Array(values) => {
+                let values = values
+                    .iter()
+                    .map(|value| self.element_value(value))
+                    .collect::<Result<Vec<_>>>()?;
+
+                format!("[{}]", values.join(","))
+            }
+        })
+    }
+}
+
+/// Joins the names whose condition holds, each followed by a space.
+fn modifiers(names: &[(bool, &str)]) -> String {
+    names
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| format!("{} ", name))
+        .collect()
+}
+
+/// A `flags:` line listing the raw value and the name of each flag that's set.
+fn flags_line<F: Flags<Bits = u16>>(flags: F) -> String {
+    let names: Vec<_> = flags
+        .iter_names()
+        .map(|(name, _)| format!("ACC_{}", name))
+        .collect();
+
+    format!("flags: (0x{:04x}) {}", flags.bits(), names.join(", "))
+        .trim_end()
+        .to_string()
+}
+
+/// The classes named in any `Exceptions` attributes among `attributes`.
+fn exceptions(class: &ClassFile, attributes: &[AttributeInfo]) -> Result<Vec<String>> {
+    let mut throws = Vec::new();
+
+    for attribute in attributes {
+        if let Attribute::Exceptions(exceptions) = attribute.attribute() {
+            for index in &exceptions.exception_index_table {
+                throws.push(java_name(class.class_ref(*index)?));
+            }
+        }
+    }
+
+    Ok(throws)
+}
+
+fn member(member: &crate::bytecode::constant_pool::Ref) -> String {
+    format!("#{}.#{}", member.class_index, member.name_and_type_index)
+}
+
+/// Converts a binary name such as `java/lang/String` to `java.lang.String`.
+fn java_name(name: &str) -> String {
+    name.replace('/', ".")
+}
+
+fn java_type(ty: &Type) -> String {
+    let mut name = match ty.kind() {
+        TypeKind::Boolean => "boolean".to_string(),
+        TypeKind::Char => "char".to_string(),
+        TypeKind::Byte => "byte".to_string(),
+        TypeKind::Short => "short".to_string(),
+        TypeKind::Int => "int".to_string(),
+        TypeKind::Long => "long".to_string(),
+        TypeKind::Float => "float".to_string(),
+        TypeKind::Double => "double".to_string(),
+        TypeKind::Reference => "java.lang.Object".to_string(),
+        TypeKind::Class(name) => java_name(name),
+    };
+
+    for _ in 0..ty.array_dimensions() {
+        name.push_str("[]");
+    }
+
+    name
+}
+
+fn generic_type(ty: &GenericType) -> String {
+    match ty {
+        GenericType::Base(kind) => java_type(&Type::new(kind.clone(), 0)),
+        GenericType::Class(class) => class_type(class),
+        GenericType::TypeVariable(name) => name.clone(),
+        GenericType::Array(element) => format!("{}[]", generic_type(element)),
+    }
+}
+
+fn class_type(class: &ClassType) -> String {
+    let mut name = java_name(&class.name);
+    name.push_str(&type_arguments(&class.type_arguments));
+
+    for inner in &class.inner {
+        name.push('.');
+        name.push_str(&inner.name);
+        name.push_str(&type_arguments(&inner.type_arguments));
+    }
+
+    name
+}
+
+fn type_arguments(arguments: &[TypeArgument]) -> String {
+    if arguments.is_empty() {
+        return String::new();
+    }
+
+    let arguments: Vec<_> = arguments
+        .iter()
+        .map(|argument| match argument {
+            TypeArgument::Any => "?".to_string(),
+            TypeArgument::Exact(ty) => generic_type(ty),
+            TypeArgument::Extends(ty) => format!("? extends {}", generic_type(ty)),
+            TypeArgument::Super(ty) => format!("? super {}", generic_type(ty)),
+        })
+        .collect();
+
+    format!("<{}>", arguments.join(", "))
+}
+
+/// Type parameters such as `<T extends java.lang.Comparable<? super T>>`.
+///
+/// Like javap, a class bound of `java.lang.Object` is only shown in verbose mode.
+fn type_parameters(parameters: &[TypeParameter], options: Options) -> String {
+    if parameters.is_empty() {
+        return String::new();
+    }
+
+    let parameters: Vec<_> = parameters
+        .iter()
+        .map(|parameter| {
+            let class_bound = parameter.class_bound.as_ref().filter(|bound| {
+                options.verbose || !matches!(bound, GenericType::Class(class) if is_object(class))
+            });
+
+            let bounds: Vec<_> = class_bound
+                .into_iter()
+                .chain(&parameter.interface_bounds)
+                .map(generic_type)
+                .collect();
+
+            match bounds.is_empty() {
+                true => parameter.name.clone(),
+                false => format!("{} extends {}", parameter.name, bounds.join(" & ")),
+            }
+        })
+        .collect();
+
+    format!("<{}>", parameters.join(", "))
+}
+
+fn is_object(class: &ClassType) -> bool {
+    class.name == "java/lang/Object" && class.type_arguments.is_empty() && class.inner.is_empty()
+}
+
+/// The annotation as javap's first line shows it, with constant pool indices.
+fn raw_annotation(annotation: &Annotation) -> String {
+    let elements: Vec<_> = annotation
+        .elements
+        .iter()
+        .map(|element| {
+            format!(
+                "#{}={}",
+                element.name_index,
+                raw_element_value(&element.value)
+            )
+        })
+        .collect();
+
+    format!("#{}({})", annotation.type_index, elements.join(","))
+}
+
+fn raw_element_value(value: &ElementValue) -> String {
+    match value {
+        ElementValue::Const {
+            tag,
+            const_value_index,
+        } => format!("{}#{}", *tag as char, const_value_index),
+        ElementValue::Enum {
+            type_name_index,
+            const_name_index,
+        } => format!("e#{}.#{}", type_name_index, const_name_index),
+        ElementValue::Class { class_info_index } => format!("c#{}", class_info_index),
+        ElementValue::Annotation(annotation) => format!("@{}", raw_annotation(annotation)),
+        ElementValue::Array(values) => {
+            let values: Vec<_> = values.iter().map(raw_element_value).collect();
+            format!("[{}]", values.join(","))
+        }
+    }
+}
+
+/// Formats a time as a UTC date such as `Oct 18, 2026`.
+fn format_date(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let days = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / 86400)
+        .unwrap_or(0) as i64;
+
+    // Converts days since 1970-01-01 to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!("{} {}, {}", MONTHS[month as usize - 1], day, year)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{disassemble, Options};
+
+    /// Compares against the output of JDK 17's javap. The lines giving the file's location and
+    /// modification time are left out of both, and javap's checksum line isn't printed.
+    #[test]
+    fn test_disassemble() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../examples/Main.class");
+        let options = |code, verbose, private| Options {
+            code,
+            verbose,
+            private,
+        };
+
+        for (options, expected) in [
+            (
+                options(true, false, false),
+                include_str!("../../examples/javap/Main.c.txt"),
+            ),
+            (
+                options(false, false, true),
+                include_str!("../../examples/javap/Main.p.txt"),
+            ),
+            (
+                options(false, true, false),
+                include_str!("../../examples/javap/Main.v.txt"),
+            ),
+            (
+                options(true, true, true),
+                include_str!("../../examples/javap/Main.v.txt"),
+            ),
+        ] {
+            let output = disassemble(&path, options).unwrap();
+            let output: String = output
+                .lines()
+                .filter(|line| {
+                    !line.starts_with("Classfile ") && !line.starts_with("  Last modified ")
+                })
+                .flat_map(|line| [line, "\n"])
+                .collect();
+
+            assert_eq!(output, expected, "{:?}", options);
+        }
+    }
+}
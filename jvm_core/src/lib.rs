@@ -0,0 +1,21 @@
+//! A Java virtual machine, and the class file tools it's built on.
+//!
+//! Embedders create a [`runtime::Runtime`] with the class paths to load from, then add their own
+//! class loaders, define classes and start threads through it. The `jvm-rs` binary is one such
+//! embedder.
+
+pub mod byte_stream;
+pub mod bytecode;
+pub mod class_loader;
+pub mod classpath;
+pub mod code;
+pub mod dump;
+pub mod error;
+mod frame;
+pub mod instructions;
+pub mod javap;
+pub mod rf;
+pub mod runtime;
+pub mod signature;
+pub mod thread;
+pub mod value;
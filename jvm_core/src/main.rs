@@ -1,19 +1,31 @@
-#![feature(concat_idents)]
-use std::{fs::File, io::Read, path::Path};
+use std::path::Path;
 
 use tracing_subscriber::EnvFilter;
 
-use crate::rf::Rf;
+use jvm_core::{
+    byte_stream::ByteStream,
+    bytecode::ClassFile,
+    classpath::{
+        self,
+        jar::{JarSource, Manifest},
+        jdk::JdkSource,
+        ClassPath, DirectorySource, MemorySource,
+    },
+    dump,
+    error::{Result, VmError},
+    javap,
+    rf::Rf,
+    runtime,
+};
 
-mod byte_stream;
-mod bytecode;
-mod error;
-mod frame;
-mod instructions;
-mod rf;
-mod runtime;
-mod thread;
-mod value;
+const USAGE: &str = "\
+Usage: jvm-rs [--jdk-home <dir>] [-cp <class path>] <main class>
+       jvm-rs [--jdk-home <dir>] -jar <jar file>
+       jvm-rs [--jdk-home <dir>] <class file>
+       jvm-rs javap [-c] [-v] [-p] <class file|jar!/entry>...
+       jvm-rs dump [--json] <class file|jar!/entry>...
+
+--jdk-home defaults to $JAVA_HOME.";
 
 fn main() {
     tracing_subscriber::fmt()
@@ -22,22 +34,86 @@ fn main() {
         .with_line_number(true)
         .init();
 
-    let path = Path::new("examples/Main.class");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("javap") => std::process::exit(javap::main(&args[1..])),
+        Some("dump") => std::process::exit(dump::main(&args[1..])),
+        _ => (),
+    }
+
+    let (runtime, main_class) = match launch_options(&args) {
+        Ok(Some(launch)) => launch,
+        Ok(None) => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let (status, _) = runtime::Runtime::start(Rf::new(runtime), &main_class);
+    if let Err(e) = status {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Sets up the runtime and works out the main class from the command line, the way `java` does.
+///
+/// Returns `None` if the arguments don't make sense.
+fn launch_options(args: &[String]) -> Result<Option<(runtime::Runtime, String)>> {
+    // Options for the VM itself come before the ones saying what to run
+    let (jdk_home, args) = match args {
+        [flag, home, args @ ..] if flag == "--jdk-home" => (Some(home.clone()), args),
+        _ => (std::env::var("JAVA_HOME").ok(), args),
+    };
 
-    let mut file = File::open(path).unwrap();
-    let mut buf = Vec::with_capacity(1024);
+    // Every class extends java/lang/Object, so little runs without the JDK's classes
+    let mut boot_class_path = ClassPath::new();
+    if let Some(home) = jdk_home {
+        boot_class_path.push(JdkSource::open(home)?);
+    }
 
-    file.read_to_end(&mut buf).unwrap();
+    let (class_path, main_class) = match args {
+        [flag, jar] if flag == "-jar" => {
+            let jar = JarSource::open(jar)?;
+            let main_class = jar
+                .manifest()
+                .and_then(Manifest::main_class)
+                .map(|name| name.replace('.', "/"))
+                .ok_or_else(|| VmError::InvalidJar {
+                    path: jar.path().display().to_string(),
+                    reason: "no Main-Class in the manifest".to_string(),
+                })?;
 
-    let mut stream = byte_stream::ByteStream::new(&buf);
-    let file = bytecode::ClassFile::read(&mut stream);
+            (ClassPath::for_jar(jar)?, main_class)
+        }
+        [flag, class_path, main_class] if matches!(flag.as_str(), "-cp" | "-classpath") => {
+            (ClassPath::parse(class_path)?, main_class.replace('.', "/"))
+        }
+        // A class file that may not be laid out by package, such as the examples
+        [path] if path.ends_with(".class") => {
+            let data = classpath::read_class_file(path)?;
+            let main_class = ClassFile::read(&mut ByteStream::new(&data))?
+                .class_name()
+                .to_string();
+            let directory = Path::new(path).parent().unwrap_or(Path::new(""));
 
-    println!("{:#?}", file);
+            let class_path = ClassPath::new()
+                .with(MemorySource::new().with(main_class.as_str(), data))
+                .with(DirectorySource::new(directory));
 
-    let runtime = Rf::new(runtime::Runtime::new(vec![file]));
-    let (status, thread) = runtime::Runtime::start(runtime.clone(), "Test/Main");
+            (class_path, main_class)
+        }
+        [main_class] if !main_class.starts_with('-') => {
+            let class_path = std::env::var("CLASSPATH").unwrap_or_else(|_| ".".to_string());
+            (ClassPath::parse(&class_path)?, main_class.replace('.', "/"))
+        }
+        _ => return Ok(None),
+    };
 
-    let rt = runtime.borrow();
-    // println!("{:#?}", rt);
-    // println!("{:?} {:#?}", status, thread);
+    let runtime = runtime::Runtime::new(class_path).with_boot_class_path(boot_class_path);
+    Ok(Some((runtime, main_class)))
 }
@@ -4,185 +4,609 @@ use std::{
 };
 
 use crate::{
-    bytecode::{ClassFile, ConstantPool},
+    byte_stream::ByteStream,
+    bytecode::{ClassFile, ConstantPool, MemberKind, MethodAccess},
+    class_loader::{ClassId, ClassLoader, LoaderDelegate, LoaderId},
+    classpath::ClassPath,
+    code::{CodeLocation, CodeSpace},
     error::{Result, VmError},
     frame::Frame,
     instructions,
     rf::Rf,
-    thread::Thread,
-    value::{runtime_pool, RuntimePool, Type, Value},
+    thread::{Thread, ThreadId},
+    value::{
+        runtime_pool::{self, ClassRef, FieldRef, FieldSlot, Layout, MethodRef, Resolved},
+        MethodDescriptor, RuntimePool, Type, TypeKind, Value,
+    },
 };
 
-pub struct Runtime {
-    initialized: HashSet<String>,
-
-    class_files: HashMap<String, ClassFile>,
+/// How far a linked class has got through initialization (JVMS 5.5).
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitState {
+    Linked,
+    /// `<clinit>` is running on the thread, or waiting for its superclasses' to finish.
+    BeingInitialized(ThreadId),
+    Initialized,
+    /// Initialization failed for the given reason, so the class can't be used.
+    Erroneous(String),
+}
 
-    code_pool: Vec<u8>,
-    runtime_pool: HashMap<Rc<str>, RuntimePool>,
+type NativeFunction = Box<dyn Fn(&[Value]) -> Option<Value>>;
 
-    funtions: HashMap<String, Box<dyn Fn(&[Value]) -> Option<Value>>>,
+pub struct Runtime {
+    /// Classes whose supertypes are being linked, to detect circular hierarchies.
+    linking: HashSet<ClassId>,
+
+    /// Indexed by `LoaderId`, starting with the bootstrap, platform and application loaders.
+    /// Unloaded loaders leave a gap, so their ids aren't reused.
+    loaders: Vec<Option<ClassLoader>>,
+    /// Every class defined so far, parsed on first reference.
+    class_files: HashMap<ClassId, ClassFile>,
+
+    code: CodeSpace,
+    /// Every linked class, indexed by `ClassRef`. Unloaded classes leave a gap, like loaders.
+    runtime_pool: Vec<Option<RuntimePool>>,
+    class_refs: HashMap<ClassId, ClassRef>,
+
+    funtions: Vec<NativeFunction>,
+    /// Indices into `funtions` by the native method each implements, which is named like
+    /// `java/lang/Object.hashCode()I` so overloads can have their own.
+    function_indices: HashMap<String, usize>,
 }
 
 impl std::fmt::Debug for Runtime {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Runtime")
-            .field("initializeda", &self.initialized)
+            .field("loaders", &self.loaders)
             .field("class_files", &self.class_files)
-            .field("code_pool", &self.code_pool)
+            .field("code", &self.code)
             .field("runtime_pool", &self.runtime_pool)
             .finish()
     }
 }
 
 impl Runtime {
-    pub fn new(class_files: Vec<ClassFile>) -> Runtime {
-        let class_files = HashMap::from_iter(
-            class_files
-                .into_iter()
-                .map(|class| (class.class_name().to_string(), class)),
-        );
+    /// Creates a runtime whose application loader loads from `class_path`.
+    pub fn new(class_path: ClassPath) -> Runtime {
+        let loaders = vec![
+            ClassLoader::new("bootstrap", None, ClassPath::new()),
+            ClassLoader::new("platform", Some(LoaderId::BOOTSTRAP), ClassPath::new()),
+            ClassLoader::new("app", Some(LoaderId::PLATFORM), class_path),
+        ]
+        .into_iter()
+        .map(Some)
+        .collect();
+        let (funtions, function_indices) = Self::setup_native_functions();
 
         Runtime {
-            initialized: HashSet::new(),
-            class_files,
-            code_pool: Vec::new(),
-            runtime_pool: HashMap::new(),
+            linking: HashSet::new(),
+            loaders,
+            class_files: HashMap::new(),
+            code: CodeSpace::default(),
+            runtime_pool: Vec::new(),
+            class_refs: HashMap::new(),
+
+            funtions,
+            function_indices,
+        }
+    }
+
+    /// Sets where the bootstrap loader finds platform classes such as `java/lang/Object`. Every
+    /// other loader asks it first, so applications can't replace classes such as
+    /// `java/lang/String`.
+    pub fn with_boot_class_path(mut self, boot_class_path: ClassPath) -> Self {
+        self.loader_mut(LoaderId::BOOTSTRAP).class_path = boot_class_path;
+        self
+    }
+
+    /// Adds a user-defined class loader, which finds classes in `class_path` after asking
+    /// `parent`. Classes it defines are distinct from those of the same name in other loaders.
+    pub fn add_class_loader(
+        &mut self,
+        name: impl Into<String>,
+        parent: LoaderId,
+        class_path: ClassPath,
+    ) -> LoaderId {
+        self.loaders
+            .push(Some(ClassLoader::new(name, Some(parent), class_path)));
+        LoaderId(self.loaders.len() - 1)
+    }
 
-            funtions: Self::setup_native_functions(),
+    /// Adds a user-defined class loader that loads classes through `delegate`, like a Java
+    /// `ClassLoader` that overrides `loadClass`. It's up to the delegate to ask `parent` first.
+    pub fn add_delegating_class_loader(
+        &mut self,
+        name: impl Into<String>,
+        parent: LoaderId,
+        delegate: impl LoaderDelegate + 'static,
+    ) -> LoaderId {
+        let mut loader = ClassLoader::new(name, Some(parent), ClassPath::new());
+        loader.delegate = Some(Rc::new(delegate));
+
+        self.loaders.push(Some(loader));
+        LoaderId(self.loaders.len() - 1)
+    }
+
+    /// Unloads a user-defined class loader along with every class it defined, once nothing can
+    /// reach it any more (JLS 12.7). It mustn't be the parent of another loader, and no other
+    /// loader can have loaded any of its classes, such as subclasses in a child loader.
+    ///
+    /// There's no heap to find instances or `Class` objects in, so the caller has to know there
+    /// aren't any left, and that no thread is still running the classes' code.
+    pub fn unload_class_loader(&mut self, loader: LoaderId) -> Result<()> {
+        let reachable = |reason: &str| VmError::Linkage {
+            class_name: String::new(),
+            reason: format!("class loader {} {}", loader.0, reason),
+        };
+
+        if loader.is_builtin() || loader == LoaderId::APPLICATION {
+            return Err(reachable("is built in"));
+        }
+        if !self.is_loaded(loader) {
+            return Err(reachable("is unknown"));
+        }
+
+        let others = self
+            .loaders
+            .iter()
+            .enumerate()
+            .filter(|&(id, _)| id != loader.0)
+            .filter_map(|(_, other)| other.as_ref());
+
+        for other in others {
+            if other.parent == Some(loader) {
+                return Err(reachable(&format!("is the parent of '{}'", other.name)));
+            }
+            if let Some((name, _)) = other
+                .classes
+                .iter()
+                .find(|&(_, &defining)| defining == loader)
+            {
+                return Err(reachable(&format!(
+                    "defined '{}', which '{}' has loaded",
+                    name, other.name
+                )));
+            }
         }
+
+        self.loaders[loader.0] = None;
+        self.class_files.retain(|class, _| class.loader != loader);
+        self.class_refs.retain(|class, _| class.loader != loader);
+        for entry in &mut self.runtime_pool {
+            if matches!(entry, Some(RuntimePool::Class(linked)) if linked.id.loader == loader) {
+                *entry = None;
+            }
+        }
+        self.code.remove_loader(loader);
+
+        Ok(())
+    }
+
+    fn is_loaded(&self, loader: LoaderId) -> bool {
+        self.loaders.get(loader.0).is_some_and(Option::is_some)
+    }
+
+    fn loader(&self, loader: LoaderId) -> &ClassLoader {
+        self.loaders[loader.0]
+            .as_ref()
+            .expect("class loader has been unloaded")
+    }
+
+    fn loader_mut(&mut self, loader: LoaderId) -> &mut ClassLoader {
+        self.loaders[loader.0]
+            .as_mut()
+            .expect("class loader has been unloaded")
     }
 
     pub fn start(runtime: Rf<Self>, main_class: &str) -> (Result<usize>, Thread) {
-        let (pc, name) = {
-            let mut runtime = runtime.borrow_mut();
+        let main = runtime.borrow_mut().find_main(main_class);
+        let (pc, class, main) = match main {
+            Ok(main) => main,
+            Err(e) => return (Err(e), Thread::new(runtime, 0)),
+        };
 
-            runtime
-                .load_class(main_class)
-                .expect("Unable to load main class!");
+        // The main class is initialized before main runs
+        let thread = Thread::new(runtime, pc);
+        if let Err(e) = thread.initialize(&class) {
+            return (Err(e), thread);
+        }
 
-            let name = runtime
-                .link_class(&main_class)
-                .expect("Unable to link main class!");
+        let thread = thread.with_frame(Frame::new_main(main));
+        (thread.run(), thread)
+    }
 
-            println!("{}", instructions::Format::from(runtime.instructions()));
+    /// Loads the main class and finds its `static void main(String[])`, returning where its
+    /// code starts.
+    fn find_main(&mut self, main_class: &str) -> Result<(usize, ClassId, ClassRef)> {
+        let class = self.get_or_load_class(LoaderId::APPLICATION, main_class)?;
+        let main = self.class_ref(&class)?;
+
+        let method = match self.get_method(main, "main", "([Ljava/lang/String;)V") {
+            Some(runtime_pool::Method::Java(method)) if method.is_static => method,
+            _ => {
+                return Err(VmError::NoSuchMethod {
+                    class_name: main_class.to_string(),
+                    name: "main([Ljava/lang/String;)V".to_string(),
+                })
+            }
+        };
 
-            match runtime.runtime_pool.get(&name) {
-                Some(RuntimePool::Class(class)) => {
-                    let method = class
-                        .methods
-                        .get("main")
-                        .expect("Main class does not contain a main method!");
+        tracing::debug!("{}", instructions::Format::from(&method.code[..]));
 
-                    (method.as_method().code_index, name)
+        Ok((method.code_index, class, main))
+    }
+
+    /// The code from `pc` to the end of its method, or `None` if no linked method's code is
+    /// there, such as when its class has been unloaded.
+    pub fn code_at(&self, pc: usize) -> Option<&[u8]> {
+        self.code.code_at(pc)
+    }
+
+    /// The class, method and bytecode index that `pc` is in, for stack traces and profilers.
+    pub fn locate(&self, pc: usize) -> Option<CodeLocation> {
+        self.code.locate(pc)
+    }
+
+    /// Finds `class` with `loader` as the initiating loader, unless it already has (JVMS 5.3.2).
+    ///
+    /// The loader's parent is asked first, so the class is defined by the first loader up the
+    /// chain whose class path has it.
+    pub fn load_class(&mut self, loader: LoaderId, class: &str) -> Result<ClassId> {
+        if !self.is_loaded(loader) {
+            return Err(VmError::ClassNotFound(class.to_string()));
+        }
+        let initiating = self.loader(loader);
+
+        if let Some((name, &defining)) = initiating.classes.get_key_value(class) {
+            return Ok(ClassId::new(defining, name.clone()));
+        }
+
+        if let Some(delegate) = initiating.delegate.clone() {
+            return self.load_delegated(loader, &*delegate, class);
+        }
+
+        let defined = match initiating.parent {
+            Some(parent) => match self.load_class(parent, class) {
+                Ok(defined) => Some(defined),
+                Err(VmError::ClassNotFound(_)) => None,
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+
+        let defined = match defined {
+            Some(defined) => defined,
+            None => {
+                let data = self
+                    .loader(loader)
+                    .class_path
+                    .find(class)?
+                    .ok_or_else(|| VmError::ClassNotFound(class.to_string()))?;
+
+                let file = Self::parse_class(&data)?;
+
+                // A class file saved under the wrong name would otherwise shadow the real class
+                if file.class_name() != class {
+                    return Err(VmError::Linkage {
+                        class_name: class.to_string(),
+                        reason: format!(
+                            "class file contains the wrong class '{}'",
+                            file.class_name()
+                        ),
+                    });
                 }
-                _ => panic!("Unable to get main class!"),
+
+                return self.define(loader, file);
             }
         };
 
-        let thread = Thread::new(runtime, pc).with_frame(Frame::new_main(name.clone()));
+        self.loader_mut(loader)
+            .classes
+            .insert(defined.name.clone(), defined.loader);
+        Ok(defined)
+    }
 
-        (thread.run(), thread)
+    /// Loads `class` through the delegate of a user-defined loader, which records the loader as
+    /// an initiating loader of whatever class the delegate returns (JVMS 5.3.2).
+    fn load_delegated(
+        &mut self,
+        loader: LoaderId,
+        delegate: &dyn LoaderDelegate,
+        class: &str,
+    ) -> Result<ClassId> {
+        let loaded = delegate.load_class(self, loader, class)?;
+
+        let linkage = |reason: &str| VmError::Linkage {
+            class_name: class.to_string(),
+            reason: reason.to_string(),
+        };
+        if *loaded.name != *class {
+            return Err(linkage(&format!(
+                "class loader returned the wrong class '{}'",
+                loaded.name
+            )));
+        }
+        if !self.is_loaded(loader) {
+            return Err(linkage("class loader was unloaded while loading"));
+        }
+
+        // The delegate may have defined the class itself, which already recorded it
+        let classes = &mut self.loader_mut(loader).classes;
+        match classes.get(class) {
+            Some(&defining) if defining != loaded.loader => Err(linkage(
+                "class loader returned a different class than the one it defined",
+            )),
+            Some(_) => Ok(loaded),
+            None => {
+                classes.insert(loaded.name.clone(), loaded.loader);
+                Ok(loaded)
+            }
+        }
+    }
+
+    /// Defines a class from the class file in `data`, with `loader` as its defining loader, like
+    /// `ClassLoader.defineClass`. The class is linked when it's first used.
+    pub fn define_class(&mut self, loader: LoaderId, data: &[u8]) -> Result<ClassId> {
+        if !self.is_loaded(loader) {
+            return Err(VmError::Linkage {
+                class_name: String::new(),
+                reason: format!("unknown class loader {}", loader.0),
+            });
+        }
+
+        let file = Self::parse_class(data)?;
+        self.define(loader, file)
     }
 
-    pub fn instructions(&self) -> &[u8] {
-        &self.code_pool
+    fn parse_class(data: &[u8]) -> Result<ClassFile> {
+        let file = ClassFile::read(&mut ByteStream::new(data))?;
+        file.check_format()?;
+
+        Ok(file)
     }
 
-    pub fn load_class(&mut self, class: &str) -> Result<()> {
-        {
-            if let Some(class_file) = self.class_files.get(class) {
-                // self.bootstrap.insert(class.to_string());
-            } else {
-                return Err(VmError::ClassNotFound(class.to_string()));
-            }
+    fn define(&mut self, loader: LoaderId, file: ClassFile) -> Result<ClassId> {
+        let name: Rc<str> = file.class_name().into();
+        let linkage = |reason: &str| VmError::Linkage {
+            class_name: name.to_string(),
+            reason: reason.to_string(),
+        };
+
+        if !loader.is_builtin() && name.starts_with("java/") {
+            return Err(linkage("prohibited package name"));
         }
 
-        Ok(())
+        // A loader that has already loaded a class by this name, even through its parent,
+        // can't go on to define another one
+        let classes = &mut self.loader_mut(loader).classes;
+        if classes.contains_key(&name) {
+            return Err(linkage("attempted duplicate class definition"));
+        }
+        classes.insert(name.clone(), loader);
+
+        let class = ClassId::new(loader, name);
+        self.class_files.insert(class.clone(), file);
+
+        Ok(class)
     }
 
-    pub fn link_class(&mut self, class_name: &str) -> Result<Rc<str>> {
+    pub fn link_class(&mut self, class: &ClassId) -> Result<()> {
         use crate::value::runtime_pool::*;
 
+        // Reaching a class again while linking its own supertypes means it's one of them
+        if !self.linking.insert(class.clone()) {
+            return Err(VmError::ClassCircularity(class.name.to_string()));
+        }
+        let supertypes = self.link_supertypes(class);
+        self.linking.remove(class);
+        let (super_class, interfaces) = supertypes?;
+
         let methods: Vec<_> = self
-            .get_class(class_name)
+            .get_class(class)
             .methods()
             .into_iter()
             .cloned()
             .collect();
 
         let fields: Vec<_> = self
-            .get_class(class_name)
+            .get_class(class)
             .fields()
             .into_iter()
             .cloned()
             .collect();
 
-        let methods = HashMap::from_iter(methods.into_iter().map(|method| {
-            let class_file = self.get_class(class_name);
-
-            let ConstantPool::Utf8(nat) = class_file.pool(method.descriptor_index as usize) else {
-                panic!("Expected method signature string!");
-            };
+        let methods = methods
+            .into_iter()
+            .map(|method| {
+                let class_file = self.get_class(class);
+
+                let MethodDescriptor { params, return_ty } =
+                    MethodDescriptor::parse(class_file.utf8(method.descriptor_index)?)?;
+                let name = method.name(class_file).to_string();
+                let descriptor = class_file.utf8(method.descriptor_index)?.to_string();
+                let is_static = method.is_static();
+
+                let linked = match method.code() {
+                    // Native and abstract methods must not have code (JVMS 4.7.3)
+                    Some(_) if method.is_native() || method.is_abstract() => {
+                        return Err(VmError::Linkage {
+                            class_name: class.name.to_string(),
+                            reason: format!(
+                                "native or abstract method '{}' has a Code attribute",
+                                name
+                            ),
+                        });
+                    }
+                    Some(code) => Method::Java(JavaMethod {
+                        max_locals: code.max_locals,
+                        max_stack: code.max_stack,
+                        // Given out once the whole class has linked
+                        code_index: 0,
+                        code: code.instructions.into(),
 
-            let (params, ret) = Type::parse_signature(nat.as_str());
+                        params,
+                        return_ty,
+                        is_static,
+                    }),
+                    None if method.is_native() => Method::Native(NativeMethod {
+                        function: self
+                            .function_indices
+                            .get(&format!("{}.{}{}", class.name, name, descriptor))
+                            .copied(),
 
-            let res = (
-                method.name(class_file).to_string(),
-                method
-                    .code()
-                    .map(|code| {
-                        let method = Method::Java(JavaMethod {
-                            max_locals: code.max_locals,
-                            max_stack: code.max_stack,
-                            code_index: self.code_pool.len(),
-
-                            params: params.clone(),
-                            return_ty: ret.clone(),
+                        params,
+                        return_ty,
+                        is_static,
+                    }),
+                    None if method.is_abstract() => {
+                        Method::Abstract(AbstractMethod { params, return_ty })
+                    }
+                    None => {
+                        return Err(VmError::Linkage {
+                            class_name: class.name.to_string(),
+                            reason: format!(
+                                "method '{}' is neither native nor abstract but has no Code attribute",
+                                name
+                            ),
                         });
+                    }
+                };
 
-                        // Add instructions to global code section
-                        self.code_pool.extend(code.instructions.into_iter());
+                Ok(((name, descriptor), linked))
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-                        method
-                    })
-                    .unwrap_or(Method::Native(NativeMethod {
-                        params,
-                        return_ty: ret,
-                    })), // If there is no code, it is a native method
-            );
+        let mut method_indices: HashMap<_, HashMap<_, _>> = HashMap::new();
+        let mut methods: Vec<_> = methods
+            .into_iter()
+            .enumerate()
+            .map(|(index, ((name, descriptor), method))| {
+                method_indices
+                    .entry(name)
+                    .or_default()
+                    .insert(descriptor, index);
+                method
+            })
+            .collect();
 
-            res
-        }));
+        // Instance fields go after the superclass's, so their slots don't depend on the subclass
+        let inherited = match &super_class {
+            Some(super_class) => &self.get_linked_class(super_class)?.layout,
+            None => &Layout::default(),
+        };
 
-        let static_fields = HashMap::from_iter(fields.into_iter().map(|field| {
-            let class_file = self.get_class(class_name);
+        let mut static_fields = Vec::new();
+        let mut field_indices: HashMap<_, HashMap<_, _>> = HashMap::new();
+        let mut declared = Vec::new();
+        for field in fields {
+            let class_file = self.get_class(class);
+            let name = field.name(class_file).to_string();
+            let descriptor = class_file.utf8(field.descriptor_index)?.to_string();
+            let ty = Type::parse(&descriptor)?;
+
+            let slot = if field.is_static() {
+                // Fields start out zeroed (JVMS 5.4.2)
+                let value = Value::default_with_type(&ty);
+                static_fields.push(Field {
+                    name: name.clone(),
+                    ty,
+                    value,
+                });
 
-            let ConstantPool::Utf8(nat) = class_file.pool(field.descriptor_index as usize) else {
-                panic!("Expected method signature string!");
+                FieldSlot::Static(static_fields.len() - 1)
+            } else {
+                declared.push((name.clone(), ty));
+                FieldSlot::Instance(inherited.size() + declared.len() - 1)
             };
 
-            let ty = Type::from(nat.as_str());
+            field_indices
+                .entry(name)
+                .or_default()
+                .insert(descriptor, slot);
+        }
+        let layout = inherited.extend(class, declared);
 
-            (
-                field.name(class_file).to_string(),
-                Field {
-                    ty,
-                    value: Value::Uninit,
-                },
-            )
-        }));
+        for (index, method) in methods.iter_mut().enumerate() {
+            if let Method::Java(method) = method {
+                method.code_index = self.code.insert(class.clone(), index, method.code.clone());
+            }
+        }
 
-        let class = Class {
+        let resolved = vec![None; self.get_class(class).constant_pool().len()];
+        self.class_refs
+            .insert(class.clone(), ClassRef(self.runtime_pool.len()));
+        self.runtime_pool.push(Some(RuntimePool::Class(Class {
+            id: class.clone(),
+            init_state: InitState::Linked,
+            super_class,
+            interfaces,
             methods,
-            fields: static_fields,
+            method_indices,
+            static_fields,
+            field_indices,
+            layout,
+            resolved,
+        })));
+
+        Ok(())
+    }
+
+    /// Loads and links the direct superclass and superinterfaces of `class` (JVMS 5.3.5), and
+    /// checks that it can extend and implement them.
+    fn link_supertypes(&mut self, class: &ClassId) -> Result<(Option<ClassId>, Vec<ClassId>)> {
+        let file = self.get_class(class);
+        let super_name = match file.super_class() {
+            0 => None,
+            index => Some(file.class_ref(index)?.to_string()),
         };
+        let interface_names = file
+            .interfaces()
+            .iter()
+            .map(|&index| file.class_ref(index).map(str::to_string))
+            .collect::<Result<Vec<_>>>()?;
+
+        let incompatible = |reason: String| VmError::IncompatibleClassChange {
+            class_name: class.name.to_string(),
+            reason,
+        };
+
+        let super_class = match super_name {
+            Some(name) => {
+                let super_class = self.get_or_load_class(class.loader, &name)?;
+                let super_file = self.get_class(&super_class);
 
-        let name: Rc<str> = class_name.into();
-        self.runtime_pool
-            .insert(name.clone(), RuntimePool::Class(class));
+                if super_file.is_interface() {
+                    return Err(incompatible(format!(
+                        "superclass '{}' is an interface",
+                        name
+                    )));
+                }
+                if super_file.is_final() {
+                    return Err(incompatible(format!(
+                        "cannot inherit from final class '{}'",
+                        name
+                    )));
+                }
 
-        Ok(name)
+                Some(super_class)
+            }
+            None => None,
+        };
+
+        let interfaces = interface_names
+            .iter()
+            .map(|name| {
+                let interface = self.get_or_load_class(class.loader, name)?;
+
+                if !self.get_class(&interface).is_interface() {
+                    return Err(incompatible(format!("'{}' is not an interface", name)));
+                }
+
+                Ok(interface)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok((super_class, interfaces))
     }
 
     // pub fn initialize_class(&mut self, lass_name: &str) {
@@ -190,214 +614,1162 @@ impl Runtime {
     //         return;
     //     }
 
-    //     let class_file = self.get_class(class_name);
+    //     let class_file = self.get_class(class);
 
     // class_file.
     // }
 
-    fn get_class(&self, class: &str) -> &ClassFile {
+    fn get_class(&self, class: &ClassId) -> &ClassFile {
         self.class_files
             .get(class)
             .expect(&format!("Class '{}' not found", class))
     }
 
-    pub fn get_or_load_class(&mut self, class_name: &Rc<str>) -> Result<Rc<str>> {
-        if let Some((k, _)) = self.runtime_pool.get_key_value(class_name) {
-            return Ok(k.clone());
-        } else {
-            self.load_class(class_name)?;
-            self.link_class(class_name)
+    /// Loads `class` with `loader` as the initiating loader, and links it if it hasn't been.
+    pub fn get_or_load_class(&mut self, loader: LoaderId, class: &str) -> Result<ClassId> {
+        let class = self.load_class(loader, class)?;
+
+        if !self.class_refs.contains_key(&class) {
+            self.link_class(&class)?;
         }
+
+        Ok(class)
     }
 
-    pub fn get_name_and_type(&self, class: &str, index: u16) -> Option<(&str, &str)> {
-        let Some(file) = self.class_files.get(class) else {
-            return None;
-        };
+    pub fn get_class_file(&self, class: &ClassId) -> Result<&ClassFile> {
+        self.class_files
+            .get(class)
+            .ok_or_else(|| VmError::ClassNotFound(class.to_string()))
+    }
 
-        let ConstantPool::NameAndType(nat) = file.pool(index as usize) else {
-            return None;
-        };
+    /// The handle threads and resolved references use for a linked class, so they don't have
+    /// to look it up by name.
+    pub fn class_ref(&self, class: &ClassId) -> Result<ClassRef> {
+        self.class_refs
+            .get(class)
+            .copied()
+            .ok_or_else(|| VmError::ClassNotFound(class.to_string()))
+    }
 
-        let ConstantPool::Utf8(name) = file.pool(nat.name_index as usize) else {
-            return None
-        };
+    pub fn get_linked(&self, class: ClassRef) -> Result<&runtime_pool::Class> {
+        match self.runtime_pool.get(class.0) {
+            Some(Some(RuntimePool::Class(linked))) => Ok(linked),
+            _ => Err(Self::unloaded(class)),
+        }
+    }
 
-        let ConstantPool::Utf8(descriptor) = file.pool(nat.descriptor_index as usize) else {
-            return None
-        };
+    fn get_linked_mut(&mut self, class: ClassRef) -> Result<&mut runtime_pool::Class> {
+        match self.runtime_pool.get_mut(class.0) {
+            Some(Some(RuntimePool::Class(linked))) => Ok(linked),
+            _ => Err(Self::unloaded(class)),
+        }
+    }
 
-        Some((name.as_str(), descriptor.as_str()))
+    fn get_linked_class(&self, class: &ClassId) -> Result<&runtime_pool::Class> {
+        self.get_linked(self.class_ref(class)?)
     }
 
-    /// Load a class item if it hasn't been already.
-    ///
-    /// `class` is the name of the current class file. This is used to read the constant pool
-    /// `index` is an index into the `class`'s constant pool
-    pub fn get_or_load_class_item(&mut self, class: &str, index: u16) -> Option<Rc<str>> {
-        let class_name = {
-            let Some(file) = self.class_files.get(class) else {
-                return None;
-            };
+    fn unloaded(class: ClassRef) -> VmError {
+        VmError::Linkage {
+            class_name: String::new(),
+            reason: format!("class {} has been unloaded", class.0),
+        }
+    }
 
-            let (ConstantPool::MethodRef(cref) | ConstantPool::FieldRef(cref) | ConstantPool::InterfaceMethodRef(cref)) = file.pool(index as usize) else {
-                return None;
-            };
+    /// Resolves the `CONSTANT_Class` at `index` in `class`'s constant pool (JVMS 5.4.3.1).
+    pub fn resolve_class(&mut self, class: ClassRef, index: u16) -> Result<ClassRef> {
+        let resolved = self.resolve(class, index, |rt| {
+            let id = rt.get_linked(class)?.id.clone();
+            let name = rt.get_class_file(&id)?.class_ref(index)?.to_string();
 
-            let ConstantPool::Class(class_pool) = file.pool(cref.class_index as usize) else {
-                return None
-            };
+            let resolved = rt.get_or_load_class(id.loader, &name)?;
+            rt.class_ref(&resolved).map(Resolved::Class)
+        })?;
 
-            let class_name = file.get_str(class_pool.name_index as usize);
-            Rc::from(class_name)
-        };
+        match resolved {
+            Resolved::Class(resolved) => Ok(resolved),
+            _ => Err(self.wrong_constant(class, index, "CONSTANT_Class")),
+        }
+    }
+
+    /// Resolves the `CONSTANT_Fieldref` at `index` in `class`'s constant pool to the field it
+    /// refers to, which may be declared by a supertype of the class it names (JVMS 5.4.3.2).
+    pub fn resolve_field(&mut self, class: ClassRef, index: u16) -> Result<FieldRef> {
+        let resolved = self.resolve(class, index, |rt| {
+            let id = rt.get_linked(class)?.id.clone();
+            let member = rt.get_class_file(&id)?.member_ref(index)?;
+            if member.kind != MemberKind::Field {
+                return Err(rt.wrong_constant(class, index, "CONSTANT_Fieldref"));
+            }
+
+            let (class_name, name, descriptor) = (
+                member.class_name.to_string(),
+                member.name.to_string(),
+                member.descriptor.to_string(),
+            );
 
-        self.get_or_load_class(&class_name).ok()
+            let owner = rt.get_or_load_class(id.loader, &class_name)?;
+            rt.lookup_field(&owner, &name, &descriptor)
+                .map(Resolved::Field)
+                .ok_or(VmError::NoSuchField { class_name, name })
+        })?;
+
+        match resolved {
+            Resolved::Field(field) => Ok(field),
+            _ => Err(self.wrong_constant(class, index, "CONSTANT_Fieldref")),
+        }
     }
 
-    pub fn get_field_by_index(
-        &self,
-        class: &str,
+    /// Resolves the `CONSTANT_Methodref` or `CONSTANT_InterfaceMethodref` at `index` in
+    /// `class`'s constant pool to the method it refers to (JVMS 5.4.3.3, 5.4.3.4).
+    pub fn resolve_method(&mut self, class: ClassRef, index: u16) -> Result<MethodRef> {
+        let resolved = self.resolve(class, index, |rt| {
+            let id = rt.get_linked(class)?.id.clone();
+            let member = rt.get_class_file(&id)?.member_ref(index)?;
+            let (kind, class_name, name, descriptor) = (
+                member.kind,
+                member.class_name.to_string(),
+                member.name.to_string(),
+                member.descriptor.to_string(),
+            );
+
+            let owner = rt.get_or_load_class(id.loader, &class_name)?;
+            let is_interface = rt.get_class(&owner).is_interface();
+            match kind {
+                MemberKind::Field => {
+                    return Err(rt.wrong_constant(class, index, "CONSTANT_Methodref"))
+                }
+                MemberKind::Method if is_interface => {
+                    return Err(VmError::IncompatibleClassChange {
+                        class_name,
+                        reason: "method reference names an interface".to_string(),
+                    })
+                }
+                MemberKind::InterfaceMethod if !is_interface => {
+                    return Err(VmError::IncompatibleClassChange {
+                        class_name,
+                        reason: "interface method reference names a class".to_string(),
+                    })
+                }
+                _ => {}
+            }
+
+            // Interfaces inherit the public methods of Object before any of their own
+            // superinterfaces' (JVMS 5.4.3.4)
+            let object = match kind {
+                MemberKind::InterfaceMethod => rt
+                    .get_linked_class(&owner)?
+                    .super_class
+                    .clone()
+                    .and_then(|object| rt.lookup_method(&object, &name, &descriptor)),
+                _ => None,
+            };
+
+            rt.lookup_method(&owner, &name, &descriptor)
+                .or(object)
+                .or_else(|| rt.lookup_interface_method(&owner, &name, &descriptor))
+                .map(Resolved::Method)
+                .ok_or(VmError::NoSuchMethod {
+                    class_name,
+                    name: format!("{}{}", name, descriptor),
+                })
+        })?;
+
+        match resolved {
+            Resolved::Method(method) => Ok(method),
+            _ => Err(self.wrong_constant(class, index, "CONSTANT_Methodref")),
+        }
+    }
+
+    /// Returns the cached resolution of the entry at `index` in `class`'s constant pool, or
+    /// resolves it with `resolve` and caches that. Failures are cached too, so that later
+    /// attempts fail the same way (JVMS 5.4.3).
+    fn resolve(
+        &mut self,
+        class: ClassRef,
         index: u16,
-    ) -> Option<(&str, &str, &runtime_pool::Field)> {
-        let Some(file) = self.class_files.get(class) else {
-            return None;
-        };
+        resolve: impl FnOnce(&mut Self) -> Result<Resolved>,
+    ) -> Result<Resolved> {
+        let cached = self
+            .get_linked(class)?
+            .resolved
+            .get(index as usize)
+            .cloned()
+            .flatten();
 
-        let (ConstantPool::MethodRef(cref) | ConstantPool::FieldRef(cref) | ConstantPool::InterfaceMethodRef(cref)) = file.pool(index as usize) else {
-            return None;
-        };
+        let resolved = match cached {
+            Some(resolved) => resolved,
+            None => {
+                let resolved = resolve(self).unwrap_or_else(Resolved::Error);
 
-        let ConstantPool::Class(class_pool) = file.pool(cref.class_index as usize) else {
-            return None
+                if let Ok(linked) = self.get_linked_mut(class) {
+                    if let Some(entry) = linked.resolved.get_mut(index as usize) {
+                        *entry = Some(resolved.clone());
+                    }
+                }
+
+                resolved
+            }
         };
 
-        let class_name = file.get_str(class_pool.name_index as usize);
+        match resolved {
+            Resolved::Error(e) => Err(e),
+            resolved => Ok(resolved),
+        }
+    }
 
-        let Some((name, _)) = self.get_name_and_type(class, cref.name_and_type_index) else {
-            return None
+    fn wrong_constant(&self, class: ClassRef, index: u16, expected: &str) -> VmError {
+        let Ok(linked) = self.get_linked(class) else {
+            return Self::unloaded(class);
         };
 
-        self.runtime_pool
-            .get(class_name)
-            .map(|c| match c {
-                RuntimePool::Class(class) => class.fields.get(name),
+        VmError::Linkage {
+            class_name: linked.id.name.to_string(),
+            reason: format!("constant pool entry {} is not a {}", index, expected),
+        }
+    }
+
+    /// Finds the field `name` with `descriptor` that `class` has, searching its superinterfaces
+    /// before its superclass for the one that declares it.
+    fn lookup_field(&self, class: &ClassId, name: &str, descriptor: &str) -> Option<FieldRef> {
+        let class_ref = self.class_ref(class).ok()?;
+        let linked = self.get_linked(class_ref).ok()?;
+
+        if let Some(slot) = linked.field_slot(name, descriptor) {
+            return Some(FieldRef {
+                class: class_ref,
+                slot,
+            });
+        }
+
+        linked
+            .interfaces
+            .iter()
+            .chain(&linked.super_class)
+            .find_map(|supertype| self.lookup_field(supertype, name, descriptor))
+    }
+
+    /// Finds a method declared by `class` or one of its superclasses.
+    fn lookup_method(&self, class: &ClassId, name: &str, descriptor: &str) -> Option<MethodRef> {
+        let mut current = Some(class);
+
+        while let Some(class) = current {
+            let linked = self.get_linked_class(class).ok()?;
+
+            if let Some(index) = linked.method_index(name, descriptor) {
+                return Some(MethodRef {
+                    class: self.class_ref(class).ok()?,
+                    index,
+                });
+            }
+
+            current = linked.super_class.as_ref();
+        }
+
+        None
+    }
+
+    /// Finds a method that `class` inherits from one of its superinterfaces. Private and static
+    /// interface methods aren't inherited.
+    fn lookup_interface_method(
+        &self,
+        class: &ClassId,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<MethodRef> {
+        let linked = self.get_linked_class(class).ok()?;
+
+        linked
+            .interfaces
+            .iter()
+            .chain(&linked.super_class)
+            .find_map(|supertype| {
+                let file = self.get_class(supertype);
+                let declared = match file.is_interface() {
+                    true => self
+                        .get_linked_class(supertype)
+                        .ok()?
+                        .method_index(name, descriptor),
+                    false => None,
+                };
+
+                declared
+                    .filter(|&index| {
+                        let flags = file.methods()[index].access_flags();
+                        !flags.intersects(MethodAccess::PRIVATE | MethodAccess::STATIC)
+                    })
+                    .and_then(|index| {
+                        Some(MethodRef {
+                            class: self.class_ref(supertype).ok()?,
+                            index,
+                        })
+                    })
+                    .or_else(|| self.lookup_interface_method(supertype, name, descriptor))
             })
-            .flatten()
-            .map(|method| (class_name, name, method))
     }
 
-    pub fn get_field_by_index_mut(
-        &mut self,
-        class: &str,
-        index: u16,
-    ) -> Option<(Rc<str>, &mut runtime_pool::Field)> {
-        let Some(file) = self.class_files.get(class) else {
-            return None;
-        };
+    /// The static field a `getstatic` or `putstatic` resolved to.
+    pub fn get_static_field(&self, field: FieldRef) -> Result<&runtime_pool::Field> {
+        match field.slot {
+            FieldSlot::Static(index) => Ok(&self.get_linked(field.class)?.static_fields[index]),
+            FieldSlot::Instance(_) => Err(self.wrong_field_kind(field, "is not static")),
+        }
+    }
 
-        let (ConstantPool::MethodRef(cref) | ConstantPool::FieldRef(cref) | ConstantPool::InterfaceMethodRef(cref)) = file.pool(index as usize) else {
-            return None;
-        };
+    pub fn get_static_field_mut(&mut self, field: FieldRef) -> Result<&mut runtime_pool::Field> {
+        match field.slot {
+            FieldSlot::Static(index) => {
+                Ok(&mut self.get_linked_mut(field.class)?.static_fields[index])
+            }
+            FieldSlot::Instance(_) => Err(self.wrong_field_kind(field, "is not static")),
+        }
+    }
 
-        let ConstantPool::Class(class_pool) = file.pool(cref.class_index as usize) else {
-            return None
-        };
+    /// The slot of the instance field a `getfield` or `putfield` resolved to, which is the same
+    /// in objects of the declaring class and all of its subclasses.
+    pub fn get_instance_field(&self, field: FieldRef) -> Result<usize> {
+        match field.slot {
+            FieldSlot::Instance(slot) => Ok(slot),
+            FieldSlot::Static(_) => Err(self.wrong_field_kind(field, "is static")),
+        }
+    }
 
-        let class_name = file.get_str(class_pool.name_index as usize);
+    /// How objects of `class` store their fields, for allocating them.
+    pub fn get_layout(&self, class: &ClassId) -> Result<&Layout> {
+        Ok(&self.get_linked_class(class)?.layout)
+    }
 
-        let Some((name, _)) = self.get_name_and_type(class, cref.name_and_type_index) else {
-            return None
+    /// A resolved field was used as the wrong kind, such as `getstatic` on an instance field.
+    fn wrong_field_kind(&self, field: FieldRef, reason: &str) -> VmError {
+        let Ok(linked) = self.get_linked(field.class) else {
+            return Self::unloaded(field.class);
         };
-        // let name = class_name.to_string();
-        let field_name = name.to_string();
-        let (class_name, _) = self.runtime_pool.get_key_value(class_name)?;
-        let class_name = class_name.clone();
-
-        self.runtime_pool
-            .get_mut(&class_name)
-            .map(|c| match c {
-                RuntimePool::Class(class) => class.fields.get_mut(&field_name),
-            })
-            .flatten()
-            .map(|field| (class_name.clone(), field))
+        let name = match field.slot {
+            FieldSlot::Static(index) => &linked.static_fields[index].name,
+            FieldSlot::Instance(slot) => &linked.layout.slots[slot].name,
+        };
+
+        VmError::IncompatibleClassChange {
+            class_name: linked.id.name.to_string(),
+            reason: format!("field '{}' {}", name, reason),
+        }
     }
 
-    pub fn get_method_by_name(
+    pub fn get_method(
         &self,
-        class_name: &str,
+        class: ClassRef,
         name: &str,
+        descriptor: &str,
     ) -> Option<&runtime_pool::Method> {
-        self.runtime_pool
-            .get(class_name)
-            .map(|c| {
-                match c {
-                    RuntimePool::Class(class) => class.methods.get(name), // _ => None
-                }
-            })
-            .flatten()
+        self.get_linked(class).ok()?.method(name, descriptor)
     }
 
-    pub fn get_method_by_index(
-        &self,
-        class: &str,
-        index: u16,
-    ) -> Option<(&str, &str, &runtime_pool::Method)> {
-        let Some(file) = self.class_files.get(class) else {
-            return None;
-        };
+    pub fn get_resolved_method(&self, method: MethodRef) -> Result<&runtime_pool::Method> {
+        Ok(&self.get_linked(method.class)?.methods[method.index])
+    }
 
-        let (ConstantPool::MethodRef(cref) | ConstantPool::FieldRef(cref) | ConstantPool::InterfaceMethodRef(cref)) = file.pool(index as usize) else {
-            return None;
-        };
+    /// The static method an `invokestatic` resolved to.
+    pub fn get_static_method(&self, method: MethodRef) -> Result<&runtime_pool::Method> {
+        let linked = self.get_linked(method.class)?;
+        let file = self.get_class_file(&linked.id)?;
+
+        // Instance initializers are only run by invokespecial, even if marked static
+        if file.methods()[method.index].name(file) == "<init>" {
+            return Err(self.wrong_method_kind(method, "is an instance initializer"));
+        }
 
-        let ConstantPool::Class(class_pool) = file.pool(cref.class_index as usize) else {
-            return None
+        match &linked.methods[method.index] {
+            method if method.is_static() => Ok(method),
+            _ => Err(self.wrong_method_kind(method, "is not static")),
+        }
+    }
+
+    /// A resolved method was invoked the wrong way, such as `invokestatic` on an instance
+    /// method.
+    fn wrong_method_kind(&self, method: MethodRef, reason: &str) -> VmError {
+        let (Ok(linked), Ok(name)) = (self.get_linked(method.class), self.method_name(method))
+        else {
+            return Self::unloaded(method.class);
         };
 
-        let class_name = file.get_str(class_pool.name_index as usize);
+        VmError::IncompatibleClassChange {
+            class_name: linked.id.name.to_string(),
+            reason: format!("method '{}' {}", name, reason),
+        }
+    }
+
+    /// The name and descriptor of a resolved method, like `java/lang/Object.hashCode()I`, which
+    /// is also the name of its native function.
+    pub fn method_name(&self, method: MethodRef) -> Result<String> {
+        let class = &self.get_linked(method.class)?.id;
+        let file = self.get_class_file(class)?;
+        let info = &file.methods()[method.index];
+
+        Ok(format!(
+            "{}.{}{}",
+            class.name,
+            info.name(file),
+            file.utf8(info.descriptor_index)?
+        ))
+    }
 
-        let Some((name, _)) = self.get_name_and_type(class, cref.name_and_type_index) else {
-            return None
+    /// Calls a native function that a native method was linked to.
+    pub fn invoke_native_function(&self, function: usize, params: &[Value]) -> Option<Value> {
+        self.funtions[function](params)
+    }
+
+    pub fn init_state(&self, class: &ClassId) -> Option<&InitState> {
+        self.get_linked_class(class)
+            .ok()
+            .map(|linked| &linked.init_state)
+    }
+
+    /// Starts initializing `class` on `thread` if it hasn't been (JVMS 5.5), along with its
+    /// superclass and any superinterfaces that declare default methods.
+    ///
+    /// Returns the classes whose `<clinit>` the thread has to run now, superclasses first. It's
+    /// empty if `class` is initialized already, or if the same thread is initializing it, as
+    /// that's a recursive request.
+    pub fn begin_initialization(
+        &mut self,
+        class: ClassRef,
+        thread: ThreadId,
+    ) -> Result<Vec<ClassRef>> {
+        let mut pending = Vec::new();
+
+        match self.collect_initialization(class, thread, &mut pending) {
+            Ok(()) => Ok(pending),
+            Err(e) => {
+                // None of them have run anything, so they can be initialized again later
+                for class in pending {
+                    if let Ok(linked) = self.get_linked_mut(class) {
+                        linked.init_state = InitState::Linked;
+                    }
+                }
+
+                Err(e)
+            }
+        }
+    }
+
+    fn collect_initialization(
+        &mut self,
+        class: ClassRef,
+        thread: ThreadId,
+        pending: &mut Vec<ClassRef>,
+    ) -> Result<()> {
+        let linked = self.get_linked(class)?;
+        match &linked.init_state {
+            InitState::Linked => {}
+            InitState::BeingInitialized(owner) if *owner == thread => return Ok(()),
+            InitState::Initialized => return Ok(()),
+            InitState::BeingInitialized(_) => {
+                // Threads don't run concurrently, so there's no waiting for the other one
+                return Err(VmError::Linkage {
+                    class_name: linked.id.name.to_string(),
+                    reason: "class is being initialized by another thread".to_string(),
+                });
+            }
+            InitState::Erroneous(reason) => {
+                return Err(VmError::NoClassDefFound {
+                    class_name: linked.id.name.to_string(),
+                    reason: reason.clone(),
+                })
+            }
+        }
+
+        self.get_linked_mut(class)?.init_state = InitState::BeingInitialized(thread);
+        self.assign_constant_values(class)?;
+
+        let linked = self.get_linked(class)?;
+        let supertypes = if self.get_class(&linked.id).is_interface() {
+            // Interfaces don't initialize their superinterfaces
+            Vec::new()
+        } else {
+            linked
+                .super_class
+                .iter()
+                .chain(linked.interfaces.iter().filter(|interface| {
+                    self.get_class(interface)
+                        .methods()
+                        .iter()
+                        .any(|method| !method.is_abstract() && !method.is_static())
+                }))
+                .map(|supertype| self.class_ref(supertype))
+                .collect::<Result<Vec<_>>>()?
         };
 
-        self.get_method_by_name(class_name, name)
-            .map(|method| (class_name, name, method))
+        for supertype in supertypes {
+            if let Err(e) = self.collect_initialization(supertype, thread, pending) {
+                // A class can't be used if its superclass can't be
+                self.get_linked_mut(class)?.init_state = InitState::Erroneous(e.to_string());
+                return Err(e);
+            }
+        }
+
+        pending.push(class);
+        Ok(())
     }
 
-    pub fn invoke_native_function(&self, name: &str, params: &[Value]) -> Option<Value> {
-        let Some(func) = self.funtions.get(name) else {
-            panic!("Function '{}' not found", name)
+    /// Sets the static fields that have a `ConstantValue` attribute, which happens before
+    /// `<clinit>` runs.
+    fn assign_constant_values(&mut self, class: ClassRef) -> Result<()> {
+        // Borrow the class files directly so the runtime pool can still be borrowed mutably
+        let Some(Some(RuntimePool::Class(linked))) = self.runtime_pool.get_mut(class.0) else {
+            return Err(Self::unloaded(class));
         };
+        let file = self
+            .class_files
+            .get(&linked.id)
+            .ok_or_else(|| VmError::ClassNotFound(linked.id.to_string()))?;
+
+        for field in file.fields().iter().filter(|field| field.is_static()) {
+            let Some(index) = field.constant_value() else {
+                continue;
+            };
+            let descriptor = file.utf8(field.descriptor_index)?;
+            let Some(FieldSlot::Static(slot)) = linked.field_slot(field.name(file), descriptor)
+            else {
+                continue;
+            };
+            let linked = &mut linked.static_fields[slot];
+
+            let value = match (file.pool(index as usize), linked.ty.kind()) {
+                (ConstantPool::Integer(int), TypeKind::Boolean) => Value::Boolean(int.bytes != 0),
+                (ConstantPool::Integer(int), TypeKind::Byte) => Value::Byte(int.bytes as i8),
+                (ConstantPool::Integer(int), TypeKind::Char) => Value::Char(int.bytes as i16),
+                (ConstantPool::Integer(int), TypeKind::Short) => Value::Short(int.bytes as i16),
+                (ConstantPool::Integer(int), _) => Value::Int(int.bytes as i32),
+                (ConstantPool::Float(float), _) => Value::Float(f32::from_bits(float.bytes)),
+                (ConstantPool::Long(long), _) => Value::Long(long.bytes as i64),
+                (ConstantPool::Double(double), _) => Value::Double(f64::from_bits(double.bytes)),
+                // Strings need a heap to live on, so these stay null for now
+                _ => continue,
+            };
 
-        func(params)
+            linked.value = value;
+        }
+
+        Ok(())
     }
 
-    pub fn is_class_initialized(&self, class: &str) -> bool {
-        self.initialized.contains(class)
+    /// Marks `class` initialized once its `<clinit>` has returned.
+    pub fn finish_initialization(&mut self, class: ClassRef) {
+        if let Ok(linked) = self.get_linked_mut(class) {
+            linked.init_state = InitState::Initialized;
+        }
     }
 
-    pub fn set_class_initialized(&mut self, class: &str) {
-        self.initialized.insert(class.to_string());
+    /// Marks every class `thread` was initializing as erroneous, after its `<clinit>` or a
+    /// superclass's failed because of `reason`.
+    pub fn fail_initialization(&mut self, thread: ThreadId, reason: &str) {
+        for RuntimePool::Class(linked) in self.runtime_pool.iter_mut().flatten() {
+            if linked.init_state == InitState::BeingInitialized(thread) {
+                linked.init_state = InitState::Erroneous(reason.to_string());
+            }
+        }
     }
 
-    fn setup_native_functions() -> HashMap<String, Box<dyn Fn(&[Value]) -> Option<Value> + 'static>>
-    {
+    fn setup_native_functions() -> (Vec<NativeFunction>, HashMap<String, usize>) {
         // let funcs = [("as".to_string(), Box::new(|| {}) as Box<dyn Fn()>)];
-        let mut funcs = HashMap::new();
+        let mut funcs = Vec::new();
+        let mut indices = HashMap::new();
 
-        let mut add_func = |name: &str, value: Box<dyn Fn(&[Value]) -> Option<Value>>| {
-            funcs.insert(name.to_string(), value)
+        let mut add_func = |name: &str, value: NativeFunction| {
+            indices.insert(name.to_string(), funcs.len());
+            funcs.push(value);
         };
 
+        // Natives are looked up by name here rather than registered
+        add_func("java/lang/Object.registerNatives()V", Box::new(|_| None));
+
         add_func(
-            "Test/Main.out",
+            "Test/Main.out(I)V",
             Box::new(|params| {
                 println!("{}", params[0]);
                 None
             }),
         );
 
-        funcs
+        (funcs, indices)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::{
+        bytecode::ClassBuilder,
+        class_loader::{ClassId, LoaderId},
+        classpath::{jdk::JdkSource, ClassPath, ClassSource, MemorySource},
+        code::CodeLocation,
+        error::VmError,
+        rf::Rf,
+        thread::Thread,
+        value::{
+            runtime_pool::{FieldRef, FieldSlot, Method, Resolved},
+            Type, TypeKind, Value,
+        },
+    };
+
+    use super::{InitState, Runtime};
+
+    const MAIN: &[u8] = include_bytes!("../../examples/Main.class");
+
+    const INTERFACE: u16 = 0x0600;
+
+    fn jdk() -> JdkSource {
+        JdkSource::open(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../examples/jdk")).unwrap()
+    }
+
+    /// A runtime whose application class path has `classes`, by name, on top of the stub JDK.
+    fn runtime_with(classes: &[(&str, &[u8])]) -> Runtime {
+        let mut source = MemorySource::new();
+        for &(name, data) in classes {
+            source.insert(name, data.to_vec());
+        }
+
+        Runtime::new(ClassPath::new().with(source))
+            .with_boot_class_path(ClassPath::new().with(jdk()))
+    }
+
+    #[test]
+    fn test_class_loaders() {
+        let mut runtime = runtime_with(&[("Test/Main", MAIN)]);
+
+        // Platform classes are defined by the bootstrap loader, whichever loader asks
+        assert_eq!(
+            runtime
+                .load_class(LoaderId::APPLICATION, "java/lang/Object")
+                .unwrap(),
+            ClassId::new(LoaderId::BOOTSTRAP, "java/lang/Object")
+        );
+        assert!(matches!(
+            runtime.load_class(LoaderId::PLATFORM, "Test/Main"),
+            Err(VmError::ClassNotFound(_))
+        ));
+
+        let app = runtime
+            .get_or_load_class(LoaderId::APPLICATION, "Test/Main")
+            .unwrap();
+
+        // Native methods are linked to the runtime's function of the same name
+        let linked = runtime.get_linked_class(&app).unwrap();
+        let Some(Method::Native(out)) = linked.method("out", "(I)V") else {
+            panic!("expected a native method");
+        };
+        assert!(out.function.is_some());
+        let plugins: Vec<_> = (0..2)
+            .map(|i| {
+                runtime.add_class_loader(
+                    format!("plugin{}", i),
+                    LoaderId::PLATFORM,
+                    ClassPath::new(),
+                )
+            })
+            .collect();
+        let classes: Vec<_> = plugins
+            .iter()
+            .map(|&plugin| runtime.define_class(plugin, MAIN).unwrap())
+            .collect();
+
+        // Each loader has its own copy of the class
+        assert_ne!(classes[0], classes[1]);
+        assert_ne!(classes[0], app);
+        assert_eq!(
+            runtime.get_or_load_class(plugins[0], "Test/Main").unwrap(),
+            classes[0]
+        );
+        assert!(runtime.get_linked_class(&classes[0]).is_ok());
+        assert!(runtime.get_linked_class(&classes[1]).is_err());
+
+        assert!(matches!(
+            runtime.define_class(plugins[1], MAIN),
+            Err(VmError::Linkage { .. })
+        ));
+
+        let string = jdk().find("java/lang/String").unwrap().unwrap();
+        assert!(matches!(
+            runtime.define_class(plugins[0], &string),
+            Err(VmError::Linkage { .. })
+        ));
+    }
+
+    #[test]
+    fn test_loader_delegates() {
+        let mut runtime = runtime_with(&[]);
+
+        // Like a Java loader, the delegate asks its parent for anything it doesn't define
+        let plugin = runtime.add_delegating_class_loader(
+            "plugin",
+            LoaderId::PLATFORM,
+            |runtime: &mut Runtime, loader: LoaderId, name: &str| match name {
+                "Test/Main" => runtime.define_class(loader, MAIN),
+                _ => runtime.load_class(LoaderId::PLATFORM, name),
+            },
+        );
+
+        let main = runtime.get_or_load_class(plugin, "Test/Main").unwrap();
+        assert_eq!(main, ClassId::new(plugin, "Test/Main"));
+        assert_eq!(runtime.load_class(plugin, "Test/Main").unwrap(), main);
+        assert_eq!(
+            runtime.load_class(plugin, "java/lang/Object").unwrap(),
+            ClassId::new(LoaderId::BOOTSTRAP, "java/lang/Object")
+        );
+        assert!(matches!(
+            runtime.load_class(plugin, "Test/Missing"),
+            Err(VmError::ClassNotFound(_))
+        ));
+
+        let wrong = runtime.add_delegating_class_loader(
+            "wrong",
+            LoaderId::PLATFORM,
+            |runtime: &mut Runtime, _: LoaderId, _: &str| {
+                runtime.load_class(LoaderId::BOOTSTRAP, "java/lang/Object")
+            },
+        );
+        assert!(matches!(
+            runtime.load_class(wrong, "java/lang/String"),
+            Err(VmError::Linkage { .. })
+        ));
+    }
+
+    #[test]
+    fn test_code_locations() {
+        let mut runtime = runtime_with(&[]);
+        let load_plugin = |runtime: &mut Runtime, name: &str| {
+            let plugin = runtime.add_class_loader(name, LoaderId::PLATFORM, ClassPath::new());
+            let class = runtime.define_class(plugin, MAIN).unwrap();
+            runtime.link_class(&class).unwrap();
+
+            let linked = runtime.get_linked_class(&class).unwrap();
+            let index = linked
+                .method_index("main", "([Ljava/lang/String;)V")
+                .unwrap();
+            let Method::Java(main) = &linked.methods[index] else {
+                panic!("expected a Java method");
+            };
+
+            (plugin, class, index, main.code_index, main.code.clone())
+        };
+
+        let (plugin, class, index, pc, code) = load_plugin(&mut runtime, "plugin");
+        assert_eq!(runtime.code_at(pc), Some(&code[..]));
+        assert_eq!(
+            runtime.locate(pc + 1),
+            Some(CodeLocation {
+                class: class.clone(),
+                method: index,
+                bci: 1
+            })
+        );
+        assert!(runtime
+            .locate(pc + code.len())
+            .is_none_or(|location| location.method != index));
+
+        // A child loader keeps its parent reachable
+        let child = runtime.add_class_loader("child", plugin, ClassPath::new());
+        runtime.load_class(child, "Test/Main").unwrap();
+        assert!(runtime.unload_class_loader(plugin).is_err());
+        assert!(runtime.unload_class_loader(LoaderId::APPLICATION).is_err());
+
+        runtime.unload_class_loader(child).unwrap();
+        runtime.unload_class_loader(plugin).unwrap();
+        assert!(runtime.get_linked_class(&class).is_err());
+        assert_eq!(runtime.code_at(pc), None);
+        assert_eq!(runtime.locate(pc + 1), None);
+        assert!(matches!(
+            runtime.load_class(plugin, "Test/Main"),
+            Err(VmError::ClassNotFound(_))
+        ));
+
+        // Reloading the class gives it new pcs, rather than reusing the unloaded ones
+        let (_, _, _, reloaded, _) = load_plugin(&mut runtime, "plugin");
+        assert!(reloaded > pc);
+
+        // A thread left at a stale pc fails instead of running whatever is there
+        let thread = Thread::new(Rf::new(runtime), pc);
+        assert!(matches!(thread.run(), Err(VmError::InvalidPc(at)) if at == pc));
+    }
+
+    #[test]
+    fn test_link_supertypes() {
+        let classes = [
+            ClassBuilder::new(0, "A", "B").build(),
+            ClassBuilder::new(0, "B", "A").build(),
+            ClassBuilder::new(INTERFACE, "I", "java/lang/Object").build(),
+            ClassBuilder::new(INTERFACE, "J", "java/lang/Object")
+                .with_interfaces(&["J"])
+                .build(),
+            ClassBuilder::new(0, "ExtendsInterface", "I").build(),
+            ClassBuilder::new(0, "ExtendsFinal", "java/lang/String").build(),
+            ClassBuilder::new(0, "ImplementsClass", "java/lang/Object")
+                .with_interfaces(&["java/lang/Object"])
+                .build(),
+            ClassBuilder::new(0, "Valid", "java/lang/Object")
+                .with_interfaces(&["I"])
+                .build(),
+        ];
+        let named: Vec<_> = classes
+            .iter()
+            .map(|data| {
+                let name = std::str::from_utf8(&data[13..13 + data[12] as usize]).unwrap();
+                (name, &data[..])
+            })
+            .collect();
+
+        let mut runtime = runtime_with(&named);
+        let mut link = |name: &str| runtime.get_or_load_class(LoaderId::APPLICATION, name);
+
+        assert!(matches!(link("A"), Err(VmError::ClassCircularity(_))));
+        assert!(matches!(link("J"), Err(VmError::ClassCircularity(_))));
+        for name in ["ExtendsInterface", "ExtendsFinal", "ImplementsClass"] {
+            assert!(matches!(
+                link(name),
+                Err(VmError::IncompatibleClassChange { .. })
+            ));
+        }
+
+        let valid = link("Valid").unwrap();
+        let valid = runtime.get_linked_class(&valid).unwrap();
+        assert_eq!(
+            valid.super_class,
+            Some(ClassId::new(LoaderId::BOOTSTRAP, "java/lang/Object"))
+        );
+        assert_eq!(valid.interfaces, [ClassId::new(LoaderId::APPLICATION, "I")]);
+    }
+
+    #[test]
+    fn test_initialization() {
+        let runtime = Rf::new(runtime_with(&[
+            (
+                "init/Base",
+                include_bytes!("../../examples/init/Base.class"),
+            ),
+            (
+                "init/Derived",
+                include_bytes!("../../examples/init/Derived.class"),
+            ),
+            (
+                "init/Broken",
+                include_bytes!("../../examples/init/Broken.class"),
+            ),
+        ]));
+        let load = |name: &str| {
+            runtime
+                .borrow_mut()
+                .get_or_load_class(LoaderId::APPLICATION, name)
+                .unwrap()
+        };
+        let (base, derived, broken) =
+            (load("init/Base"), load("init/Derived"), load("init/Broken"));
+
+        let thread = Thread::new(runtime.clone(), 0);
+        thread.initialize(&derived).unwrap();
+
+        let rt = runtime.borrow();
+        let field = |class: &ClassId, name: &str, descriptor: &str| {
+            let class = rt.get_linked_class(class).unwrap();
+            class.static_field(name, descriptor).unwrap().value.clone()
+        };
+
+        // The superclass's <clinit> runs first
+        assert_eq!(rt.init_state(&base), Some(&InitState::Initialized));
+        assert_eq!(rt.init_state(&derived), Some(&InitState::Initialized));
+        assert!(matches!(field(&base, "base", "I"), Value::Int(1)));
+        assert!(matches!(field(&derived, "derived", "I"), Value::Int(2)));
+        assert!(matches!(field(&derived, "CONSTANT", "I"), Value::Int(7)));
+        assert!(matches!(
+            field(&derived, "BIG", "J"),
+            Value::Long(0x100_0000_0000)
+        ));
+        assert!(matches!(field(&derived, "table", "[I"), Value::Null));
+        drop(rt);
+
+        assert!(matches!(
+            thread.initialize(&broken),
+            Err(VmError::ExceptionInInitializer { class_name, .. }) if class_name == "init/Broken"
+        ));
+        assert!(matches!(
+            runtime.borrow().init_state(&broken),
+            Some(InitState::Erroneous(_))
+        ));
+        assert!(matches!(
+            thread.initialize(&broken),
+            Err(VmError::NoClassDefFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_resolution() {
+        let mut runtime = runtime_with(&[
+            (
+                "resolve/Named",
+                include_bytes!("../../examples/resolve/Named.class"),
+            ),
+            (
+                "resolve/Parent",
+                include_bytes!("../../examples/resolve/Parent.class"),
+            ),
+            (
+                "resolve/Child",
+                include_bytes!("../../examples/resolve/Child.class"),
+            ),
+            (
+                "resolve/User",
+                include_bytes!("../../examples/resolve/User.class"),
+            ),
+        ]);
+        let user = runtime
+            .get_or_load_class(LoaderId::APPLICATION, "resolve/User")
+            .unwrap();
+        let app = |name: &str| ClassId::new(LoaderId::APPLICATION, name);
+        let user = runtime.class_ref(&user).unwrap();
+
+        let file = runtime.get_class_file(&app("resolve/User")).unwrap();
+        let member = |class_name: &str, name: &str| {
+            (1..file.constant_pool().len() as u16)
+                .find(|&index| {
+                    file.member_ref(index)
+                        .is_ok_and(|member| member.class_name == class_name && member.name == name)
+                })
+                .unwrap()
+        };
+        let (count, name, next, missing) = (
+            member("resolve/Child", "count"),
+            member("resolve/Child", "NAME"),
+            member("resolve/Child", "next"),
+            member("resolve/Missing", "value"),
+        );
+        let instance_methods = [
+            member("java/lang/Object", "<init>"),
+            member("java/lang/String", "length"),
+        ];
+
+        // Members are resolved to the supertypes that declare them
+        for _ in 0..2 {
+            let (counter, named) = (
+                runtime.resolve_field(user, count).unwrap(),
+                runtime.resolve_field(user, name).unwrap(),
+            );
+            let parent = runtime.class_ref(&app("resolve/Parent")).unwrap();
+            assert_eq!(
+                counter,
+                FieldRef {
+                    class: parent,
+                    slot: FieldSlot::Static(0)
+                }
+            );
+            assert_eq!(
+                named.class,
+                runtime.class_ref(&app("resolve/Named")).unwrap()
+            );
+
+            let next = runtime.resolve_method(user, next).unwrap();
+            assert_eq!(next.class, parent);
+            assert!(matches!(
+                runtime.get_resolved_method(next),
+                Ok(Method::Java(_))
+            ));
+        }
+
+        // Only static methods can be invoked with invokestatic
+        let next = runtime.resolve_method(user, next).unwrap();
+        assert!(runtime.get_static_method(next).is_ok());
+        for index in instance_methods {
+            let method = runtime.resolve_method(user, index).unwrap();
+            assert!(matches!(
+                runtime.get_static_method(method),
+                Err(VmError::IncompatibleClassChange { .. })
+            ));
+        }
+
+        let linked = runtime.get_linked(user).unwrap();
+        assert!(matches!(
+            linked.resolved[count as usize],
+            Some(Resolved::Field(_))
+        ));
+        assert!(linked.resolved[missing as usize].is_none());
+
+        assert!(matches!(
+            runtime.resolve_field(user, missing),
+            Err(VmError::ClassNotFound(_))
+        ));
+        assert!(runtime.resolve_method(user, count).is_err());
+
+        // Failures are cached, even once the class could be found
+        let mut missing_class = MemorySource::new();
+        missing_class.insert(
+            "resolve/Missing",
+            ClassBuilder::new(0, "resolve/Missing", "java/lang/Object").build(),
+        );
+        runtime.loader_mut(LoaderId::APPLICATION).class_path = ClassPath::new().with(missing_class);
+        assert!(runtime
+            .get_or_load_class(LoaderId::APPLICATION, "resolve/Missing")
+            .is_ok());
+        assert!(matches!(
+            runtime.resolve_field(user, missing),
+            Err(VmError::ClassNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_field_layout() {
+        let mut runtime = runtime_with(&[
+            (
+                "layout/Point",
+                include_bytes!("../../examples/layout/Point.class"),
+            ),
+            (
+                "layout/Point3",
+                include_bytes!("../../examples/layout/Point3.class"),
+            ),
+        ]);
+        let point3 = runtime
+            .get_or_load_class(LoaderId::APPLICATION, "layout/Point3")
+            .unwrap();
+        let point = ClassId::new(LoaderId::APPLICATION, "layout/Point");
+        let object = ClassId::new(LoaderId::BOOTSTRAP, "java/lang/Object");
+
+        assert_eq!(runtime.get_layout(&object).unwrap().size(), 0);
+        assert_eq!(runtime.get_layout(&point).unwrap().size(), 3);
+
+        // Subclass fields come after the superclass's, even ones that hide them
+        let layout = runtime.get_layout(&point3).unwrap();
+        let slots: Vec<_> = layout
+            .slots
+            .iter()
+            .map(|slot| (slot.class.name.as_ref(), slot.name.as_str()))
+            .collect();
+        assert_eq!(
+            slots,
+            [
+                ("layout/Point", "x"),
+                ("layout/Point", "y"),
+                ("layout/Point", "label"),
+                ("layout/Point3", "z"),
+                ("layout/Point3", "x"),
+            ]
+        );
+        assert_eq!(layout.references, [2, 3, 4]);
+
+        let linked = runtime.get_linked_class(&point).unwrap();
+        let statics: Vec<_> = linked
+            .static_fields
+            .iter()
+            .map(|field| &field.name)
+            .collect();
+        assert_eq!(statics, ["created"]);
+
+        let file = runtime.get_class_file(&point3).unwrap();
+        let point3 = runtime.class_ref(&point3).unwrap();
+        let fields: Vec<_> = (1..file.constant_pool().len() as u16)
+            .filter(|&index| {
+                file.member_ref(index)
+                    .is_ok_and(|member| member.name == "x")
+            })
+            .collect();
+        let slots: Vec<_> = fields
+            .into_iter()
+            .map(|index| {
+                let field = runtime.resolve_field(point3, index).unwrap();
+                assert!(matches!(
+                    runtime.get_static_field(field),
+                    Err(VmError::IncompatibleClassChange { .. })
+                ));
+
+                runtime.get_instance_field(field).unwrap()
+            })
+            .collect();
+        assert_eq!(slots, [4, 0]);
+    }
+
+    #[test]
+    fn test_field_descriptors() {
+        let fields = ClassBuilder::new(0, "Fields", "java/lang/Object")
+            .with_fields(&[(0x0008, "x", "I"), (0, "x", "J"), (0, "y", "I")])
+            .build();
+        let mut runtime = runtime_with(&[("Fields", &fields)]);
+
+        let class = runtime
+            .get_or_load_class(LoaderId::APPLICATION, "Fields")
+            .unwrap();
+        let linked = runtime.get_linked_class(&class).unwrap();
+
+        // Fields with the same name but different types are different fields
+        assert_eq!(linked.field_slot("x", "I"), Some(FieldSlot::Static(0)));
+        assert_eq!(linked.field_slot("x", "J"), Some(FieldSlot::Instance(0)));
+        assert_eq!(linked.field_slot("y", "I"), Some(FieldSlot::Instance(1)));
+        assert_eq!(linked.field_slot("x", "Z"), None);
+        assert!(matches!(
+            linked.static_field("x", "I").unwrap().value,
+            Value::Int(0)
+        ));
+
+        assert_eq!(
+            runtime.lookup_field(&class, "x", "J"),
+            Some(FieldRef {
+                class: runtime.class_ref(&class).unwrap(),
+                slot: FieldSlot::Instance(0)
+            })
+        );
+        assert_eq!(runtime.lookup_field(&class, "y", "J"), None);
+    }
+
+    #[test]
+    fn test_overloads() {
+        let mut runtime = runtime_with(&[(
+            "overload/Overloads",
+            include_bytes!("../../examples/overload/Overloads.class"),
+        )]);
+
+        let class = runtime
+            .get_or_load_class(LoaderId::APPLICATION, "overload/Overloads")
+            .unwrap();
+        let linked = runtime.get_linked_class(&class).unwrap();
+
+        // Methods stay in the order they're declared in, after the constructor
+        assert_eq!(linked.methods.len(), 6);
+        assert_eq!(linked.method_index("out", "(I)V"), Some(1));
+        assert_eq!(linked.method_index("out", "(Ljava/lang/String;)V"), Some(2));
+        assert_eq!(linked.method_index("out", "(J)V"), None);
+
+        let Some(Method::Native(out)) = linked.method("out", "(Ljava/lang/String;)V") else {
+            panic!("expected a native method");
+        };
+        assert_eq!(out.function, None);
+        assert_eq!(
+            out.params,
+            [Type::new(
+                TypeKind::Class("java/lang/String".to_string()),
+                0
+            )]
+        );
+
+        for (descriptor, ty) in [("(I)I", TypeKind::Int), ("(J)J", TypeKind::Long)] {
+            let Some(Method::Java(twice)) = linked.method("twice", descriptor) else {
+                panic!("expected a Java method");
+            };
+            assert_eq!(twice.return_ty, Some(Type::new(ty, 0)));
+        }
+
+        let Some(Method::Abstract(run)) = linked.method("run", "()V") else {
+            panic!("expected an abstract method");
+        };
+        assert!(run.params.is_empty());
+        assert_eq!(run.return_ty, None);
+    }
+
+    #[test]
+    fn test_start_without_main() {
+        let no_main = ClassBuilder::new(0x0021, "NoMain", "java/lang/Object").build();
+        let runtime = Rf::new(runtime_with(&[("NoMain", &no_main)]));
+
+        let (status, _) = Runtime::start(runtime.clone(), "NoMain");
+        assert!(matches!(status, Err(VmError::NoSuchMethod { .. })));
+
+        let (status, _) = Runtime::start(runtime, "Missing");
+        assert!(matches!(status, Err(VmError::ClassNotFound(_))));
     }
 }
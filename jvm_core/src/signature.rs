@@ -0,0 +1,667 @@
+//! Generic type signatures from `Signature` attributes (JVMS 4.7.9.1).
+//!
+//! Descriptors only describe the erased types the JVM works with. Signatures keep the
+//! generic information from the source language, such as `List<String>`, type variables and
+//! wildcards, which is used for reflection but has no effect on execution.
+
+use std::fmt::Display;
+
+use crate::{
+    error::{Result, VmError},
+    value::{MethodDescriptor, Type, TypeKind},
+};
+
+/// The type of a field, parameter or type argument, including generic information.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenericType {
+    /// A primitive type.
+    Base(TypeKind),
+    Class(ClassType),
+    /// A type variable such as `T`.
+    TypeVariable(String),
+    Array(Box<GenericType>),
+}
+
+/// A class or interface type, such as `java/util/Map<TK;TV;>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassType {
+    /// The binary name of the outermost class, such as `java/util/Map`.
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+    /// Member classes of the outermost class, such as `Inner` in `Outer<T>.Inner<U>`.
+    pub inner: Vec<InnerClassType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InnerClassType {
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeArgument {
+    /// The unbounded wildcard `?`.
+    Any,
+    Exact(GenericType),
+    /// `? extends T`
+    Extends(GenericType),
+    /// `? super T`
+    Super(GenericType),
+}
+
+/// A type parameter declaration, such as `T extends Number & Comparable<T>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeParameter {
+    pub name: String,
+    /// Absent when the only bounds are interfaces.
+    pub class_bound: Option<GenericType>,
+    pub interface_bounds: Vec<GenericType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub superclass: ClassType,
+    pub interfaces: Vec<ClassType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub params: Vec<GenericType>,
+    /// `None` for methods that return void.
+    pub return_ty: Option<GenericType>,
+    /// Class types or type variables.
+    pub throws: Vec<GenericType>,
+}
+
+impl GenericType {
+    /// Parses a FieldSignature, which is a reference type.
+    pub fn parse_field(signature: &str) -> Result<GenericType> {
+        let mut parser = Parser::new(signature);
+        let ty = parser.reference_type()?;
+        parser.end()?;
+
+        Ok(ty)
+    }
+
+    /// Erases the type to its descriptor type.
+    ///
+    /// Type variables erase to the erasure of their leftmost bound, looked up by name in
+    /// `type_parameters`. Variables that aren't declared there erase to `java/lang/Object`.
+    pub fn erasure(&self, type_parameters: &[TypeParameter]) -> Type {
+        self.erasure_within(type_parameters, &mut Vec::new())
+    }
+
+    /// `visiting` holds the type variables whose bounds are being erased. javac never writes a
+    /// bound that refers back to its own variable, like `<T:TT;>`, but a class file can, so
+    /// those erase to `java/lang/Object` instead of recursing forever.
+    fn erasure_within<'a>(
+        &'a self,
+        type_parameters: &'a [TypeParameter],
+        visiting: &mut Vec<&'a str>,
+    ) -> Type {
+        let object = || Type::new(TypeKind::Class("java/lang/Object".to_string()), 0);
+
+        match self {
+            GenericType::Base(kind) => Type::new(kind.clone(), 0),
+            GenericType::Class(class) => Type::new(TypeKind::Class(class.binary_name()), 0),
+            GenericType::TypeVariable(name) => {
+                if visiting.contains(&name.as_str()) {
+                    return object();
+                }
+
+                let Some(bound) = type_parameters
+                    .iter()
+                    .find(|parameter| &parameter.name == name)
+                    .and_then(|parameter| {
+                        parameter
+                            .class_bound
+                            .as_ref()
+                            .or(parameter.interface_bounds.first())
+                    })
+                else {
+                    return object();
+                };
+
+                visiting.push(name);
+                let erased = bound.erasure_within(type_parameters, visiting);
+                visiting.pop();
+
+                erased
+            }
+            GenericType::Array(element) => {
+                let element = element.erasure_within(type_parameters, visiting);
+
+                // An array of a type variable bound by an array can erase to more dimensions
+                // than a descriptor can have, so they're capped there
+                Type::new(
+                    element.kind().clone(),
+                    element.array_dimensions().saturating_add(1),
+                )
+            }
+        }
+    }
+}
+
+impl ClassType {
+    /// The binary name of the class, with member classes separated by `$`.
+    pub fn binary_name(&self) -> String {
+        let mut name = self.name.clone();
+
+        for inner in &self.inner {
+            name.push('$');
+            name.push_str(&inner.name);
+        }
+
+        name
+    }
+}
+
+impl ClassSignature {
+    pub fn parse(signature: &str) -> Result<ClassSignature> {
+        let mut parser = Parser::new(signature);
+
+        let type_parameters = parser.type_parameters()?;
+        let superclass = parser.class_type()?;
+
+        let mut interfaces = Vec::new();
+        while parser.peek().is_some() {
+            interfaces.push(parser.class_type()?);
+        }
+
+        Ok(ClassSignature {
+            type_parameters,
+            superclass,
+            interfaces,
+        })
+    }
+}
+
+impl MethodSignature {
+    pub fn parse(signature: &str) -> Result<MethodSignature> {
+        let mut parser = Parser::new(signature);
+
+        let type_parameters = parser.type_parameters()?;
+
+        parser.expect('(')?;
+        let mut params = Vec::new();
+        while parser.peek() != Some(')') {
+            params.push(parser.java_type()?);
+        }
+        parser.expect(')')?;
+
+        let return_ty = match parser.peek() {
+            Some('V') => {
+                parser.pos += 1;
+                None
+            }
+            _ => Some(parser.java_type()?),
+        };
+
+        let mut throws = Vec::new();
+        while parser.peek() == Some('^') {
+            parser.pos += 1;
+
+            match parser.reference_type()? {
+                GenericType::Array(_) => {
+                    return Err(parser.error("a thrown type can't be an array"));
+                }
+                ty => throws.push(ty),
+            }
+        }
+        parser.end()?;
+
+        Ok(MethodSignature {
+            type_parameters,
+            params,
+            return_ty,
+            throws,
+        })
+    }
+
+    /// Erases the signature to a method descriptor.
+    ///
+    /// `class_type_parameters` are the type parameters of the declaring class, which the
+    /// method's own type parameters shadow. Note that the signature may leave out parameters
+    /// that are in the descriptor, such as the synthetic parameters of inner class
+    /// constructors.
+    pub fn erasure(&self, class_type_parameters: &[TypeParameter]) -> MethodDescriptor {
+        let type_parameters: Vec<_> = self
+            .type_parameters
+            .iter()
+            .chain(class_type_parameters)
+            .cloned()
+            .collect();
+
+        MethodDescriptor {
+            params: self
+                .params
+                .iter()
+                .map(|param| param.erasure(&type_parameters))
+                .collect(),
+            return_ty: self
+                .return_ty
+                .as_ref()
+                .map(|ty| ty.erasure(&type_parameters)),
+        }
+    }
+}
+
+/// How deeply type arguments can nest, such as `LA<LA<LA<...>;>;>;`. Parsing them is
+/// recursive, so without a limit a crafted signature could overflow the stack.
+const MAX_TYPE_ARGUMENT_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    signature: &'a str,
+    pos: usize,
+    /// The number of type argument lists being parsed.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(signature: &'a str) -> Self {
+        Self {
+            signature,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn error(&self, reason: &str) -> VmError {
+        VmError::InvalidSignature {
+            signature: self.signature.to_string(),
+            position: self.pos,
+            reason: reason.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.signature[self.pos..].chars().next()
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{}'", c)));
+        }
+
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn end(&self) -> Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error("expected the end of the signature")),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<&'a str> {
+        let rest = &self.signature[self.pos..];
+        let len = rest
+            .find(['.', ';', '[', '/', '<', '>', ':'])
+            .unwrap_or(rest.len());
+
+        if len == 0 {
+            return Err(self.error("expected an identifier"));
+        }
+
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    /// JavaTypeSignature: a reference type or a primitive type.
+    fn java_type(&mut self) -> Result<GenericType> {
+        let kind = match self.peek() {
+            Some('B') => TypeKind::Byte,
+            Some('C') => TypeKind::Char,
+            Some('D') => TypeKind::Double,
+            Some('F') => TypeKind::Float,
+            Some('I') => TypeKind::Int,
+            Some('J') => TypeKind::Long,
+            Some('S') => TypeKind::Short,
+            Some('Z') => TypeKind::Boolean,
+            _ => return self.reference_type(),
+        };
+        self.pos += 1;
+
+        Ok(GenericType::Base(kind))
+    }
+
+    fn reference_type(&mut self) -> Result<GenericType> {
+        match self.peek() {
+            Some('L') => self.class_type().map(GenericType::Class),
+            Some('T') => {
+                self.pos += 1;
+                let name = self.identifier()?.to_string();
+                self.expect(';')?;
+
+                Ok(GenericType::TypeVariable(name))
+            }
+            Some('[') => {
+                let mut array_dimensions = 0;
+                while self.peek() == Some('[') {
+                    array_dimensions += 1;
+                    self.pos += 1;
+                }
+
+                if array_dimensions > 255 {
+                    return Err(self.error("arrays can have at most 255 dimensions"));
+                }
+
+                let mut ty = self.java_type()?;
+                for _ in 0..array_dimensions {
+                    ty = GenericType::Array(Box::new(ty));
+                }
+
+                Ok(ty)
+            }
+            _ => Err(self.error("expected a reference type")),
+        }
+    }
+
+    fn class_type(&mut self) -> Result<ClassType> {
+        self.expect('L')?;
+
+        let mut name = self.identifier()?.to_string();
+        while self.peek() == Some('/') {
+            self.pos += 1;
+            name.push('/');
+            name.push_str(self.identifier()?);
+        }
+
+        let type_arguments = self.type_arguments()?;
+
+        let mut inner = Vec::new();
+        while self.peek() == Some('.') {
+            self.pos += 1;
+
+            inner.push(InnerClassType {
+                name: self.identifier()?.to_string(),
+                type_arguments: self.type_arguments()?,
+            });
+        }
+
+        self.expect(';')?;
+
+        Ok(ClassType {
+            name,
+            type_arguments,
+            inner,
+        })
+    }
+
+    /// Optional type arguments such as `<TK;+Ljava/lang/Number;*>`.
+    fn type_arguments(&mut self) -> Result<Vec<TypeArgument>> {
+        if self.peek() != Some('<') {
+            return Ok(Vec::new());
+        }
+
+        if self.depth >= MAX_TYPE_ARGUMENT_DEPTH {
+            return Err(self.error(&format!(
+                "type arguments can nest at most {} levels deep",
+                MAX_TYPE_ARGUMENT_DEPTH
+            )));
+        }
+        self.pos += 1;
+        self.depth += 1;
+
+        let mut arguments = Vec::new();
+        while self.peek() != Some('>') {
+            let argument = match self.peek() {
+                Some('*') => {
+                    self.pos += 1;
+                    TypeArgument::Any
+                }
+                Some('+') => {
+                    self.pos += 1;
+                    TypeArgument::Extends(self.reference_type()?)
+                }
+                Some('-') => {
+                    self.pos += 1;
+                    TypeArgument::Super(self.reference_type()?)
+                }
+                _ => TypeArgument::Exact(self.reference_type()?),
+            };
+
+            arguments.push(argument);
+        }
+
+        if arguments.is_empty() {
+            return Err(self.error("expected at least one type argument"));
+        }
+        self.pos += 1;
+        self.depth -= 1;
+
+        Ok(arguments)
+    }
+
+    /// Optional type parameters such as `<T:Ljava/lang/Object;:Ljava/lang/Comparable<TT;>;>`.
+    fn type_parameters(&mut self) -> Result<Vec<TypeParameter>> {
+        if self.peek() != Some('<') {
+            return Ok(Vec::new());
+        }
+        self.pos += 1;
+
+        let mut parameters = Vec::new();
+        while self.peek() != Some('>') {
+            let name = self.identifier()?.to_string();
+
+            self.expect(':')?;
+            let class_bound = match self.peek() {
+                Some('L' | 'T' | '[') => Some(self.reference_type()?),
+                _ => None,
+            };
+
+            let mut interface_bounds = Vec::new();
+            while self.peek() == Some(':') {
+                self.pos += 1;
+                interface_bounds.push(self.reference_type()?);
+            }
+
+            parameters.push(TypeParameter {
+                name,
+                class_bound,
+                interface_bounds,
+            });
+        }
+
+        if parameters.is_empty() {
+            return Err(self.error("expected at least one type parameter"));
+        }
+        self.pos += 1;
+
+        Ok(parameters)
+    }
+}
+
+/// Formats the type in signature form.
+impl Display for GenericType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenericType::Base(kind) => write!(f, "{}", Type::new(kind.clone(), 0)),
+            GenericType::Class(class) => write!(f, "{}", class),
+            GenericType::TypeVariable(name) => write!(f, "T{};", name),
+            GenericType::Array(element) => write!(f, "[{}", element),
+        }
+    }
+}
+
+fn write_type_arguments(
+    f: &mut std::fmt::Formatter<'_>,
+    arguments: &[TypeArgument],
+) -> std::fmt::Result {
+    if arguments.is_empty() {
+        return Ok(());
+    }
+
+    write!(f, "<")?;
+    for argument in arguments {
+        match argument {
+            TypeArgument::Any => write!(f, "*")?,
+            TypeArgument::Exact(ty) => write!(f, "{}", ty)?,
+            TypeArgument::Extends(ty) => write!(f, "+{}", ty)?,
+            TypeArgument::Super(ty) => write!(f, "-{}", ty)?,
+        }
+    }
+    write!(f, ">")
+}
+
+fn write_type_parameters(
+    f: &mut std::fmt::Formatter<'_>,
+    parameters: &[TypeParameter],
+) -> std::fmt::Result {
+    if parameters.is_empty() {
+        return Ok(());
+    }
+
+    write!(f, "<")?;
+    for parameter in parameters {
+        write!(f, "{}:", parameter.name)?;
+
+        if let Some(bound) = &parameter.class_bound {
+            write!(f, "{}", bound)?;
+        }
+
+        for bound in &parameter.interface_bounds {
+            write!(f, ":{}", bound)?;
+        }
+    }
+    write!(f, ">")
+}
+
+impl Display for ClassType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "L{}", self.name)?;
+        write_type_arguments(f, &self.type_arguments)?;
+
+        for inner in &self.inner {
+            write!(f, ".{}", inner.name)?;
+            write_type_arguments(f, &inner.type_arguments)?;
+        }
+
+        write!(f, ";")
+    }
+}
+
+impl Display for ClassSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_type_parameters(f, &self.type_parameters)?;
+        write!(f, "{}", self.superclass)?;
+
+        for interface in &self.interfaces {
+            write!(f, "{}", interface)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for MethodSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_type_parameters(f, &self.type_parameters)?;
+
+        write!(f, "(")?;
+        for param in &self.params {
+            write!(f, "{}", param)?;
+        }
+        write!(f, ")")?;
+
+        match &self.return_ty {
+            Some(ty) => write!(f, "{}", ty)?,
+            None => write!(f, "V")?,
+        }
+
+        for ty in &self.throws {
+            write!(f, "^{}", ty)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        error::VmError,
+        value::{MethodDescriptor, Type},
+    };
+
+    use super::{ClassSignature, GenericType, MethodSignature, TypeArgument};
+
+    #[test]
+    fn test_parse_signatures() {
+        let signature = "<K:Ljava/lang/Object;V:Ljava/lang/Object;>Ljava/util/AbstractMap<TK;TV;>;\
+                         Ljava/util/Map<TK;TV;>;Ljava/lang/Cloneable;";
+        let class = ClassSignature::parse(signature).unwrap();
+        assert_eq!(class.type_parameters.len(), 2);
+        assert_eq!(class.superclass.name, "java/util/AbstractMap");
+        assert_eq!(class.interfaces.len(), 2);
+        assert_eq!(class.to_string(), signature);
+
+        let signature = "<T::Ljava/lang/Comparable<-TT;>;>(Ljava/util/Collection<+TT;>;[I)[TT;\
+                         ^Ljava/io/IOException;^TE;";
+        let method = MethodSignature::parse(signature).unwrap();
+        assert_eq!(method.to_string(), signature);
+        assert_eq!(
+            method.erasure(&class.type_parameters),
+            MethodDescriptor::parse("(Ljava/util/Collection;[I)[Ljava/lang/Comparable;").unwrap()
+        );
+
+        let field = GenericType::parse_field("LOuter<TK;>.Inner<*>;").unwrap();
+        assert_eq!(field.to_string(), "LOuter<TK;>.Inner<*>;");
+        assert_eq!(field.erasure(&[]), Type::parse("LOuter$Inner;").unwrap());
+
+        let GenericType::Class(class) = field else {
+            panic!("expected a class type");
+        };
+        assert_eq!(class.inner[0].type_arguments, vec![TypeArgument::Any]);
+
+        for invalid in ["I", "Ljava/util/List<>;", "TT", "Ljava/util/List;I"] {
+            assert!(GenericType::parse_field(invalid).is_err(), "{}", invalid);
+        }
+        assert!(MethodSignature::parse("()V^[I").is_err());
+
+        let array = format!("{}I", "[".repeat(255));
+        assert_eq!(
+            GenericType::parse_field(&array).unwrap().erasure(&[]),
+            Type::parse(&array).unwrap()
+        );
+        assert!(GenericType::parse_field(&format!("[{}", array)).is_err());
+
+        let nested = |depth: usize| format!("{}LB;{}", "LA<".repeat(depth), ">;".repeat(depth));
+        assert!(GenericType::parse_field(&nested(256)).is_ok());
+        for depth in [257, 13000] {
+            assert!(matches!(
+                GenericType::parse_field(&nested(depth)),
+                Err(VmError::InvalidSignature { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_erasure_cycles() {
+        let object = Type::parse("Ljava/lang/Object;").unwrap();
+
+        // Bounds that refer back to their own variable erase to Object rather than recursing
+        for signature in [
+            "<T:TT;>Ljava/lang/Object;",
+            "<T:TU;U:TT;>Ljava/lang/Object;",
+            "<T::TT;>Ljava/lang/Object;",
+        ] {
+            let class = ClassSignature::parse(signature).unwrap();
+            assert_eq!(
+                GenericType::TypeVariable("T".to_string()).erasure(&class.type_parameters),
+                object,
+                "{}",
+                signature
+            );
+        }
+
+        let class = ClassSignature::parse("<T:[TT;>Ljava/lang/Object;").unwrap();
+        assert_eq!(
+            GenericType::TypeVariable("T".to_string()).erasure(&class.type_parameters),
+            Type::parse("[Ljava/lang/Object;").unwrap()
+        );
+    }
+}
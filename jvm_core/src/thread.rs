@@ -1,16 +1,35 @@
-use std::{cell::Cell, ops::*, rc::Rc, sync::atomic::AtomicUsize};
+use std::{
+    cell::Cell,
+    ops::*,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use crate::{
     byte_stream::{ByteStream, ReaderContext},
-    error::Result,
+    bytecode::ClassAccess,
+    class_loader::ClassId,
+    error::{Result, VmError},
     frame::{Frame, FrameFlags},
     instructions::Instruction,
     rf::Rf,
     runtime::Runtime,
-    value::{runtime_pool, Value, Type},
+    value::{runtime_pool::{self, ClassRef}, Value, Type},
 };
 
+/// Identifies a thread, such as the one initializing a class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ThreadId(usize);
+
+impl ThreadId {
+    fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        ThreadId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
 pub struct Thread {
+    id: ThreadId,
     runtime: Rf<Runtime>,
 
     pc: AtomicUsize,
@@ -201,6 +220,7 @@ impl std::fmt::Debug for Thread {
 impl Thread {
     pub fn new(runtime: Rf<Runtime>, pc: usize) -> Thread {
         Thread {
+            id: ThreadId::next(),
             runtime,
             pc: AtomicUsize::new(pc),
             stack: Cell::new(Vec::new()),
@@ -214,15 +234,67 @@ impl Thread {
     }
 
     pub fn run(&self) -> Result<usize> {
+        self.execute().map_err(|e| self.fail_initialization(e))
+    }
+
+    /// Initializes `class` if it hasn't been, running the `<clinit>` of it and its superclasses
+    /// to completion, like `Class.forName` does. The thread must not be running anything else.
+    pub fn initialize(&self, class: &ClassId) -> Result<()> {
+        let class = self.runtime.borrow().class_ref(class)?;
+        let pc = self.pc.load(Ordering::Acquire);
+
+        let result = match self.initialize_class(pc, class) {
+            Ok(Some(clinit)) => {
+                self.pc.store(clinit, Ordering::Release);
+                self.run().map(|_| ())
+            }
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+
+        self.pc.store(pc, Ordering::Release);
+        result
+    }
+
+    /// Since nothing can catch `e` yet, every class this thread was initializing has failed
+    /// (JVMS 5.5 step 12). Reports `e` against the innermost one.
+    fn fail_initialization(&self, e: VmError) -> VmError {
+        let frames = self.frames.take();
+        let class = frames
+            .iter()
+            .rev()
+            .find(|frame| frame.flags.contains(FrameFlags::CLINIT))
+            .map(|frame| frame.class);
+        self.frames.set(frames);
+
+        let reason = e.to_string();
+        let mut rt = self.runtime.borrow_mut();
+        rt.fail_initialization(self.id, &reason);
+
+        match class.map(|class| rt.get_linked(class)) {
+            Some(Ok(class)) => VmError::ExceptionInInitializer {
+                class_name: class.id.name.to_string(),
+                reason,
+            },
+            _ => e,
+        }
+    }
+
+    fn execute(&self) -> Result<usize> {
         let ctx = ReaderContext {
             constant_pool: Vec::new(),
+            major_version: 0,
+            view: None,
         };
 
         loop {
             let ip = self.pc.load(std::sync::atomic::Ordering::Acquire);
             let bytes: [u8; 6] = {
                 let rt = self.runtime.borrow();
-                let mut instructions = rt.instructions()[ip..].iter();
+                let mut instructions = rt
+                    .code_at(ip)
+                    .ok_or(VmError::InvalidPc(ip))?
+                    .iter();
 
                 core::array::from_fn(|_| instructions.next().copied().unwrap_or(0))
             };
@@ -230,7 +302,7 @@ impl Thread {
             let mut stream = ByteStream::new(&bytes[..]);
 
             let instructin_address = ip;
-            let instruction = Instruction::from(stream.read::<u8>(&ctx));
+            let instruction = Instruction::from(stream.read::<u8>(&ctx)?);
 
             let mut ip_override = None;
             {
@@ -406,12 +478,12 @@ impl Thread {
 
                     // Other instructions
                     Instruction::BiPush => {
-                        let byte = stream.read::<i8>(&ctx) as i32;
+                        let byte = stream.read::<i8>(&ctx)? as i32;
 
                         self.push(byte);
                     },
                     Instruction::SiPush => {
-                        let short = stream.read::<i16>(&ctx) as i32;
+                        let short = stream.read::<i16>(&ctx)? as i32;
 
                         self.push(short);
                     },
@@ -437,14 +509,14 @@ impl Thread {
                         self.push(5i32);
                     },
                     Instruction::ILoad => {
-                        let index = stream.read::<u8>(&ctx);
+                        let index = stream.read::<u8>(&ctx)?;
                         let value = self.get_local(index);
                         value.as_int();
 
                         self.push(value);
                     },
                     Instruction::IStore => {
-                        let index = stream.read::<u8>(&ctx);
+                        let index = stream.read::<u8>(&ctx)?;
                         let value = self.pop();
                         value.as_int();
 
@@ -497,8 +569,8 @@ impl Thread {
                         }
                     },
                     Instruction::IInc => {
-                        let index = stream.read::<u8>(&ctx) as usize;
-                        let constant = stream.read::<i8>(&ctx) as i32;
+                        let index = stream.read::<u8>(&ctx)? as usize;
+                        let constant = stream.read::<i8>(&ctx)? as i32;
 
                         let mut frames = self.frames.take();
                         let frame = frames.last_mut().expect("Unable to get current frame!");
@@ -520,14 +592,14 @@ impl Thread {
                         self.push(1i64);
                     },
                     Instruction::LLoad => {
-                        let index = stream.read::<u8>(&ctx);
+                        let index = stream.read::<u8>(&ctx)?;
                         let value = self.get_local(index);
                         value.as_long();
 
                         self.push(value);
                     },
                     Instruction::LStore => {
-                        let index = stream.read::<u8>(&ctx);
+                        let index = stream.read::<u8>(&ctx)?;
                         let value = self.pop();
                         value.as_long();
 
@@ -590,14 +662,14 @@ impl Thread {
                         self.push(2f32);
                     },
                     Instruction::FLoad => {
-                        let index = stream.read::<u8>(&ctx);
+                        let index = stream.read::<u8>(&ctx)?;
                         let value = self.get_local(index);
                         value.as_float();
 
                         self.push(value);
                     },
                     Instruction::FStore => {
-                        let index = stream.read::<u8>(&ctx);
+                        let index = stream.read::<u8>(&ctx)?;
                         let value = self.pop();
                         value.as_float();
 
@@ -616,14 +688,14 @@ impl Thread {
                         self.push(1f32);
                     },
                     Instruction::DLoad => {
-                        let index = stream.read::<u8>(&ctx);
+                        let index = stream.read::<u8>(&ctx)?;
                         let value = self.get_local(index);
                         value.as_double();
 
                         self.push(value);
                     },
                     Instruction::DStore => {
-                        let index = stream.read::<u8>(&ctx);
+                        let index = stream.read::<u8>(&ctx)?;
                         let value = self.pop();
                         value.as_double();
 
@@ -768,12 +840,12 @@ impl Thread {
                     },
 
                     Instruction::Goto => {
-                        let offset = stream.read::<i16>(&ctx);
+                        let offset = stream.read::<i16>(&ctx)?;
 
                         ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                     },
                     Instruction::GotoW => {
-                        let offset = stream.read::<i32>(&ctx);
+                        let offset = stream.read::<i32>(&ctx)?;
 
                         ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                     },
@@ -782,7 +854,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 == value2 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -793,7 +865,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 != value2 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -804,7 +876,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 < value2 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -815,7 +887,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 > value2 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -826,7 +898,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 <= value2 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -837,7 +909,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 >= value2 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -915,7 +987,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 == 0 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -925,7 +997,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 != 0 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -935,7 +1007,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 < 0 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -945,7 +1017,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 > 0 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -955,7 +1027,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 <= 0 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -965,7 +1037,7 @@ impl Thread {
                         let value1 = self.pop().as_int();
 
                         if value1 >= 0 {
-                            let offset = stream.read::<i16>(&ctx);
+                            let offset = stream.read::<i16>(&ctx)?;
                             ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                         } else {
                             stream.index += 2;
@@ -976,7 +1048,7 @@ impl Thread {
 
                         match value1 {
                             Value::Null => {
-                                let offset = stream.read::<i16>(&ctx);
+                                let offset = stream.read::<i16>(&ctx)?;
                                 ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                             }
                             _ => ()
@@ -988,7 +1060,7 @@ impl Thread {
 
                         match value1 {
                             Value::Reference => {
-                                let offset = stream.read::<i16>(&ctx);
+                                let offset = stream.read::<i16>(&ctx)?;
                                 ip_override = Some(instructin_address.checked_add_signed(offset as isize).expect("Program counter overflow!"))
                             }
                             _ => ()
@@ -1039,6 +1111,15 @@ impl Thread {
                     },
                     Instruction::Return => {
                         let frames = self.frames.take();
+                        if frames.last().is_some_and(|frame| frame.flags.contains(FrameFlags::CLINIT)) {
+                            match self.return_from_clinit(frames) {
+                                Some(ip) => ip_override = Some(ip),
+                                // The thread was only initializing classes
+                                None => return Ok(0),
+                            }
+                            break 'outer;
+                        }
+
                         if frames.len() == 1 {
                             self.frames.set(frames);
                             return Ok(0);
@@ -1053,21 +1134,21 @@ impl Thread {
                         Instruction::DReturn =;
                     ,
                     Instruction::ALoad => {
-                        let index = stream.read::<u8>(&ctx);
+                        let index = stream.read::<u8>(&ctx)?;
                         let value = self.get_local(index);
                         value.as_array();
 
                         self.push(value);
                     },
                     Instruction::AStore => {
-                        let index = stream.read::<u8>(&ctx);
+                        let index = stream.read::<u8>(&ctx)?;
                         let value = self.pop();
                         value.as_array();
                         
                         self.set_local(index, value);
                     },
                     Instruction::NewArray => {
-                        let type_tag = stream.read::<u8>(&ctx);
+                        let type_tag = stream.read::<u8>(&ctx)?;
                         let count = self.pop().as_int() as usize;
 
                         let vals = vec![Value::default_with_type(&Type::from_array_tag(type_tag)); count];
@@ -1082,114 +1163,90 @@ impl Thread {
                         self.push(Value::Int(array.len() as i32));
                     },
                     Instruction::GetStatic => {
-                        let index = stream.read::<u16>(&ctx);
+                        let index = stream.read::<u16>(&ctx)?;
                         tracing::debug!("{}", index);
 
-                        let frames = self.frames.take();
-                        let frame = frames.last().expect("Unable to retrieve current frame!");
-
-                        // If we are initializing we don't want to start revursively initializing
-                        if !frame.flags.contains(FrameFlags::CLINIT) {
-
-                            let (class, is_init) = {
-                                let mut rt = self.runtime.borrow_mut();
-                                let (class, _) = rt.get_field_by_index_mut(&frame.class_name, index).expect("Unable to get static field!");
-                                let is_init = rt.is_class_initialized(&class);
-
-                                (class, is_init)
-                            };
-
-                            self.frames.set(frames);
-                            if !is_init {
-                                // We want to return to this instruction when done initializing.
-                                if let Some(ip) = self.initialize_class(ip, &class, index) {
-                                    ip_override = Some(ip);
-                                    break 'outer;
-                                }
-                            }
-                        } else {
-                            self.frames.set(frames);
+                        // The class that declares the field is the one that's initialized
+                        let field = self.resolve(|rt, class| rt.resolve_field(class, index))?;
+                        if let Some(ip) = self.initialize_class(ip, field.class)? {
+                            // We want to return to this instruction when done initializing.
+                            ip_override = Some(ip);
+                            break 'outer;
                         }
 
-                        let frames = self.frames.take();
-                        let frame = frames.last().expect("Unable to retrieve current frame!");
-
-                        let rt = self.runtime.borrow_mut();
-                        let (class, name, field) = rt.get_field_by_index(&frame.class_name, index).expect("Unable to get static field!");
-
-                        self.push(field.value.clone());
-
-                        self.frames.set(frames);
+                        let value = self.runtime.borrow().get_static_field(field)?.value.clone();
+                        self.push(value);
                     },
                     Instruction::PutStatic => {
-                        let index = stream.read::<u16>(&ctx);
+                        let index = stream.read::<u16>(&ctx)?;
                         tracing::debug!("{}", index);
-                        tracing::debug!("{:?}", &self);
-
-                        let frames = self.frames.take();
-                        tracing::debug!("{:?}", frames);
-                        let frame = frames.last().expect("Unable to retrieve current frame!");
-
-                        // If we are initializing we don't want to start revursively initializing
-                        if !frame.flags.contains(FrameFlags::CLINIT) {
-                            tracing::info!("CLINIT");
-                            let (class, is_init) = {
-                                let mut rt = self.runtime.borrow_mut();
-                                let (class, _) = rt.get_field_by_index_mut(&frame.class_name, index).expect("Unable to get static field!");
-                                let is_init = rt.is_class_initialized(&class);
-
-                                (class, is_init)
-                            };
 
-                            self.frames.set(frames);
-                            if !is_init {
-                                // We want to return to this instruction when done initializing.
-                                if let Some(ip) = self.initialize_class(ip, &class, index) {
-                                    ip_override = Some(ip);
-                                    break 'outer;
-                                }
-                            }
-                        } else {
-                            self.frames.set(frames);
+                        let field = self.resolve(|rt, class| rt.resolve_field(class, index))?;
+                        if let Some(ip) = self.initialize_class(ip, field.class)? {
+                            // We want to return to this instruction when done initializing.
+                            ip_override = Some(ip);
+                            break 'outer;
                         }
 
-                        let frames = self.frames.take();
-                        let frame = frames.last().expect("Unable to retrieve current frame!");
-
-                        let mut rt = self.runtime.borrow_mut();
-                        let (class, field) = rt.get_field_by_index_mut(&frame.class_name, index).expect("Unable to get static field!");
-
                         let value = self.pop();
 
+                        let mut rt = self.runtime.borrow_mut();
+                        let field = rt.get_static_field_mut(field)?;
                         value.matches_type(&field.ty)
                             .then_some(())
                             .expect("Value does not match type!");
 
                         field.value = value;
-
-                        self.frames.set(frames);
                     },
-                    Instruction::InvokeStatic => {
-                        let index = stream.read::<u16>(&ctx);
+                    Instruction::New => {
+                        let index = stream.read::<u16>(&ctx)?;
 
-                        let mut frames = self.frames.take();
-                        let frame = frames.last().expect("Unable to retrieve current call frame!");
+                        let class = self.resolve(|rt, class| rt.resolve_class(class, index))?;
 
-                        let mut rt = self.runtime.borrow_mut();
-                        let class = {
-                            rt.get_or_load_class_item(&frame.class_name, index).expect("Class not found!")
-                        };
+                        {
+                            let rt = self.runtime.borrow();
+                            let id = &rt.get_linked(class)?.id;
+
+                            let file = rt.get_class_file(id)?.access_flags();
+                            if file.intersects(ClassAccess::ABSTRACT | ClassAccess::INTERFACE) {
+                                return Err(VmError::Instantiation(id.name.to_string()));
+                            }
+                        }
 
-                        let (class_name, method_name, method) = rt.get_method_by_index(&frame.class_name, index).expect("Method not found!");
+                        if let Some(ip) = self.initialize_class(ip, class)? {
+                            ip_override = Some(ip);
+                            break 'outer;
+                        }
+
+                        // There's no heap to allocate the object on yet
+                        self.push(Value::Reference);
+                    },
+                    Instruction::InvokeStatic => {
+                        let index = stream.read::<u16>(&ctx)?;
+
+                        let resolved = self.resolve(|rt, class| rt.resolve_method(class, index))?;
+                        // The method must be static before its class is initialized
+                        self.runtime.borrow().get_static_method(resolved)?;
+                        if let Some(ip) = self.initialize_class(ip, resolved.class)? {
+                            ip_override = Some(ip);
+                            break 'outer;
+                        }
+
+                        let rt = self.runtime.borrow();
+                        let method = rt.get_static_method(resolved)?;
 
                         match method {
                             runtime_pool::Method::Native(method) => {
+                                let Some(function) = method.function else {
+                                    return Err(VmError::UnsatisfiedLink(rt.method_name(resolved)?));
+                                };
+
                                 // TODO; check types
                                 let param_len = method.params.len();
                                 let mut stack = self.stack.take();
                                 let params = &stack[stack.len() - param_len..];
 
-                                let value = rt.invoke_native_function(&format!("{}.{}", class_name, method_name), params);
+                                let value = rt.invoke_native_function(function, params);
 
                                 stack.truncate(stack.len() - param_len);
                                 self.stack.set(stack);
@@ -1205,19 +1262,28 @@ impl Thread {
                                 let params = &stack[stack.len() - param_len..];
 
                                 tracing::info!("{} {}", instructin_address, stream.index);
-                                let mut new_frame = Frame::new(stack.len() - param_len, instructin_address + 1, class.clone());
-                                new_frame.locals.extend(params.iter().cloned());
+                                let mut new_frame = Frame::new(stack.len() - param_len, instructin_address + 1, resolved.class);
+                                for (param, ty) in params.iter().zip(&method.params) {
+                                    new_frame.locals.push(param.clone());
+
+                                    // Long and double parameters take up two local variable slots
+                                    if ty.slots() == 2 {
+                                        new_frame.locals.push(Value::Uninit);
+                                    }
+                                }
+                                let mut frames = self.frames.take();
                                 frames.push(new_frame);
+                                self.frames.set(frames);
 
                                 ip_override = Some(method.code_index);
 
                                 stack.truncate(stack.len() - param_len);
                                 self.stack.set(stack);
                             },
-                            _ => (),
+                            runtime_pool::Method::Abstract(_) => {
+                                return Err(VmError::AbstractMethod(rt.method_name(resolved)?));
+                            }
                         }
-
-                        self.frames.set(frames);
                     },
                     Instruction::Nop => {},
                     _ => {unimplemented!()},
@@ -1283,33 +1349,77 @@ impl Thread {
         ptr
     }
 
-    fn initialize_class(&self, ip: usize, class_name: &Rc<str>, index: u16) -> Option<usize> {
-        let stack = self.stack.take();
-        let mut frames = self.frames.take();
+    /// Resolves a symbolic reference in the current class's constant pool with `resolve`.
+    fn resolve<T>(&self, resolve: impl FnOnce(&mut Runtime, ClassRef) -> Result<T>) -> Result<T> {
+        let frames = self.frames.take();
+        let class = frames.last().expect("Unable to retrieve current frame!").class;
+        self.frames.set(frames);
+
+        resolve(&mut self.runtime.borrow_mut(), class)
+    }
+
+    /// Starts initializing `class` if it hasn't been (JVMS 5.5), by pushing a frame for the
+    /// first `<clinit>` that has to run. They return to `ip`, which should be the instruction
+    /// that needed the class, so it runs again once the class is initialized.
+    ///
+    /// Returns where to jump to, or `None` if the class can be used straight away.
+    fn initialize_class(&self, ip: usize, class: ClassRef) -> Result<Option<usize>> {
+        let mut pending = self
+            .runtime
+            .borrow_mut()
+            .begin_initialization(class, self.id)?;
+
+        // Superclasses come first, so run the classes from the end
+        pending.reverse();
+        Ok(self.run_next_clinit(ip, pending))
+    }
 
+    /// Pushes a frame for the last class in `pending` that has a `<clinit>`, keeping the rest
+    /// for when it returns. Classes without one are initialized straight away.
+    fn run_next_clinit(&self, ip: usize, mut pending: Vec<ClassRef>) -> Option<usize> {
         let mut rt = self.runtime.borrow_mut();
-        {
-            rt.get_or_load_class_item(&class_name, index)
-                .expect("Class not found!")
-        };
-        rt.set_class_initialized(&class_name);
 
-        let Some(method) = rt
-            .get_method_by_name(&class_name, "<clinit>") else {
-                // If no clinit, there is nothing to run.
-                self.frames.set(frames);
-                self.stack.set(stack);
-                return None;
-        };
+        while let Some(class) = pending.pop() {
+            let Some(method) = rt.get_method(class, "<clinit>", "()V") else {
+                rt.finish_initialization(class);
+                continue;
+            };
+            let code_index = method.as_method().code_index;
 
-        let new_frame = Frame::new_clinit(stack.len(), ip, class_name.clone());
-        tracing::info!("{new_frame:?}");
-        frames.push(new_frame);
+            let stack = self.stack.take();
+            let mut frames = self.frames.take();
 
-        self.frames.set(frames);
+            let new_frame = Frame::new_clinit(stack.len(), ip, class).with_pending(pending);
+            tracing::info!("{new_frame:?}");
+            frames.push(new_frame);
+
+            self.frames.set(frames);
+            self.stack.set(stack);
+
+            return Some(code_index);
+        }
+
+        None
+    }
+
+    /// Pops a `<clinit>` frame that has returned, marking its class initialized, and moves on to
+    /// the next pending `<clinit>`. Returns `None` if there's nothing left to run, because the
+    /// thread was only initializing classes.
+    fn return_from_clinit(&self, mut frames: Vec<Frame>) -> Option<usize> {
+        let frame = frames.pop().unwrap();
+        let is_empty = frames.is_empty();
+
+        let mut stack = self.stack.take();
+        stack.truncate(frame.base_pointer);
         self.stack.set(stack);
+        self.frames.set(frames);
+
+        self.runtime
+            .borrow_mut()
+            .finish_initialization(frame.class);
 
-        Some(method.as_method().code_index)
+        self.run_next_clinit(frame.return_pc, frame.pending)
+            .or((!is_empty).then_some(frame.return_pc))
     }
 
     // fn set_stack_index(&self, index: usize) -> {
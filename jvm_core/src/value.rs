@@ -1,6 +1,9 @@
-use std::{fmt::Display, sync::Arc};
+use std::{fmt::Display, str::FromStr, sync::Arc};
 
-use crate::rf::Rf;
+use crate::{
+    error::{Result, VmError},
+    rf::Rf,
+};
 
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -21,6 +24,11 @@ pub enum Value {
 
 impl Value {
     pub fn default_with_type(ty: &Type) -> Value {
+        // Arrays of any element type start out null
+        if ty.array_dimensions() > 0 {
+            return Value::Null;
+        }
+
         match ty.kind {
             TypeKind::Byte => Value::Byte(0),
             TypeKind::Char => Value::Char(0),
@@ -397,36 +405,104 @@ pub struct Type {
 }
 
 impl Type {
-    /// Parses method signature suchas "(Ljava/lang/String;)V"
-    ///
-    /// ##Example
-    /// ```
-    /// use crate::value::Type;
-    ///
-    /// let signature = "(I,Z,B)V";
-    /// let (params, ty) = parse_signature(signature);
-    ///
-    /// assert_eq!(params, vec![Type::Int, Type::Boolean, Type::Byte]);
-    /// assert_eq!(ret, None);
-    /// ```
-    pub fn parse_signature(str: impl AsRef<str>) -> (Vec<Type>, Option<Type>) {
-        let s = str.as_ref();
-
-        let mut iter = s.split_terminator(['(', ')']);
-
-        let Some("") = iter.next() else { panic!("Malformed method signature!") };
-        let params = iter.next().expect("Malformed method signature!");
-        let params: Vec<_> = params
-            .split(",")
-            .take_while(|s| !s.is_empty())
-            .map(Type::from)
-            .collect();
-
-        let ret_ty = iter.next().expect("Malformed method signature!");
-
-        match ret_ty {
-            "V" => (params, None),
-            c => (params, Some(Type::from(c))),
+    pub fn new(kind: TypeKind, array_dimensions: u8) -> Type {
+        Type {
+            array_dimensions,
+            kind,
+        }
+    }
+
+    /// The element type for arrays, otherwise the type itself.
+    pub fn kind(&self) -> &TypeKind {
+        &self.kind
+    }
+
+    pub fn array_dimensions(&self) -> u8 {
+        self.array_dimensions
+    }
+
+    /// Whether values of this type are references to objects or arrays.
+    pub fn is_reference(&self) -> bool {
+        self.array_dimensions > 0 || matches!(self.kind, TypeKind::Class(_) | TypeKind::Reference)
+    }
+
+    /// Parses a field descriptor such as `[Ljava/lang/String;` (JVMS 4.3.2).
+    pub fn parse(descriptor: &str) -> Result<Type> {
+        let mut pos = 0;
+        let ty = Self::parse_at(descriptor, &mut pos)?;
+
+        if pos != descriptor.len() {
+            return Err(invalid_descriptor(
+                descriptor,
+                pos,
+                "expected the end of the descriptor",
+            ));
+        }
+
+        Ok(ty)
+    }
+
+    /// Parses a single field type starting at `pos`, leaving `pos` just after it.
+    fn parse_at(descriptor: &str, pos: &mut usize) -> Result<Type> {
+        let bytes = descriptor.as_bytes();
+        let mut array_dimensions = 0usize;
+
+        while bytes.get(*pos) == Some(&b'[') {
+            array_dimensions += 1;
+            *pos += 1;
+        }
+
+        if array_dimensions > 255 {
+            return Err(invalid_descriptor(
+                descriptor,
+                *pos,
+                "arrays can have at most 255 dimensions",
+            ));
+        }
+
+        let kind = match bytes.get(*pos) {
+            Some(b'B') => TypeKind::Byte,
+            Some(b'C') => TypeKind::Char,
+            Some(b'D') => TypeKind::Double,
+            Some(b'F') => TypeKind::Float,
+            Some(b'I') => TypeKind::Int,
+            Some(b'J') => TypeKind::Long,
+            Some(b'S') => TypeKind::Short,
+            Some(b'Z') => TypeKind::Boolean,
+            Some(b'L') => {
+                let rest = &descriptor[*pos + 1..];
+                let Some(end) = rest.find(';') else {
+                    return Err(invalid_descriptor(
+                        descriptor,
+                        *pos,
+                        "class name is missing its ';'",
+                    ));
+                };
+
+                let name = &rest[..end];
+                let invalid_part = |part: &str| part.is_empty() || part.contains(['.', '[']);
+                if name.split('/').any(invalid_part) {
+                    return Err(invalid_descriptor(descriptor, *pos + 1, "invalid class name"));
+                }
+
+                *pos += end + 1;
+                TypeKind::Class(name.to_string())
+            }
+            _ => return Err(invalid_descriptor(descriptor, *pos, "expected a field type")),
+        };
+        *pos += 1;
+
+        Ok(Type {
+            array_dimensions: array_dimensions as u8,
+            kind,
+        })
+    }
+
+    /// The number of local variable or operand stack slots a value of this type takes up.
+    pub fn slots(&self) -> usize {
+        match self.kind {
+            TypeKind::Long | TypeKind::Double if self.array_dimensions == 0 => 2,
+            _ => 1,
         }
     }
 
@@ -501,37 +577,119 @@ impl Type {
     }
 }
 
-impl<S: AsRef<str>> From<S> for Type {
-    fn from(value: S) -> Self {
-        let s = value.as_ref();
-        let mut chars = s.chars();
-        let mut arr = 0;
-
-        while let Some(char) = chars.next() {
-            let ty = match char {
-                'B' => TypeKind::Byte,
-                'C' => TypeKind::Char,
-                'D' => TypeKind::Double,
-                'F' => TypeKind::Float,
-                'I' => TypeKind::Int,
-                'S' => TypeKind::Short,
-                'Z' => TypeKind::Boolean,
-                'J' => TypeKind::Long,
-                'L' => TypeKind::Class(chars.take_while(|c| *c != ';').collect()),
-                '[' => {
-                    arr += 1;
-                    continue;
+impl FromStr for Type {
+    type Err = VmError;
+
+    fn from_str(descriptor: &str) -> Result<Self> {
+        Type::parse(descriptor)
+    }
+}
+
+/// Formats the type as a field descriptor.
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for _ in 0..self.array_dimensions {
+            write!(f, "[")?;
+        }
+
+        match &self.kind {
+            TypeKind::Boolean => write!(f, "Z"),
+            TypeKind::Char => write!(f, "C"),
+            TypeKind::Byte => write!(f, "B"),
+            TypeKind::Short => write!(f, "S"),
+            TypeKind::Int => write!(f, "I"),
+            TypeKind::Long => write!(f, "J"),
+            TypeKind::Float => write!(f, "F"),
+            TypeKind::Double => write!(f, "D"),
+            // An untyped reference can hold any object
+            TypeKind::Reference => write!(f, "Ljava/lang/Object;"),
+            TypeKind::Class(name) => write!(f, "L{};", name),
+        }
+    }
+}
+
+/// The parameter and return types of a method (JVMS 4.3.3).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodDescriptor {
+    pub params: Vec<Type>,
+    /// `None` for methods that return void.
+    pub return_ty: Option<Type>,
+}
+
+impl MethodDescriptor {
+    /// Parses a method descriptor such as `(ILjava/lang/String;[J)V`.
+    pub fn parse(descriptor: &str) -> Result<MethodDescriptor> {
+        if !descriptor.starts_with('(') {
+            return Err(invalid_descriptor(descriptor, 0, "expected '('"));
+        }
+
+        let mut pos = 1;
+        let mut params = Vec::new();
+
+        loop {
+            match descriptor.as_bytes().get(pos) {
+                Some(b')') => break,
+                Some(_) => params.push(Type::parse_at(descriptor, &mut pos)?),
+                None => return Err(invalid_descriptor(descriptor, pos, "expected ')'")),
+            }
+        }
+        pos += 1;
+
+        let return_ty = match &descriptor[pos..] {
+            "V" => None,
+            _ => {
+                let mut end = pos;
+                let ty = Type::parse_at(descriptor, &mut end)?;
+
+                if end != descriptor.len() {
+                    return Err(invalid_descriptor(
+                        descriptor,
+                        end,
+                        "expected the end of the descriptor",
+                    ));
                 }
-                _ => panic!("Malformed type string!"),
-            };
 
-            return Type {
-                array_dimensions: arr,
-                kind: ty,
-            };
+                Some(ty)
+            }
+        };
+
+        Ok(MethodDescriptor { params, return_ty })
+    }
+
+    /// The number of local variable slots taken up by the parameters, not including `this`.
+    pub fn param_slots(&self) -> usize {
+        self.params.iter().map(Type::slots).sum()
+    }
+}
+
+impl FromStr for MethodDescriptor {
+    type Err = VmError;
+
+    fn from_str(descriptor: &str) -> Result<Self> {
+        MethodDescriptor::parse(descriptor)
+    }
+}
+
+impl Display for MethodDescriptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(")?;
+        for param in &self.params {
+            write!(f, "{}", param)?;
+        }
+        write!(f, ")")?;
+
+        match &self.return_ty {
+            Some(ty) => write!(f, "{}", ty),
+            None => write!(f, "V"),
         }
+    }
+}
 
-        panic!("Malformed type string! {}", s);
+fn invalid_descriptor(descriptor: &str, position: usize, reason: &str) -> VmError {
+    VmError::InvalidDescriptor {
+        descriptor: descriptor.to_string(),
+        position,
+        reason: reason.to_string(),
     }
 }
 
@@ -541,24 +699,165 @@ pub enum RuntimePool {
 }
 
 pub mod runtime_pool {
-    use std::collections::HashMap;
+    use std::{collections::HashMap, rc::Rc};
+
+    use crate::{class_loader::ClassId, error::VmError, runtime::InitState};
 
     use super::{Type, TypeKind, Value};
 
+    /// A linked class, by its index in the runtime's classes. Indices aren't reused once a
+    /// class is unloaded, so a stale one can't refer to some other class.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct ClassRef(pub(crate) usize);
+
     #[derive(Debug)]
     pub struct Class {
-        pub methods: HashMap<String, Method>,
-        pub fields: HashMap<String, Field>,
+        pub id: ClassId,
+        pub init_state: InitState,
+        /// `None` only for `java/lang/Object`.
+        pub super_class: Option<ClassId>,
+        pub interfaces: Vec<ClassId>,
+        /// In the order the class file declares them, so other tables can refer to methods by
+        /// index.
+        pub methods: Vec<Method>,
+        /// Indices into `methods` by name, then descriptor, as methods can be overloaded.
+        pub method_indices: HashMap<String, HashMap<String, usize>>,
+        /// The static fields the class declares, in the order it declares them.
+        pub static_fields: Vec<Field>,
+        /// Where each field the class declares is stored, by name, then descriptor, as fields of
+        /// different types can share a name (JVMS 4.5).
+        pub field_indices: HashMap<String, HashMap<String, FieldSlot>>,
+        /// How instances of the class store their fields, including inherited ones.
+        pub layout: Layout,
+        /// The class file's constant pool entries that have been resolved, at the same indices,
+        /// so each is only resolved once (JVMS 5.4.3).
+        pub resolved: Vec<Option<Resolved>>,
+    }
+
+    impl Class {
+        pub fn method_index(&self, name: &str, descriptor: &str) -> Option<usize> {
+            self.method_indices.get(name)?.get(descriptor).copied()
+        }
+
+        pub fn method(&self, name: &str, descriptor: &str) -> Option<&Method> {
+            self.method_index(name, descriptor)
+                .map(|index| &self.methods[index])
+        }
+
+        pub fn field_slot(&self, name: &str, descriptor: &str) -> Option<FieldSlot> {
+            self.field_indices.get(name)?.get(descriptor).copied()
+        }
+
+        pub fn static_field(&self, name: &str, descriptor: &str) -> Option<&Field> {
+            match self.field_slot(name, descriptor)? {
+                FieldSlot::Static(index) => Some(&self.static_fields[index]),
+                FieldSlot::Instance(_) => None,
+            }
+        }
+    }
+
+    /// A symbolic reference that has been resolved, or that failed to be.
+    #[derive(Debug, Clone)]
+    pub enum Resolved {
+        Class(ClassRef),
+        Field(FieldRef),
+        Method(MethodRef),
+        /// Later attempts to resolve the reference fail with the same error.
+        Error(VmError),
+    }
+
+    /// Where a field is stored: in the static fields of the class that declares it, or in a
+    /// slot of its instances.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum FieldSlot {
+        Static(usize),
+        Instance(usize),
+    }
+
+    /// A resolved field, which may be declared by a supertype of the class it was referenced
+    /// through.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct FieldRef {
+        /// The class that declares the field.
+        pub class: ClassRef,
+        pub slot: FieldSlot,
+    }
+
+    /// A resolved method, by its index in the methods of the class that declares it.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct MethodRef {
+        pub class: ClassRef,
+        pub index: usize,
     }
 
     #[derive(Debug)]
     pub struct Field {
+        pub name: String,
         pub ty: Type,
         pub value: Value,
     }
 
+    /// Where the instance fields of a class are stored in its objects, which have a slot for
+    /// each field. The fields of a subclass come after those of its superclass, so a field is
+    /// in the same slot in objects of every subclass.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct Layout {
+        /// Every instance field, including inherited ones, by slot.
+        pub slots: Vec<Slot>,
+        /// The slots that hold references, in order, for a garbage collector to trace.
+        pub references: Vec<usize>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Slot {
+        /// The class that declares the field.
+        pub class: ClassId,
+        pub name: String,
+        pub ty: Type,
+    }
+
+    impl Layout {
+        /// The layout of a subclass of this one, which declares `fields` in that order.
+        pub fn extend(
+            &self,
+            class: &ClassId,
+            fields: impl IntoIterator<Item = (String, Type)>,
+        ) -> Layout {
+            let mut layout = self.clone();
+
+            for (name, ty) in fields {
+                if ty.is_reference() {
+                    layout.references.push(layout.slots.len());
+                }
+
+                layout.slots.push(Slot {
+                    class: class.clone(),
+                    name,
+                    ty,
+                });
+            }
+
+            layout
+        }
+
+        /// The number of slots in an instance.
+        pub fn size(&self) -> usize {
+            self.slots.len()
+        }
+    }
+
     #[derive(Debug)]
     pub struct NativeMethod {
+        /// The runtime's native function for the method, found when it's linked.
+        pub function: Option<usize>,
+
+        pub params: Vec<Type>,
+        pub return_ty: Option<Type>,
+        pub is_static: bool,
+    }
+
+    #[derive(Debug)]
+    pub struct AbstractMethod {
         pub params: Vec<Type>,
         pub return_ty: Option<Type>,
     }
@@ -567,19 +866,32 @@ pub mod runtime_pool {
     pub struct JavaMethod {
         pub max_locals: u16,
         pub max_stack: u16,
+        /// The pc of the first instruction of `code`.
         pub code_index: usize,
+        pub code: Rc<[u8]>,
 
         pub params: Vec<Type>,
         pub return_ty: Option<Type>,
+        pub is_static: bool,
     }
 
     #[derive(Debug)]
     pub enum Method {
         Native(NativeMethod),
+        Abstract(AbstractMethod),
         Java(JavaMethod),
     }
 
     impl Method {
+        /// Abstract methods are never static.
+        pub fn is_static(&self) -> bool {
+            match self {
+                Method::Native(method) => method.is_static,
+                Method::Abstract(_) => false,
+                Method::Java(method) => method.is_static,
+            }
+        }
+
         pub fn as_method(&self) -> &JavaMethod {
             match self {
                 Method::Java(method) => method,
@@ -593,12 +905,33 @@ pub mod runtime_pool {
 mod test {
     #[test]
     fn test_parse_sig() {
-        use crate::value::Type;
-
-        let signature = "(I,Z,B)V";
-        let (params, ty) = Type::parse_signature(signature);
+        use crate::value::{MethodDescriptor, Type};
+
+        let signature = "(ILjava/lang/String;[JZ)V";
+        let descriptor = MethodDescriptor::parse(signature).unwrap();
+
+        assert_eq!(
+            descriptor.params,
+            vec![
+                Type::int(),
+                Type::parse("Ljava/lang/String;").unwrap(),
+                Type::parse("[J").unwrap(),
+                Type::boolean()
+            ]
+        );
+        assert_eq!(descriptor.return_ty, None);
+        assert_eq!(descriptor.param_slots(), 4);
+        assert_eq!(descriptor.to_string(), signature);
+
+        let descriptor = MethodDescriptor::parse("(DJ)[[Ljava/lang/Object;").unwrap();
+        assert_eq!(descriptor.param_slots(), 4);
+        assert_eq!(descriptor.to_string(), "(DJ)[[Ljava/lang/Object;");
+
+        let invalid = ["", "I", "(I", "(I,Z,B)V", "()", "()VV", "(Ljava/lang/String)V", "(L;)V"];
+        for invalid in invalid {
+            assert!(MethodDescriptor::parse(invalid).is_err(), "{}", invalid);
+        }
 
-        assert_eq!(params, vec![Type::int(), Type::boolean(), Type::byte()]);
-        assert_eq!(ty, None);
+        assert!(Type::parse("II").is_err());
     }
 }
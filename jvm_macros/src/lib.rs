@@ -43,28 +43,30 @@ pub fn derive_stream_reader(tokens: proc_macro::TokenStream) -> proc_macro::Toke
                 match get_attribute(attrs, "many") {
                     Some(AttrData::Many(ty)) => {
                         if let Some(ident) = ident {
+                            let name = ident.to_string();
                             out_tokens.extend(quote! {
                                 #ident: {
-                                    let len = stream.read::<#ty>(ctx);
-                                    stream.read_many(len as usize, ctx)
+                                    let len = stream.read::<#ty>(ctx).map_err(|e| e.within(#name))?;
+                                    stream.read_many(len as usize, ctx).map_err(|e| e.within(#name))?
                                 },
                             });
                         } else {
                             out_tokens.extend(quote! {
                                 {
-                                    let len = stream.read::<#ty>();
-                                    stream.read_many(len as usize, ctx)
+                                    let len = stream.read::<#ty>(ctx)?;
+                                    stream.read_many(len as usize, ctx)?
                                 },
                             });
                         }
                     },
                     _ => {
                         if let Some(ident) = ident {
+                            let name = ident.to_string();
                             out_tokens.extend(quote! {
-                                #ident: stream.read(ctx),
+                                #ident: stream.read(ctx).map_err(|e| e.within(#name))?,
                             });
                         } else {
-                            out_tokens.extend(quote! {stream.read(ctx), });
+                            out_tokens.extend(quote! {stream.read(ctx)?, });
                         }
                     }
                 }
@@ -94,7 +96,64 @@ pub fn derive_stream_reader(tokens: proc_macro::TokenStream) -> proc_macro::Toke
     let tokens = quote! {
         impl crate::byte_stream::StreamRead for #ty_ident {
             #[allow(dead_code)]
-            fn read<'a>(stream: &mut crate::byte_stream::ByteStream<'a>, ctx: &crate::byte_stream::ReaderContext) -> Self {
+            fn read<'a>(stream: &mut crate::byte_stream::ByteStream<'a>, ctx: &crate::byte_stream::ReaderContext) -> crate::error::Result<Self> {
+                Ok(#tokens)
+            }
+        }
+    };
+
+    tokens.into()
+}
+
+#[proc_macro_derive(StreamWriter, attributes(many))]
+pub fn derive_stream_writer(tokens: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(tokens as syn::DeriveInput);
+
+    let ty_ident = &input.ident;
+
+    let tokens = match input.data {
+        syn::Data::Struct(data) => {
+            let mut out_tokens = TokenStream::new();
+
+            for (i, field) in data.fields.iter().enumerate() {
+                let Field {
+                    ident, attrs, ..
+                } = &field;
+
+                let member = match ident {
+                    Some(ident) => quote! { #ident },
+                    None => {
+                        let index = syn::Index::from(i);
+                        quote! { #index }
+                    }
+                };
+
+                match get_attribute(attrs, "many") {
+                    Some(AttrData::Many(ty)) => {
+                        out_tokens.extend(quote! {
+                            crate::byte_stream::StreamWrite::write(&(self.#member.len() as #ty), buffer);
+                            for item in self.#member.iter() {
+                                crate::byte_stream::StreamWrite::write(item, buffer);
+                            }
+                        });
+                    },
+                    _ => {
+                        out_tokens.extend(quote! {
+                            crate::byte_stream::StreamWrite::write(&self.#member, buffer);
+                        });
+                    }
+                }
+            }
+
+            out_tokens
+        }
+        _ => TokenStream::new(),
+    };
+
+    let tokens = quote! {
+        impl crate::byte_stream::StreamWrite for #ty_ident {
+            #[allow(unused_variables)]
+            fn write(&self, buffer: &mut Vec<u8>) {
                 #tokens
             }
         }